@@ -5,15 +5,39 @@ use anyhow::Result;
 use clap::Parser;
 use neptune_privacy::config_models::data_directory::DataDirectory;
 use neptune_privacy::config_models::network::Network;
+use neptune_privacy::protocol::consensus::type_scripts::native_currency_amount::NativeCurrencyAmount;
 
 use crate::rpc::client::RestRpcClient;
+use crate::rpc::ConsolidateParams;
+use crate::rpc::DecodeParams;
+use crate::rpc::LedgerParams;
 use crate::rpc::Output;
 use crate::rpc::SendToAddressParams;
+use crate::rpc::TxHistoryParams;
+use crate::wallet::airgap::UnsignedTransaction;
 use crate::wallet::fake_archival_state::generate_snapshot;
+use crate::wallet::WalletState;
 #[derive(Parser)]
 enum WalletCli {
     RUN(RunArgs),
     SEND(SendArgs),
+    /// Select inputs and write an unsigned transaction for an offline wallet
+    PREPARE(PrepareArgs),
+    /// Prove an unsigned transaction with the wallet seed, opens the wallet directly and
+    /// needs no running server
+    SIGN(SignArgs),
+    /// Broadcast a transaction file or a transaction saved in the wallet
+    BROADCAST(BroadcastArgs),
+    /// Write a proven transaction saved in the wallet to a file
+    EXPORT(ExportArgs),
+    /// Print a transaction file or a saved or pending transaction
+    DECODE(DecodeArgs),
+    /// Merge small UTXOs into one UTXO per batch
+    CONSOLIDATE(ConsolidateArgs),
+    /// Export or import labels as BIP-329-like JSONL
+    LABELS(LabelsArgs),
+    /// Write the history as an accounting ledger
+    LEDGER(LedgerArgs),
     HISTORY(HistoryArgs),
     SNAPSHOT(SnapshotArgs),
 }
@@ -22,6 +46,7 @@ enum WalletCli {
 struct GlobalArgs {
     #[clap(long)]
     data_dir: Option<PathBuf>,
+    /// Rest server url, separate multiple servers with commas
     #[clap(long)]
     rpc: Option<String>,
 }
@@ -30,22 +55,150 @@ struct GlobalArgs {
 struct RunArgs {
     #[clap(flatten)]
     global: GlobalArgs,
+    /// Create a watch-only wallet from exported keys or a list of addresses in this file
+    #[clap(long)]
+    watch_only_keys: Option<PathBuf>,
 }
 
 #[derive(clap::Args)]
 struct SendArgs {
     #[clap(flatten)]
     global: GlobalArgs,
-    #[clap(long)]
-    amount: String,
+    #[clap(long, required_unless_present = "sweep")]
+    amount: Option<String>,
     #[clap(long)]
     address: String,
     #[clap(long, default_value = "0.01")]
     fee: String,
-    #[clap(long)]
+    #[clap(long, conflicts_with = "sweep")]
     priority_fee: Option<String>,
+    /// Send all spendable UTXOs, the fee is deducted from the amount
+    #[clap(long, conflicts_with = "amount")]
+    sweep: bool,
+    /// Only sweep these UTXO ids, separated with commas
+    #[clap(long, requires = "sweep", value_delimiter = ',')]
+    utxos: Vec<i64>,
     #[clap(long, default_value = FEE_ADDRESS)]
     fee_address: String,
+    /// Time-lock the payment until this day, YYYY-MM-DD (UTC)
+    #[clap(long)]
+    release_date: Option<chrono::NaiveDate>,
+}
+
+#[derive(clap::Args)]
+struct PrepareArgs {
+    #[clap(flatten)]
+    global: GlobalArgs,
+    #[clap(long)]
+    amount: String,
+    #[clap(long)]
+    address: String,
+    #[clap(long, default_value = "0.01")]
+    fee: String,
+    /// Time-lock the payment until this day, YYYY-MM-DD (UTC)
+    #[clap(long)]
+    release_date: Option<chrono::NaiveDate>,
+    /// Where to write the unsigned transaction
+    #[clap(long, short)]
+    output: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct SignArgs {
+    #[clap(flatten)]
+    global: GlobalArgs,
+    /// Wallet to sign with, defaults to the selected wallet
+    #[clap(long)]
+    wallet_id: Option<i64>,
+    /// Unsigned transaction written by PREPARE
+    #[clap(long, short)]
+    input: PathBuf,
+    /// Where to write the signed transaction
+    #[clap(long, short)]
+    output: PathBuf,
+    /// Do not ask to confirm the outputs and fee
+    #[clap(long, short)]
+    yes: bool,
+}
+
+#[derive(clap::Args)]
+struct BroadcastArgs {
+    #[clap(flatten)]
+    global: GlobalArgs,
+    /// Transaction file written by SIGN or EXPORT
+    #[clap(long, short, conflicts_with = "txid", required_unless_present = "txid")]
+    input: Option<PathBuf>,
+    /// Id of a transaction saved in the wallet
+    #[clap(long)]
+    txid: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct ExportArgs {
+    #[clap(flatten)]
+    global: GlobalArgs,
+    #[clap(long)]
+    txid: String,
+    #[clap(long, short)]
+    output: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct DecodeArgs {
+    #[clap(flatten)]
+    global: GlobalArgs,
+    /// Signed transaction, transfer transaction or transaction details file
+    #[clap(long, short, conflicts_with = "txid", required_unless_present = "txid")]
+    input: Option<PathBuf>,
+    /// Id of a transaction saved or pending in the wallet
+    #[clap(long)]
+    txid: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct ConsolidateArgs {
+    #[clap(flatten)]
+    global: GlobalArgs,
+    /// Consolidate at most this many UTXOs
+    #[clap(long, default_value = "100")]
+    count: usize,
+    /// Only UTXOs worth less than this amount
+    #[clap(long)]
+    below: Option<String>,
+    /// minimum or oldest
+    #[clap(long, default_value = "minimum")]
+    input_rule: String,
+    #[clap(long, default_value = "10")]
+    max_inputs: usize,
+    /// Fee of each batch
+    #[clap(long, default_value = "0.01")]
+    fee: String,
+    /// symmetric or generation
+    #[clap(long, default_value = "symmetric")]
+    key: String,
+    /// Only print the batches and the projected fee
+    #[clap(long)]
+    dry_run: bool,
+}
+
+#[derive(clap::Args)]
+struct LedgerArgs {
+    #[clap(flatten)]
+    global: GlobalArgs,
+    /// csv or json
+    #[clap(long, default_value = "csv")]
+    format: String,
+    /// First day, YYYY-MM-DD (UTC)
+    #[clap(long)]
+    from: Option<chrono::NaiveDate>,
+    /// Last day, YYYY-MM-DD (UTC)
+    #[clap(long)]
+    to: Option<chrono::NaiveDate>,
+    /// date,price CSV to add fiat values
+    #[clap(long)]
+    prices: Option<PathBuf>,
+    #[clap(long, short)]
+    output: PathBuf,
 }
 
 #[derive(clap::Args)]
@@ -62,10 +215,29 @@ struct SnapshotArgs {
 
 const FEE_ADDRESS:&'static str = "nolgam1nurfm22evhpscn5ddwgwa96z0048454c84hwapmvqq6rqqwqx4w34kudq6q5adjvgch8f8v9dsfz3h0vk60npzya04248umqq2xs9n9cznxzl92nh65k6pg60jesff6wu77l8e3c2h8yyjtwwd9kz00m6z7nl5vxk5929q34837shxn4x5t6p9wgheljlfs3kp7lnrl2z0an80y50lwzm704svvpw3ze5k9fkccttuhunjn96cr3jcgt80qggj5x9ltta5z3qmyxhxxmz9ns7kddcrtun0mfd5fz2d05xnkhjzp3pphc83jytrecc437gf7e9czqh9qfhw5000f43ghyc2dfa5vcl38rwzax27kuv0e0gtkj7q2ar3dt0q6y32fdp9nhtm9l4crg7ud7w6vlg28ncns5q4f86teneuu8ezs2zur30gscw5qk9dgmter2nzryph5k2r68k5xf5pf7lkjas9km6eu6jjl2ujfjv5572xqrdrymm3mne6gptpvg54qxfwp3kkm45fvc5knjecsv7w5dfx82u9kcl5mrdd39k8dgc6gddty49f4yy32nfczhxq0k5dx5qmyet273mz6ggthrtvsxtteg3ceg366pnhmgaplejmjgq7qyyc0vz43ecvry8k7p7ddysqutxgpm6w950mzcxcppe5rm6pkjv9tv5uxyx3kz8lpd744udfc8h0575lfkxuwfp4y3uf9nu3fzj8x2r4gt8y3wtwdlf3flldp0m289jc3lh0dv9372dxk7fddx3ns9acfz7cdxsluucxnrn7e8p7lx5h3ngztft68ae5fcnplekay90kvnqjnxr3e80q4xl0nufucchr66p6swa2gkptf85304wwjktllz7f2sswpx3qkpld8mku900jz0g6e2q9y806enem49qud89uqu6z8d98v9sux5anr2v88hr80jqz7t7g4dcj5spgnc0l996lrq0hfswzfwldx7klsxk82zlpfzwpfgkmu3gkdyqnh9salfwrckn95tk0k0kyhrkchhaplehldfj5wf6dnkhapaxhzwfzu8gglp2rf3jtpx7ew3hlq6yqtxtrfxu0ctwsycj9eqccnlpg77mjs292t39kz4n99vjd2yejuxztk4828yk2wk5urejc3fd00gwqmcxl4k2pw85vmxrvv8n9dv6amcgkmuhgfzfcy3wm0p5yhtvdhs4l0447au6x7kwdhmuxjgk7x80gtdmgd74zswdw0jkngwef2zctxnuktxp4e5fqftgw0yplq0d3lcrcqg6q3rw5ljc654adhee53xmmeaazg0avtzkt2q0ngsq8xuxxcax8u2x9zhcxjltcsewhe7ffzqrkznv3z3vuhar4whazsergmymz4jx2d3l8qwrlhcducztkkeygm8luwnrmh2fcrpkg79gj34u88e72ljt94aapkn5uunu457h2kc3czpgekjl2wjyuz9wcpyfk3z22xx7lx7etchn5mfqxpvjf63wcy0sd9qap8mwnmfzs5j4zh9jv8n8jdwvjyk5d3x0j42cdvh5zhq00g429j0vrvm8097vfq2fg2axhrzfuy6qv97swl39dm3q859guyk4pqv9a82kz5wgnvs84l9g3g5wjf9z888spenf97ddaprkxvxluhg268hst8jgfa78t4nrqklgvw6f630nt4yrsddwahmfcfux9gmt0zjyg9vkfrfct8qtg9lehrvgmwq4e7h6ys6r34l2xn82fy2ey5wwq0jn6vk52vugmzlpgc0aywltxqzn7dvz6dlec98en9f482vdmhf33th0k5nrpwq3qj6xg7ve09nna3kp3ff4nhknt4etqhzauc8v2047yl72yefh4zddc6g9s4ye4hvukulhhu37gqrll7qyg0sx6gtgalwgwcc50gd00m90vzca8mxykdqjhfesxre99ahmfcpa2xtqftzlvu8ag55wqm84rqapa06774v876lms39y5mx0r67mus4n45crh4j99f6wptmcmy9q8hqlnl8qgvxetx3ce3kla74uwuleh7jkzdpafgcvl7amv0s8usgg6z2nr3utc4xg5qgzaf5zw3tjnak72e0ptl86k5d2667pkzauq35c7x83tms2ysev6x20h5am89qu6mm77f8f7cemtd4hhxh4qp6ae55krpst59656mqzpzc8uup42mxrarc298n7y86ekgrgft3nkasfa30u9w50dxt6gx3rpyvpgsyv8nz3d0dhzgdtkt7gxd6nj02awyesdmncj0pwzdp59gh2c09rqfm7x8t7le70ej2dd7ncq2z2qwl0cphu8ds5hxzegur3mlrrqx0zdvmje79s86ads9v6srn2skztz7mlr47f2xs43tt2eejx0j66ukqusg2ltjjxe79efggq022u9j8dqd6qcuedrfhhm8rqg6na9rcuq35aqn40q4llseyrdz68x5enuyt7yhk3d3kqxwjfullcrqhtc82vzraw0pdgjxpjtxgjvrqeqfdn7j9ck57w2u5dppfuvkk52cc3mn28nnshn87j84vfd3tdkqu9wl037yn49l829gftaky623476hw4wc7x26al8q7mfsg56pmzlyzdmgqsa33r37k0thurnjasahp3c9z5mwk3zgtgtfvj2qydgz5su6wvewhh7yeqft8z2ze4j99qha32wagywmjuqhtff3v7wpdmrcu84zmlxd5zhf5lngp4t070uup93w7lv95uk6ckhrqq4fx8epcuynh6qwh86a03nvnjf7vxvmkae2l2qzu24pjz8wdtwqs87pfdhzcwj29ruzh9ag54zqe8qzw46azds62ug7qxgf3z00rgu5q28newruew6pcvv7w7uvs9fzchha5awsfk2xfjtyu3ml5y98m2fs7peusgwv9r78uy8w6stzgc9prtsa57l03l7sfhakkt40va06uwva5qc6vy8mztwkdw2z69xpzuf4qaz9rk83wtjqjj5xvxp4xjpeple9dxgxp0tqhqzt2f8t8r03dn0vx9tl6tnh7mn6k2tnatwqkjx0csz5fj7a3g4fs07rv2p2hxag0hc8p29hx4skh0xp6x2y6afwrs5jx8hagl8pm320wwwfeh2zsernkgul5jhpy2ea5tjf934z6qgwsxezex94w935z2txr8gw3fcsrpp4m94nmwmap3pe6xyw5qlz7yyjg9merzckv6lxe5k8rtysn7fgzy3f5ug99hzq29gpllklmja7sdjg2wwgxee6m5nqercjx48cta7qp4q6hyerdts4fc5ly0hemn9rnygwng4hckqc7le3u7jpemgjxjc4rudzdekqllkg88k9p3m0gadjm4s2ha5r42p0cv5ss44n7kfyzw4scpyjw0alt2rmuwckvezejusxsxdqu6c8ad0ja7fqh2e4";
 
+#[derive(clap::Args)]
+struct LabelsArgs {
+    #[clap(flatten)]
+    global: GlobalArgs,
+    /// Write all labels to this file
+    #[clap(long, conflicts_with = "import", required_unless_present = "import")]
+    export: Option<PathBuf>,
+    /// Read labels from this file, existing ones are overwritten
+    #[clap(long)]
+    import: Option<PathBuf>,
+}
+
 #[derive(clap::Args)]
 struct HistoryArgs {
     #[clap(flatten)]
     global: GlobalArgs,
+    /// One line per transaction with net amount and fee instead of one per UTXO
+    #[clap(long)]
+    by_tx: bool,
+    #[clap(long, requires = "by_tx")]
+    page: Option<usize>,
+    #[clap(long, requires = "by_tx")]
+    page_size: Option<usize>,
 }
 
 pub async fn run() {
@@ -78,6 +250,30 @@ pub async fn run() {
         WalletCli::SEND(args) => {
             send(args).await.unwrap();
         }
+        WalletCli::PREPARE(args) => {
+            prepare(args).await.unwrap();
+        }
+        WalletCli::SIGN(args) => {
+            sign(args).await.unwrap();
+        }
+        WalletCli::BROADCAST(args) => {
+            broadcast(args).await.unwrap();
+        }
+        WalletCli::EXPORT(args) => {
+            export(args).await.unwrap();
+        }
+        WalletCli::DECODE(args) => {
+            decode(args).await.unwrap();
+        }
+        WalletCli::CONSOLIDATE(args) => {
+            consolidate(args).await.unwrap();
+        }
+        WalletCli::LABELS(args) => {
+            labels(args).await.unwrap();
+        }
+        WalletCli::LEDGER(args) => {
+            ledger(args).await.unwrap();
+        }
         WalletCli::HISTORY(args) => {
             history(args).await.unwrap();
         }
@@ -96,10 +292,11 @@ async fn run_server(args: RunArgs) -> Result<()> {
     crate::service::manage(config.clone());
 
     if let Some(rpc) = args.global.rpc {
-        crate::rpc_client::node_rpc_client().set_rest_server(rpc);
+        crate::rpc_client::node_rpc_client()
+            .set_rest_servers(rpc.split(',').map(|v| v.trim().to_string()).collect());
     } else {
         crate::rpc_client::node_rpc_client()
-            .set_rest_server(config.get_remote_rest().await.unwrap());
+            .set_rest_servers(config.get_remote_rests().await.unwrap());
     }
 
     if !crate::rpc::commands::has_password().await.unwrap() {
@@ -111,7 +308,23 @@ async fn run_server(args: RunArgs) -> Result<()> {
     crate::rpc::commands::try_password().await.unwrap();
 
     let wallets = crate::rpc::commands::get_wallets().await.unwrap();
-    if wallets.is_empty() {
+    if let (true, Some(path)) = (wallets.is_empty(), args.watch_only_keys) {
+        let keys = tokio::fs::read_to_string(&path).await?;
+
+        let start_height = dialoguer::Input::new()
+            .with_prompt("Enter the start height:")
+            .default(0)
+            .interact_text()?;
+
+        crate::command::commands::add_watch_only_wallet(
+            "watch-only".to_string(),
+            keys,
+            0,
+            start_height,
+        )
+        .await
+        .expect("Failed to add watch-only wallet");
+    } else if wallets.is_empty() {
         // ask user to input mnemonic
         let mnemonic: String = dialoguer::Input::new()
             .with_prompt("Enter your mnemonic(divide by space):")
@@ -154,6 +367,14 @@ async fn run_server(args: RunArgs) -> Result<()> {
     Ok(())
 }
 
+/// Start of `date` in unix milliseconds.
+fn release_millis(date: chrono::NaiveDate) -> u64 {
+    date.and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp_millis() as u64
+}
+
 async fn send(args: SendArgs) -> Result<()> {
     let data_dir = DataDirectory::get(args.global.data_dir, Network::Main)?.root_dir_path();
     let token = read_token(&data_dir).await?;
@@ -161,13 +382,17 @@ async fn send(args: SendArgs) -> Result<()> {
 
     let mut outputs = vec![Output {
         address: args.address,
-        amount: args.amount,
+        amount: args.amount.unwrap_or_default(),
+        contact_id: None,
+        release_date: args.release_date.map(release_millis),
     }];
 
     if let Some(fee) = args.priority_fee {
         outputs.push(Output {
             address: args.fee_address,
             amount: fee,
+            contact_id: None,
+            release_date: None,
         });
     }
 
@@ -175,7 +400,8 @@ async fn send(args: SendArgs) -> Result<()> {
         outputs,
         fee: args.fee,
         input_rule: None,
-        inputs: vec![],
+        inputs: args.utxos,
+        sweep: args.sweep,
     };
     let txid = rest_client.send(&params).await?;
     println!("txid: {}", txid);
@@ -183,12 +409,229 @@ async fn send(args: SendArgs) -> Result<()> {
     Ok(())
 }
 
+async fn prepare(args: PrepareArgs) -> Result<()> {
+    let data_dir = DataDirectory::get(args.global.data_dir, Network::Main)?.root_dir_path();
+    let token = read_token(&data_dir).await?;
+    let rest_client = RestRpcClient::new(token);
+
+    let params = SendToAddressParams {
+        outputs: vec![Output {
+            address: args.address,
+            amount: args.amount,
+            contact_id: None,
+            release_date: args.release_date.map(release_millis),
+        }],
+        fee: args.fee,
+        input_rule: None,
+        inputs: vec![],
+        sweep: false,
+    };
+    let unsigned = rest_client.prepare(&params).await?;
+    tokio::fs::write(&args.output, hex::decode(unsigned.data)?).await?;
+    println!("unsigned transaction written to {}", args.output.display());
+
+    Ok(())
+}
+
+async fn sign(args: SignArgs) -> Result<()> {
+    let data_dir = DataDirectory::get(args.global.data_dir, Network::Main)?.root_dir_path();
+    let config = crate::config::Config::new(&data_dir).await?;
+    if config.decrypt_config("").await.is_err() {
+        let password = dialoguer::Password::new()
+            .with_prompt("Enter the wallet password:")
+            .interact()?;
+        config.decrypt_config(&password).await?;
+    }
+
+    let wallet_id = match args.wallet_id {
+        Some(id) => id,
+        None => config.get_wallet_id().await?,
+    };
+    let wallet = WalletState::new_from_config(&config, wallet_id).await?;
+
+    let unsigned = UnsignedTransaction::from_bytes(&tokio::fs::read(&args.input).await?)?;
+
+    let total_input: NativeCurrencyAmount = unsigned
+        .inputs
+        .iter()
+        .map(|input| input.recovery_data.utxo.get_native_currency_amount())
+        .sum();
+    let total_output: NativeCurrencyAmount =
+        unsigned.outputs.iter().map(|(_, amount, _)| *amount).sum();
+    println!("{} inputs, {} in total", unsigned.inputs.len(), total_input);
+    for (address, amount, release_date) in &unsigned.outputs {
+        let address = address.to_bech32m(unsigned.network)?;
+        match release_date {
+            Some(release_date) => println!(
+                "send {} to {}, locked until {}",
+                amount, address, release_date
+            ),
+            None => println!("send {} to {}", amount, address),
+        }
+    }
+    println!("fee: {}", unsigned.fee);
+    if total_input > total_output + unsigned.fee {
+        println!("change: {}", total_input - total_output - unsigned.fee);
+    }
+
+    if !args.yes
+        && !dialoguer::Confirm::new()
+            .with_prompt("Prove this transaction?")
+            .interact()?
+    {
+        println!("aborted");
+        return Ok(());
+    }
+
+    let signed = wallet.sign_unsigned_transaction(unsigned).await?;
+    tokio::fs::write(&args.output, signed.to_bytes()?).await?;
+    println!(
+        "txid: {}, signed transaction written to {}",
+        signed.txid(),
+        args.output.display()
+    );
+
+    Ok(())
+}
+
+async fn broadcast(args: BroadcastArgs) -> Result<()> {
+    let data_dir = DataDirectory::get(args.global.data_dir, Network::Main)?.root_dir_path();
+    let token = read_token(&data_dir).await?;
+    let rest_client = RestRpcClient::new(token);
+
+    let txid = match (args.input, args.txid) {
+        (Some(input), _) => {
+            let signed = tokio::fs::read(&input).await?;
+            rest_client.broadcast(hex::encode(signed)).await?
+        }
+        (None, Some(txid)) => rest_client.broadcast_saved(&txid).await?,
+        (None, None) => anyhow::bail!("either --input or --txid is required"),
+    };
+    println!("txid: {}", txid);
+
+    Ok(())
+}
+
+async fn export(args: ExportArgs) -> Result<()> {
+    let data_dir = DataDirectory::get(args.global.data_dir, Network::Main)?.root_dir_path();
+    let token = read_token(&data_dir).await?;
+    let rest_client = RestRpcClient::new(token);
+
+    let saved = rest_client.export_saved(&args.txid).await?;
+    tokio::fs::write(&args.output, hex::decode(saved.data)?).await?;
+    println!("transaction written to {}", args.output.display());
+
+    Ok(())
+}
+
+async fn decode(args: DecodeArgs) -> Result<()> {
+    let data_dir = DataDirectory::get(args.global.data_dir, Network::Main)?.root_dir_path();
+    let token = read_token(&data_dir).await?;
+    let rest_client = RestRpcClient::new(token);
+
+    let data = match args.input {
+        Some(input) => Some(hex::encode(tokio::fs::read(&input).await?)),
+        None => None,
+    };
+    let decoded = rest_client
+        .decode(&DecodeParams {
+            data,
+            txid: args.txid,
+        })
+        .await?;
+    println!("{}", serde_json::to_string_pretty(&decoded)?);
+
+    Ok(())
+}
+
+async fn consolidate(args: ConsolidateArgs) -> Result<()> {
+    let data_dir = DataDirectory::get(args.global.data_dir, Network::Main)?.root_dir_path();
+    let token = read_token(&data_dir).await?;
+    let rest_client = RestRpcClient::new(token);
+
+    let plan = rest_client
+        .consolidate(&ConsolidateParams {
+            count: args.count,
+            below: args.below,
+            input_rule: Some(args.input_rule),
+            max_inputs: Some(args.max_inputs),
+            fee: args.fee,
+            key: Some(args.key),
+            dry_run: args.dry_run,
+        })
+        .await?;
+    println!("{}", serde_json::to_string_pretty(&plan)?);
+
+    Ok(())
+}
+
+async fn ledger(args: LedgerArgs) -> Result<()> {
+    let data_dir = DataDirectory::get(args.global.data_dir, Network::Main)?.root_dir_path();
+    let token = read_token(&data_dir).await?;
+    let rest_client = RestRpcClient::new(token);
+
+    let prices = match &args.prices {
+        Some(path) => Some(tokio::fs::read_to_string(path).await?),
+        None => None,
+    };
+    let millis = |date: chrono::NaiveDate, end_of_day: bool| {
+        let time = if end_of_day {
+            date.and_hms_milli_opt(23, 59, 59, 999)
+        } else {
+            date.and_hms_opt(0, 0, 0)
+        };
+        time.unwrap().and_utc().timestamp_millis() as u64
+    };
+
+    let ledger = rest_client
+        .export_ledger(&LedgerParams {
+            format: Some(args.format),
+            from: args.from.map(|date| millis(date, false)),
+            to: args.to.map(|date| millis(date, true)),
+            prices,
+        })
+        .await?;
+    tokio::fs::write(&args.output, ledger).await?;
+    println!("ledger written to {}", args.output.display());
+
+    Ok(())
+}
+
+async fn labels(args: LabelsArgs) -> Result<()> {
+    let data_dir = DataDirectory::get(args.global.data_dir, Network::Main)?.root_dir_path();
+    let token = read_token(&data_dir).await?;
+    let rest_client = RestRpcClient::new(token);
+
+    if let Some(output) = args.export {
+        let jsonl = rest_client.export_labels().await?;
+        tokio::fs::write(&output, jsonl).await?;
+        println!("labels written to {}", output.display());
+    } else if let Some(input) = args.import {
+        let jsonl = tokio::fs::read_to_string(&input).await?;
+        let imported = rest_client.import_labels(jsonl).await?;
+        println!("imported {} labels", imported);
+    }
+
+    Ok(())
+}
+
 async fn history(args: HistoryArgs) -> Result<()> {
     let data_dir = DataDirectory::get(args.global.data_dir, Network::Main)?.root_dir_path();
     let token = read_token(&data_dir).await?;
 
     let rest_client = RestRpcClient::new(token);
 
+    if args.by_tx {
+        let params = TxHistoryParams {
+            page: args.page,
+            page_size: args.page_size,
+            ..Default::default()
+        };
+        let history = rest_client.tx_history(&params).await?;
+        println!("{}", serde_json::to_string_pretty(&history)?);
+        return Ok(());
+    }
+
     let history = rest_client.history().await?;
 
     for h in history {
@@ -199,10 +642,13 @@ async fn history(args: HistoryArgs) -> Result<()> {
 }
 
 async fn snapshot(args: SnapshotArgs) -> Result<()> {
-    crate::rpc_client::node_rpc_client().set_rest_server(
+    crate::rpc_client::node_rpc_client().set_rest_servers(
         args.global
             .rpc
-            .unwrap_or("https://xptwallet.vxb.ai".to_string()),
+            .unwrap_or("https://xptwallet.vxb.ai".to_string())
+            .split(',')
+            .map(|v| v.trim().to_string())
+            .collect(),
     );
 
     generate_snapshot(
@@ -7,12 +7,13 @@ use neptune_privacy::api::export::Network;
 
 use crate::config::wallet::ScanConfig;
 use crate::config::wallet::WalletData;
+use crate::config::wallet::WatchOnlyKeys;
 use crate::config::Config;
 use crate::rpc_client;
 use crate::wallet::block_cache::BlockCacheFile;
 use crate::wallet::block_cache::PersistBlockCache;
 use crate::wallet::fake_archival_state::generate_snapshot;
-use crate::wallet::sync::SyncState;
+use crate::wallet::sync_manager::SyncManager;
 use crate::wallet::wallet_file;
 
 type Result<T> = std::result::Result<T, String>;
@@ -38,10 +39,38 @@ pub async fn set_remote_rest(rest: String) -> Result<()> {
     let config = crate::service::get_state::<Arc<Config>>();
     config.set_remote_rest(&rest).await.into_tauri_result()?;
 
-    rpc_client::node_rpc_client().set_rest_server(rest);
+    rpc_client::node_rpc_client()
+        .set_rest_servers(config.get_remote_rests().await.into_tauri_result()?);
     Ok(())
 }
 
+#[cfg_attr(feature = "gui", tauri::command)]
+#[cfg_attr(not(feature = "gui"), allow(unused))]
+pub async fn set_remote_rests(rests: Vec<String>) -> Result<()> {
+    if rests.is_empty() {
+        return Err("at least one rest server is required".to_string());
+    }
+    let config = crate::service::get_state::<Arc<Config>>();
+    config.set_remote_rests(&rests).await.into_tauri_result()?;
+
+    rpc_client::node_rpc_client().set_rest_servers(rests);
+    Ok(())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+#[cfg_attr(not(feature = "gui"), allow(unused))]
+pub async fn get_remote_rests() -> Result<Vec<String>> {
+    let config = crate::service::get_state::<Arc<Config>>();
+    Ok(config.get_remote_rests().await.into_tauri_result()?)
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+#[cfg_attr(not(feature = "gui"), allow(unused))]
+pub async fn remote_rest_status() -> Result<Vec<rpc_client::RestServerStatus>> {
+    rpc_client::node_rpc_client().check_health().await;
+    Ok(rpc_client::node_rpc_client().rest_servers())
+}
+
 #[cfg_attr(feature = "gui", tauri::command)]
 #[cfg_attr(not(feature = "gui"), allow(unused))]
 pub async fn get_remote_rest() -> Result<String> {
@@ -57,7 +86,7 @@ pub async fn set_network(network: String) -> Result<()> {
     config.set_network(network).await.into_tauri_result()?;
     set_wallet_id(-1).await?;
     crate::rpc_client::node_rpc_client()
-        .set_rest_server(config.get_remote_rest().await.into_tauri_result()?);
+        .set_rest_servers(config.get_remote_rests().await.into_tauri_result()?);
 
     Ok(())
 }
@@ -84,6 +113,24 @@ pub async fn get_disk_cache() -> Result<bool> {
     Ok(config.get_disk_cache().await.into_tauri_result()?)
 }
 
+#[cfg_attr(feature = "gui", tauri::command)]
+#[cfg_attr(not(feature = "gui"), allow(unused))]
+pub async fn set_paranoid_mode(enabled: bool) -> Result<()> {
+    let config = crate::service::get_state::<Arc<Config>>();
+    config
+        .set_paranoid_mode(enabled)
+        .await
+        .into_tauri_result()?;
+    Ok(())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+#[cfg_attr(not(feature = "gui"), allow(unused))]
+pub async fn get_paranoid_mode() -> Result<bool> {
+    let config = crate::service::get_state::<Arc<Config>>();
+    Ok(config.get_paranoid_mode().await.into_tauri_result()?)
+}
+
 #[cfg_attr(feature = "gui", tauri::command)]
 pub async fn add_wallet(
     name: String,
@@ -117,13 +164,71 @@ pub async fn add_wallet(
         .await
         .into_tauri_result()?;
 
+    if let Some(sync_manager) = crate::service::try_get_state::<Arc<SyncManager>>() {
+        sync_manager.add_wallet(id).await.into_tauri_result()?;
+    }
+
+    Ok(id)
+}
+
+/// Add a wallet that can see incoming funds and balances but never spend.
+///
+/// `keys` is the output of [export_watch_only_keys] or a list of symmetric addresses.
+#[cfg_attr(feature = "gui", tauri::command)]
+#[cfg_attr(not(feature = "gui"), allow(unused))]
+pub async fn add_watch_only_wallet(
+    name: String,
+    keys: String,
+    num_keys: u64,
+    start_height: u64,
+) -> Result<i64> {
+    let config = crate::service::get_state::<Arc<Config>>();
+    let network = config.get_network().await.into_tauri_result()?;
+    let keys = WatchOnlyKeys::parse(&keys, network).into_tauri_result()?;
+
+    let wallet_config = ScanConfig {
+        num_keys,
+        start_height,
+    };
+
+    let id = config
+        .add_watch_only_wallet(&name, keys, wallet_config)
+        .await
+        .into_tauri_result()?;
+
+    if let Some(sync_manager) = crate::service::try_get_state::<Arc<SyncManager>>() {
+        sync_manager.add_wallet(id).await.into_tauri_result()?;
+    }
+
     Ok(id)
 }
 
+#[cfg_attr(feature = "gui", tauri::command)]
+#[cfg_attr(not(feature = "gui"), allow(unused))]
+pub async fn export_watch_only_keys(password: String, id: i64, num_keys: u64) -> Result<String> {
+    let config = crate::service::get_state::<Arc<Config>>();
+    let config_password = config.password.lock().await.clone();
+    if config_password.is_none() {
+        return Err("password is not set".to_string());
+    }
+    if password != config_password.unwrap() {
+        return Err("wrong password".to_string());
+    }
+    let keys = config
+        .get_wallet_watch_only_keys(id, num_keys)
+        .await
+        .context("failed to get watch-only keys")
+        .into_tauri_result()?;
+    serde_json::to_string(&keys).map_err(|e| e.to_string())
+}
+
 #[cfg_attr(feature = "gui", tauri::command)]
 #[cfg_attr(not(feature = "gui"), allow(unused))]
 pub async fn remove_wallet(id: i64) -> Result<()> {
     let config = crate::service::get_state::<Arc<Config>>();
+    if let Some(sync_manager) = crate::service::try_get_state::<Arc<SyncManager>>() {
+        sync_manager.remove_wallet(id).await;
+    }
     config.remove_wallet(id).await.into_tauri_result()?;
     wallet_file::delete_wallet(config.as_ref(), id)
         .await
@@ -168,15 +273,26 @@ pub async fn set_wallet_id(id: i64) -> Result<()> {
     let config = crate::service::get_state::<Arc<Config>>();
     if id >= 0 {
         config.set_wallet_id(id).await.into_tauri_result()?;
+
+        // other wallets keep syncing in the background
+        if let Some(sync_manager) = crate::service::try_get_state::<Arc<SyncManager>>() {
+            sync_manager.set_current(id).await.into_tauri_result()?;
+            return Ok(());
+        }
     }
 
-    if let Some(sync_state) = crate::service::try_get_state::<Arc<SyncState>>() {
-        sync_state.cancel_sync().await;
+    // network changed or nothing is syncing yet, (re)start syncing all wallets
+    if let Some(sync_manager) = crate::service::try_get_state::<Arc<SyncManager>>() {
+        sync_manager.cancel_all().await;
     };
 
-    let sync_state = Arc::new(SyncState::new(&config).await.into_tauri_result()?);
-    crate::service::manage_or_replace(sync_state.clone());
-    sync_state.sync().await;
+    let sync_manager = Arc::new(
+        SyncManager::new(config.inner().clone())
+            .await
+            .into_tauri_result()?,
+    );
+    crate::service::manage_or_replace(sync_manager.clone());
+    sync_manager.start().await.into_tauri_result()?;
 
     Ok(())
 }
@@ -184,7 +300,8 @@ pub async fn set_wallet_id(id: i64) -> Result<()> {
 #[cfg_attr(feature = "gui", tauri::command)]
 #[cfg_attr(not(feature = "gui"), allow(unused))]
 pub async fn wallet_address(index: u64) -> Result<String> {
-    let state = crate::service::get_state::<Arc<SyncState>>();
+    let sync_manager = crate::service::get_state::<Arc<SyncManager>>();
+    let state = sync_manager.current().await.into_tauri_result()?;
     Ok(state.wallet.get_address(index).await.into_tauri_result()?)
 }
 
@@ -229,7 +346,8 @@ pub async fn try_password() -> Result<bool> {
 #[cfg_attr(feature = "gui", tauri::command)]
 #[cfg_attr(not(feature = "gui"), allow(unused))]
 pub async fn reset_to_height(height: u64) -> Result<()> {
-    let state = crate::service::get_state::<Arc<SyncState>>();
+    let sync_manager = crate::service::get_state::<Arc<SyncManager>>();
+    let state = sync_manager.current().await.into_tauri_result()?;
     state.reset_to_height(height).await.into_tauri_result()?;
     Ok(())
 }
@@ -269,10 +387,13 @@ pub async fn list_cache() -> Result<Vec<BlockCacheFile>> {
     let network = config.get_network().await.into_tauri_result()?;
     let data_dir = config.get_data_dir().await.into_tauri_result()?;
     let mut files = PersistBlockCache::list_cache_files(&data_dir).into_tauri_result()?;
-    let sync_state = crate::service::get_state::<Arc<SyncState>>().status().await;
+    // blocks above the slowest wallet are still needed
+    let height = crate::service::get_state::<Arc<SyncManager>>()
+        .min_height()
+        .await;
 
     files.retain(|file| {
-        if file.network == network.to_string() && file.range.1 > sync_state.height as i64 {
+        if file.network == network.to_string() && file.range.1 > height as i64 {
             return false;
         }
         return true;
@@ -36,6 +36,19 @@ sqlx_migrator::sqlite_migration!(
     )]
 );
 
+struct AddWalletWatchOnlyMigration;
+
+sqlx_migrator::sqlite_migration!(
+    AddWalletWatchOnlyMigration,
+    "config",
+    "add_wallets_watch_only",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        "ALTER TABLE wallets ADD COLUMN watch_only BOOLEAN NOT NULL DEFAULT 0", //up
+        "ALTER TABLE wallets DROP COLUMN watch_only"                            //down
+    )]
+);
+
 impl super::Config {
     pub async fn migrate_tables(&self) -> anyhow::Result<()> {
         let mut migrator = Migrator::default();
@@ -43,6 +56,7 @@ impl super::Config {
         // Adding migrations add its parents, replaces and not before as well
         migrator.add_migration(Box::new(CreateConfigConfigMigration))?;
         migrator.add_migration(Box::new(CreateConfigWalletMigration))?;
+        migrator.add_migration(Box::new(AddWalletWatchOnlyMigration))?;
 
         let mut conn = self.db.acquire().await?;
         // use apply all to apply all pending migration
@@ -18,6 +18,7 @@ use crate::rpc::tls;
 
 mod config_migrate;
 pub mod consts;
+pub mod view_key;
 pub mod wallet;
 
 pub struct Config {
@@ -128,6 +129,18 @@ impl Config {
         }
     }
 
+    pub async fn set_paranoid_mode(&self, enabled: bool) -> Result<()> {
+        self.set_data("paranoid_mode", &enabled).await
+    }
+
+    /// Cross check synced blocks with a second rest server.
+    pub async fn get_paranoid_mode(&self) -> Result<bool> {
+        match self.get_data::<bool>("paranoid_mode").await? {
+            Some(v) => Ok(v),
+            None => Ok(false),
+        }
+    }
+
     async fn remote_rest_key(&self) -> Result<&str> {
         let network = self.get_network().await?;
         match network {
@@ -136,19 +149,48 @@ impl Config {
             _ => Ok("remote_rest"),
         }
     }
+    /// Make `rest` the preferred server, the other servers are kept as fallbacks.
     pub async fn set_remote_rest(&self, rest: &String) -> Result<()> {
         let key = self.remote_rest_key().await?;
-        self.set_data::<String>(key, &rest).await
+        self.set_data::<String>(key, &rest).await?;
+
+        let mut rests = self.get_remote_rests().await?;
+        rests.retain(|v| v != rest);
+        rests.insert(0, rest.clone());
+        self.set_remote_rests(&rests).await
     }
 
     pub async fn get_remote_rest(&self) -> Result<String> {
-        let key = self.remote_rest_key().await?;
         Ok(self
-            .get_data::<String>(key)
+            .get_remote_rests()
             .await?
+            .into_iter()
+            .next()
             .unwrap_or("https://xptwallet.vxb.ai".to_string()))
     }
 
+    /// Rest servers of the current network, in order of preference.
+    pub async fn set_remote_rests(&self, rests: &[String]) -> Result<()> {
+        let key = format!("{}_list", self.remote_rest_key().await?);
+        self.set_data(&key, &rests).await
+    }
+
+    pub async fn get_remote_rests(&self) -> Result<Vec<String>> {
+        let key = self.remote_rest_key().await?;
+        if let Some(rests) = self
+            .get_data::<Vec<String>>(&format!("{}_list", key))
+            .await?
+        {
+            if !rests.is_empty() {
+                return Ok(rests);
+            }
+        }
+        Ok(vec![self
+            .get_data::<String>(key)
+            .await?
+            .unwrap_or("https://xptwallet.vxb.ai".to_string())])
+    }
+
     pub async fn decrypt_config(&self, password: &str) -> Result<()> {
         let pass_test = self
             .get_data::<Vec<u8>>(PASSWORD_TEST_KEY)
@@ -0,0 +1,290 @@
+use aes_gcm::aead::Aead;
+use aes_gcm::aead::Nonce;
+use aes_gcm::Aes256Gcm;
+use aes_gcm::KeyInit;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use neptune_privacy::api::export::SpendingKey;
+use neptune_privacy::api::export::Utxo;
+use neptune_privacy::prelude::tasm_lib::prelude::Digest;
+use neptune_privacy::prelude::twenty_first::math::b_field_element::BFieldElement;
+use neptune_privacy::prelude::twenty_first::math::lattice;
+use neptune_privacy::protocol::consensus::transaction::transaction_kernel::TransactionKernel;
+use neptune_privacy::state::wallet::address::common;
+use neptune_privacy::state::wallet::address::KeyType;
+use neptune_privacy::state::wallet::address::ReceivingAddress;
+use neptune_privacy::state::wallet::incoming_utxo::IncomingUtxo;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::warn;
+
+/// The part of a wallet key needed to find its UTXOs.
+///
+/// Holds the receiver identifier, the decryption key and the receiver preimage, but neither
+/// the seed nor the unlock key, so it can not produce a lock script witness. The receiver
+/// preimage is needed to compute the removal records of spent UTXOs, the lock scripts of
+/// generation and symmetric keys are keyed on the unlock key and can not be opened with it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ViewKey {
+    Generation {
+        receiver_identifier: BFieldElement,
+        decryption_key: lattice::kem::SecretKey,
+        receiver_preimage: Digest,
+        lock_script_hash: Digest,
+        address: ReceivingAddress,
+    },
+    Symmetric {
+        receiver_identifier: BFieldElement,
+        /// AES key of the UTXO notifications, derived one-way from the seed.
+        decryption_key: [u8; 32],
+        receiver_preimage: Digest,
+        lock_script_hash: Digest,
+    },
+}
+
+impl ViewKey {
+    /// Strip a spending key down to its view material, fails for key types without notifications.
+    pub fn from_spending_key(spending_key: &SpendingKey) -> Result<Self> {
+        let lock_script_hash = spending_key.lock_script_hash();
+        match spending_key {
+            SpendingKey::Generation(key) => Ok(ViewKey::Generation {
+                receiver_identifier: key.receiver_identifier(),
+                decryption_key: key.decryption_key(),
+                receiver_preimage: key.privacy_preimage(),
+                lock_script_hash,
+                address: key.to_address().into(),
+            }),
+            SpendingKey::Symmetric(key) => Ok(ViewKey::Symmetric {
+                receiver_identifier: key.receiver_identifier(),
+                decryption_key: key.secret_key().into(),
+                receiver_preimage: key.privacy_preimage(),
+                lock_script_hash,
+            }),
+            _ => bail!("key type has no view key"),
+        }
+    }
+
+    /// The address to hand out for this key, symmetric addresses carry the seed and are
+    /// not part of a view key.
+    pub fn to_address(&self) -> Option<ReceivingAddress> {
+        match self {
+            ViewKey::Generation { address, .. } => Some(address.clone()),
+            ViewKey::Symmetric { .. } => None,
+        }
+    }
+
+    pub fn receiver_preimage(&self) -> Digest {
+        match self {
+            ViewKey::Generation {
+                receiver_preimage, ..
+            }
+            | ViewKey::Symmetric {
+                receiver_preimage, ..
+            } => *receiver_preimage,
+        }
+    }
+
+    /// Hash of the lock script of UTXOs sent to this key.
+    pub fn lock_script_hash(&self) -> Digest {
+        match self {
+            ViewKey::Generation {
+                lock_script_hash, ..
+            }
+            | ViewKey::Symmetric {
+                lock_script_hash, ..
+            } => *lock_script_hash,
+        }
+    }
+
+    fn key_type(&self) -> KeyType {
+        match self {
+            ViewKey::Generation { .. } => KeyType::Generation,
+            ViewKey::Symmetric { .. } => KeyType::Symmetric,
+        }
+    }
+
+    fn receiver_identifier(&self) -> BFieldElement {
+        match self {
+            ViewKey::Generation {
+                receiver_identifier,
+                ..
+            }
+            | ViewKey::Symmetric {
+                receiver_identifier,
+                ..
+            } => *receiver_identifier,
+        }
+    }
+
+    /// Decrypt the UTXO notifications in `tx_kernel` addressed to this key.
+    pub fn scan_for_announced_utxos(&self, tx_kernel: &TransactionKernel) -> Vec<IncomingUtxo> {
+        let flag = BFieldElement::from(self.key_type());
+        let receiver_identifier = self.receiver_identifier();
+
+        tx_kernel
+            .announcements
+            .iter()
+            .filter_map(|announcement| match announcement.message.as_slice() {
+                [key_type, identifier, ciphertext @ ..]
+                    if *key_type == flag && *identifier == receiver_identifier =>
+                {
+                    Some(ciphertext)
+                }
+                _ => None,
+            })
+            .filter_map(|ciphertext| match self.decrypt(ciphertext) {
+                Ok(payload) => Some(payload),
+                Err(e) => {
+                    warn!("could not decrypt utxo notification: {}", e);
+                    None
+                }
+            })
+            .map(|(utxo, sender_randomness)| {
+                IncomingUtxo::new(utxo, sender_randomness, self.receiver_preimage(), false)
+            })
+            .collect()
+    }
+
+    fn decrypt(&self, ciphertext: &[BFieldElement]) -> Result<(Utxo, Digest)> {
+        match self {
+            ViewKey::Generation { decryption_key, .. } => {
+                let (kem_ciphertext, ciphertext) = ciphertext
+                    .split_at_checked(lattice::kem::CIPHERTEXT_SIZE_IN_BFES)
+                    .context("ciphertext too short")?;
+                let kem_ciphertext: [BFieldElement; lattice::kem::CIPHERTEXT_SIZE_IN_BFES] =
+                    kem_ciphertext.try_into()?;
+                let shared_key = lattice::kem::dec(*decryption_key, kem_ciphertext.into())
+                    .context("could not establish shared key")?;
+                decrypt_payload(&shared_key, ciphertext)
+            }
+            ViewKey::Symmetric { decryption_key, .. } => {
+                decrypt_payload(decryption_key, ciphertext)
+            }
+        }
+    }
+}
+
+/// Key a wallet scans blocks with.
+///
+/// Wallets with entropy scan with the upstream spending key, only watch-only wallets go
+/// through a [ViewKey].
+#[derive(Debug, Clone)]
+pub enum ScanKey {
+    Spending(SpendingKey),
+    View(ViewKey),
+}
+
+impl ScanKey {
+    pub fn scan_for_announced_utxos(&self, tx_kernel: &TransactionKernel) -> Vec<IncomingUtxo> {
+        match self {
+            ScanKey::Spending(key) => key.scan_for_announced_utxos(tx_kernel),
+            ScanKey::View(key) => key.scan_for_announced_utxos(tx_kernel),
+        }
+    }
+
+    /// Hash of the lock script of UTXOs sent to this key.
+    pub fn lock_script_hash(&self) -> Digest {
+        match self {
+            ScanKey::Spending(key) => key.lock_script_hash(),
+            ScanKey::View(key) => key.lock_script_hash(),
+        }
+    }
+}
+
+/// Decrypt a nonce followed by an AES-GCM ciphertext of the bincode encoded
+/// `(utxo, sender_randomness)`.
+fn decrypt_payload(key: &[u8], ciphertext: &[BFieldElement]) -> Result<(Utxo, Digest)> {
+    let [nonce, ciphertext @ ..] = ciphertext else {
+        bail!("ciphertext has no nonce");
+    };
+    let nonce = [nonce.value().to_be_bytes().as_slice(), &[0u8; 4]].concat();
+    let ciphertext = common::bfes_to_bytes(ciphertext)?;
+
+    let cipher = Aes256Gcm::new_from_slice(key)?;
+    #[allow(deprecated)]
+    let plaintext = cipher
+        .decrypt(Nonce::<Aes256Gcm>::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|e| anyhow!("wrong decryption key: {}", e))?;
+
+    Ok(bincode::deserialize(&plaintext)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use neptune_privacy::api::export::NativeCurrencyAmount;
+    use neptune_privacy::api::export::Timestamp;
+    use neptune_privacy::api::export::Tip5;
+    use neptune_privacy::protocol::consensus::transaction::transaction_kernel::TransactionKernelProxy;
+    use neptune_privacy::state::wallet::transaction_output::TxOutput;
+    use neptune_privacy::state::wallet::transaction_output::TxOutputList;
+    use neptune_privacy::state::wallet::wallet_entropy::WalletEntropy;
+
+    use super::*;
+
+    /// A kernel with one on-chain notification per output, encrypted by upstream.
+    fn kernel_paying(addresses: &[ReceivingAddress]) -> TransactionKernel {
+        let outputs: TxOutputList = addresses
+            .iter()
+            .enumerate()
+            .map(|(i, address)| {
+                TxOutput::onchain_native_currency(
+                    NativeCurrencyAmount::coins(i as u32 + 1),
+                    Tip5::hash(&(i as u64)),
+                    address.clone(),
+                    false,
+                )
+            })
+            .collect::<Vec<_>>()
+            .into();
+
+        TransactionKernelProxy {
+            inputs: vec![],
+            outputs: outputs.addition_records(),
+            announcements: outputs.announcements(),
+            fee: NativeCurrencyAmount::coins(0),
+            coinbase: None,
+            timestamp: Timestamp::now(),
+            mutator_set_hash: Digest::default(),
+            merge_bit: false,
+        }
+        .into_kernel()
+    }
+
+    #[test]
+    fn test_view_key_scans_like_spending_key() {
+        let entropy = WalletEntropy::devnet_wallet();
+        let other = WalletEntropy::new_random();
+        let keys: [SpendingKey; 4] = [
+            entropy.nth_generation_spending_key(0).into(),
+            entropy.nth_generation_spending_key(3).into(),
+            entropy.nth_symmetric_key(0).into(),
+            entropy.nth_symmetric_key(3).into(),
+        ];
+        let kernel = kernel_paying(&[
+            keys[1].to_address(),
+            SpendingKey::from(other.nth_generation_spending_key(0)).to_address(),
+            keys[2].to_address(),
+            keys[1].to_address(),
+        ]);
+
+        let records = |utxos: Vec<IncomingUtxo>| {
+            utxos
+                .iter()
+                .map(|utxo| (utxo.addition_record(), Tip5::hash(&utxo.utxo)))
+                .collect::<Vec<_>>()
+        };
+        for (key, expected) in keys.iter().zip([0, 2, 1, 0]) {
+            let upstream = records(key.scan_for_announced_utxos(&kernel));
+            assert_eq!(upstream.len(), expected);
+
+            let view_key = ViewKey::from_spending_key(key).unwrap();
+            assert_eq!(view_key.lock_script_hash(), key.lock_script_hash());
+            assert_eq!(
+                records(view_key.scan_for_announced_utxos(&kernel)),
+                upstream
+            );
+        }
+    }
+}
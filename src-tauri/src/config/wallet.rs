@@ -1,29 +1,39 @@
+use anyhow::bail;
+use anyhow::Context;
 use anyhow::Result;
 use neptune_privacy::api::export::Network;
 use neptune_privacy::api::export::SpendingKey;
+use neptune_privacy::state::wallet::address::hash_lock_key::HashLockKey;
+use neptune_privacy::state::wallet::address::ReceivingAddress;
 use neptune_privacy::state::wallet::secret_key_material::SecretKeyMaterial;
 use neptune_privacy::state::wallet::wallet_entropy::WalletEntropy;
 use serde::Deserialize;
 use serde::Serialize;
 use sqlx::Row;
 
+use super::view_key::ScanKey;
+use super::view_key::ViewKey;
 use super::Config;
 
 impl Config {
-    pub async fn get_current_wallet(&self) -> Result<WalletConfig> {
+    pub async fn get_wallet(&self, id: i64) -> Result<WalletConfig> {
         let mut conn = self.db.acquire().await?;
 
-        let id = self.get_wallet_id().await?;
-
-        let row = sqlx::query("select id,secret_key,scan_config from wallets where id = ?")
-            .bind(&id)
-            .fetch_one(&mut *conn)
-            .await?;
+        let row =
+            sqlx::query("select id,secret_key,scan_config,watch_only from wallets where id = ?")
+                .bind(&id)
+                .fetch_one(&mut *conn)
+                .await?;
 
         let secret = row.get::<Vec<u8>, _>("secret_key");
-        let mnemonic = self.secret_to_mnemonic(secret).await?;
-        let secret = SecretKeyMaterial::from_phrase(&mnemonic)?;
-        let key = WalletEntropy::new(secret);
+        let key = if row.get::<bool, _>("watch_only") {
+            let keys = self.secret_to_watch_only_keys(secret).await?;
+            WalletKey::WatchOnly(keys)
+        } else {
+            let mnemonic = self.secret_to_mnemonic(secret).await?;
+            let secret = SecretKeyMaterial::from_phrase(&mnemonic)?;
+            WalletKey::Entropy(WalletEntropy::new(secret))
+        };
 
         let scan_config = row.get::<String, _>("scan_config");
         let scan_config = serde_json::from_str::<ScanConfig>(&scan_config)?;
@@ -43,15 +53,31 @@ impl Config {
     pub async fn get_wallet_mnemonic(&self, id: i64) -> Result<Vec<String>> {
         let mut conn = self.db.acquire().await?;
 
-        let row = sqlx::query("select secret_key from wallets where id = ?")
+        let row = sqlx::query("select secret_key,watch_only from wallets where id = ?")
             .bind(&id)
             .fetch_one(&mut *conn)
             .await?;
 
+        if row.get::<bool, _>("watch_only") {
+            bail!("watch-only wallet has no mnemonic");
+        }
+
         let secret = row.get::<Vec<u8>, _>("secret_key");
         self.secret_to_mnemonic(secret).await
     }
 
+    /// Export the scanning keys of a wallet, they can be imported as a watch-only wallet.
+    pub async fn get_wallet_watch_only_keys(
+        &self,
+        id: i64,
+        num_keys: u64,
+    ) -> Result<WatchOnlyKeys> {
+        match self.get_wallet(id).await?.key {
+            WalletKey::Entropy(key) => WatchOnlyKeys::from_entropy(&key, num_keys),
+            WalletKey::WatchOnly(keys) => Ok(keys),
+        }
+    }
+
     pub async fn add_wallet(
         &self,
         name: &str,
@@ -82,6 +108,44 @@ impl Config {
         Ok(res.last_insert_rowid())
     }
 
+    pub async fn add_watch_only_wallet(
+        &self,
+        name: &str,
+        keys: WatchOnlyKeys,
+        mut scan_config: ScanConfig,
+    ) -> Result<i64> {
+        let mut conn = self.db.acquire().await?;
+
+        // scan every exported key from the start
+        scan_config.num_keys = scan_config.num_keys.max(keys.num_keys());
+
+        let network = self.get_network().await?;
+
+        // symmetric keys have no address without their seed
+        let address = match keys.receiving_address(0) {
+            Some(address) => address.to_bech32m(network)?,
+            None => String::new(),
+        };
+
+        let scan_config = serde_json::to_string(&scan_config)?;
+
+        let secret = self.watch_only_keys_to_secret(&keys).await?;
+
+        let res = sqlx::query(
+            "INSERT INTO wallets (name, secret_key, scan_config, address, balance, watch_only) VALUES (?,?,?,?,?,?)",
+        )
+        .bind(&name)
+        .bind(&secret)
+        .bind(&scan_config)
+        .bind(&address)
+        .bind(&"".to_string())
+        .bind(true)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(res.last_insert_rowid())
+    }
+
     pub async fn remove_wallet(&self, id: i64) -> Result<()> {
         let mut conn = self.db.acquire().await?;
         sqlx::query("delete from wallets where id = ?")
@@ -94,7 +158,7 @@ impl Config {
     pub async fn get_wallets(&self) -> Result<Vec<WalletData>> {
         let mut conn = self.db.acquire().await?;
 
-        let rows = sqlx::query("select id,name,address,balance from wallets")
+        let rows = sqlx::query("select id,name,address,balance,watch_only from wallets")
             .fetch_all(&mut *conn)
             .await?;
 
@@ -104,16 +168,30 @@ impl Config {
             let name = row.get::<String, _>("name");
             let address = row.get::<String, _>("address");
             let balance = row.get::<String, _>("balance");
+            let watch_only = row.get::<bool, _>("watch_only");
             wallets.push(WalletData {
                 id,
                 name,
                 address,
                 balance,
+                watch_only,
             })
         }
         Ok(wallets)
     }
 
+    pub async fn get_wallet_ids(&self) -> Result<Vec<i64>> {
+        let mut conn = self.db.acquire().await?;
+
+        let ids = sqlx::query("select id from wallets")
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| row.get::<i64, _>("id"))
+            .collect();
+        Ok(ids)
+    }
+
     pub async fn update_wallet_balance(&self, id: i64, balance: String) -> Result<()> {
         let mut conn = self.db.acquire().await?;
         sqlx::query("update wallets set balance = ? where id = ?")
@@ -138,6 +216,18 @@ impl Config {
         let phrase = phrase.split(" ").map(|v| v.to_string()).collect::<Vec<_>>();
         Ok(phrase)
     }
+
+    async fn watch_only_keys_to_secret(&self, keys: &WatchOnlyKeys) -> Result<Vec<u8>> {
+        let json = serde_json::to_vec(keys)?;
+        let encoded = crate::rpc::tls::aes::aes_encode(&self.decrypt_key.lock().await, &json)?;
+        Ok(encoded)
+    }
+
+    async fn secret_to_watch_only_keys(&self, secret: Vec<u8>) -> Result<WatchOnlyKeys> {
+        let decode_key = self.decrypt_key.lock().await.clone();
+        let json = crate::rpc::tls::aes::aes_decode(&decode_key, &secret)?;
+        Ok(serde_json::from_slice(&json)?)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -146,10 +236,12 @@ pub struct WalletData {
     name: String,
     address: String,
     balance: String,
+    watch_only: bool,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ScanConfig {
+    /// Gap limit, keys up to this many indices past the last used key are scanned.
     #[serde(default = "default_num_keys")]
     pub num_keys: u64,
     #[serde(default)]
@@ -158,7 +250,7 @@ pub struct ScanConfig {
 
 pub struct WalletConfig {
     pub id: i64,
-    pub key: WalletEntropy,
+    pub key: WalletKey,
     pub scan_config: ScanConfig,
     pub network: Network,
 }
@@ -172,6 +264,167 @@ impl Default for ScanConfig {
     }
 }
 
+/// Key material of a wallet.
+pub enum WalletKey {
+    Entropy(WalletEntropy),
+    /// Only the keys needed to scan for incoming and spent UTXOs, the wallet can never spend.
+    WatchOnly(WatchOnlyKeys),
+}
+
+impl WalletKey {
+    pub fn is_watch_only(&self) -> bool {
+        matches!(self, WalletKey::WatchOnly(_))
+    }
+
+    /// The wallet entropy, fails for watch-only wallets.
+    pub fn entropy(&self) -> Result<&WalletEntropy> {
+        match self {
+            WalletKey::Entropy(key) => Ok(key),
+            WalletKey::WatchOnly(_) => bail!("watch-only wallet can not spend"),
+        }
+    }
+
+    /// Spending keys exist for wallets with entropy only.
+    pub fn nth_generation_spending_key(&self, index: u64) -> Option<SpendingKey> {
+        match self {
+            WalletKey::Entropy(key) => Some(key.nth_generation_spending_key(index).into()),
+            WalletKey::WatchOnly(_) => None,
+        }
+    }
+
+    pub fn nth_symmetric_key(&self, index: u64) -> Option<SpendingKey> {
+        match self {
+            WalletKey::Entropy(key) => Some(key.nth_symmetric_key(index).into()),
+            WalletKey::WatchOnly(_) => None,
+        }
+    }
+
+    /// The key to scan for index `index`, none past the keys of a watch-only wallet.
+    pub fn nth_generation_scan_key(&self, index: u64) -> Option<ScanKey> {
+        match self {
+            WalletKey::Entropy(_) => self
+                .nth_generation_spending_key(index)
+                .map(ScanKey::Spending),
+            WalletKey::WatchOnly(keys) => keys
+                .generation_keys
+                .get(index as usize)
+                .cloned()
+                .map(ScanKey::View),
+        }
+    }
+
+    pub fn nth_symmetric_scan_key(&self, index: u64) -> Option<ScanKey> {
+        match self {
+            WalletKey::Entropy(_) => self.nth_symmetric_key(index).map(ScanKey::Spending),
+            WalletKey::WatchOnly(keys) => keys
+                .symmetric_keys
+                .get(index as usize)
+                .cloned()
+                .map(ScanKey::View),
+        }
+    }
+
+    /// Number of exported generation and symmetric keys, none if keys are derived.
+    pub fn num_watched_keys(&self) -> Option<(u64, u64)> {
+        match self {
+            WalletKey::Entropy(_) => None,
+            WalletKey::WatchOnly(keys) => Some((
+                keys.generation_keys.len() as u64,
+                keys.symmetric_keys.len() as u64,
+            )),
+        }
+    }
+
+    /// The receiving address handed out at `index`.
+    pub fn nth_receiving_address(&self, index: u64) -> Option<ReceivingAddress> {
+        match self {
+            WalletKey::Entropy(_) => self
+                .nth_generation_spending_key(index)
+                .map(|key| key.to_address()),
+            WalletKey::WatchOnly(keys) => keys.receiving_address(index),
+        }
+    }
+
+    /// The guesser fee key unlocks composer rewards, watch-only wallets do not have it.
+    pub fn guesser_fee_key(&self) -> Option<HashLockKey> {
+        match self {
+            WalletKey::Entropy(key) => Some(key.guesser_fee_key()),
+            WalletKey::WatchOnly(_) => None,
+        }
+    }
+}
+
+/// Exported view material of a wallet.
+///
+/// Only [ViewKey]s are exported, nothing in here can unlock a UTXO. The guesser fee key is
+/// a hash lock whose receiver preimage is the unlock secret, so composer rewards are not
+/// visible to watch-only wallets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatchOnlyKeys {
+    #[serde(default)]
+    pub generation_keys: Vec<ViewKey>,
+    #[serde(default)]
+    pub symmetric_keys: Vec<ViewKey>,
+}
+
+impl WatchOnlyKeys {
+    pub fn from_entropy(key: &WalletEntropy, num_keys: u64) -> Result<Self> {
+        Ok(Self {
+            generation_keys: (0..num_keys)
+                .map(|i| ViewKey::from_spending_key(&key.nth_generation_spending_key(i).into()))
+                .collect::<Result<_>>()?,
+            symmetric_keys: (0..num_keys)
+                .map(|i| ViewKey::from_spending_key(&key.nth_symmetric_key(i).into()))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Parse keys exported by [Config::get_wallet_watch_only_keys], or a list of
+    /// bech32m addresses separated by whitespace.
+    pub fn parse(data: &str, network: Network) -> Result<Self> {
+        let data = data.trim();
+        let keys = if data.starts_with('{') {
+            serde_json::from_str::<Self>(data).context("invalid watch-only keys")?
+        } else {
+            Self::from_addresses(data.split_whitespace(), network)?
+        };
+        if keys.num_keys() == 0 {
+            bail!("no keys to watch");
+        }
+        Ok(keys)
+    }
+
+    /// Watch symmetric addresses, only their view material is kept.
+    pub fn from_addresses<'a>(
+        addresses: impl IntoIterator<Item = &'a str>,
+        network: Network,
+    ) -> Result<Self> {
+        let mut keys = Self::default();
+        for address in addresses {
+            match ReceivingAddress::from_bech32m(address, network)? {
+                ReceivingAddress::Symmetric(key) => keys
+                    .symmetric_keys
+                    .push(ViewKey::from_spending_key(&SpendingKey::Symmetric(key))?),
+                _ => bail!(
+                    "address {} can not be watched without its receiver keys",
+                    address
+                ),
+            }
+        }
+        Ok(keys)
+    }
+
+    pub fn num_keys(&self) -> u64 {
+        self.generation_keys.len().max(self.symmetric_keys.len()) as u64
+    }
+
+    fn receiving_address(&self, index: u64) -> Option<ReceivingAddress> {
+        self.generation_keys
+            .get(index as usize)
+            .and_then(|key| key.to_address())
+    }
+}
+
 fn default_num_keys() -> u64 {
     25
 }
@@ -183,3 +436,19 @@ pub fn mnemonic_to_address(mnemonic: &[String], network: Network) -> Result<Stri
 
     spending_key.to_address().to_bech32m(network)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watch_only_keys() {
+        let err = WatchOnlyKeys::parse("{\"generation_keys\": 1}", Network::Main).unwrap_err();
+        assert!(err.to_string().contains("invalid watch-only keys"));
+
+        let err = WatchOnlyKeys::parse(" {} ", Network::Main).unwrap_err();
+        assert_eq!(err.to_string(), "no keys to watch");
+
+        assert!(WatchOnlyKeys::parse("not-an-address", Network::Main).is_err());
+    }
+}
@@ -67,16 +67,17 @@ pub fn run() {
                 let config = crate::config::Config::new(&data_dir).await.unwrap();
 
                 crate::rpc_client::node_rpc_client()
-                    .set_rest_server(config.get_remote_rest().await.unwrap());
+                    .set_rest_servers(config.get_remote_rests().await.unwrap());
 
                 let level = config.get_log_level().await.unwrap();
                 crate::logger::setup_logger(level).unwrap();
                 config
             });
 
-            let persist_store = tauri::async_runtime::block_on(
-                crate::session_store::persist::PersisStore::new(&data_dir),
-            )
+            let persist_store = tauri::async_runtime::block_on(async {
+                let network = config.get_network().await?;
+                crate::session_store::persist::PersisStore::new(&data_dir, network).await
+            })
             .unwrap();
             crate::service::manage(persist_store);
 
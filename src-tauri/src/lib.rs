@@ -24,38 +24,68 @@ pub use neptune_privacy;
 pub fn add_commands<R: tauri::Runtime>(app: tauri::Builder<R>) -> tauri::Builder<R> {
     app.invoke_handler(tauri::generate_handler![
         command::commands::add_wallet,
+        command::commands::add_watch_only_wallet,
         command::commands::delete_cache,
         command::commands::export_wallet,
+        command::commands::export_watch_only_keys,
         command::commands::generate_snapshot_file,
         command::commands::get_disk_cache,
         command::commands::get_network,
+        command::commands::get_paranoid_mode,
         command::commands::get_remote_rest,
+        command::commands::get_remote_rests,
         command::commands::get_wallet_id,
         command::commands::get_wallets,
         command::commands::has_password,
         command::commands::input_password,
         command::commands::list_cache,
+        command::commands::remote_rest_status,
         command::commands::remove_wallet,
         command::commands::reset_to_height,
         command::commands::set_disk_cache,
         command::commands::set_network,
+        command::commands::set_paranoid_mode,
         command::commands::set_password,
         command::commands::set_remote_rest,
+        command::commands::set_remote_rests,
         command::commands::set_wallet_id,
         command::commands::snapshot_dir,
         command::commands::try_password,
         command::commands::wallet_address,
         rpc::commands::avaliable_utxos,
+        rpc::commands::balance_at,
+        rpc::commands::balance_series,
+        rpc::commands::broadcast_saved_transaction,
+        rpc::commands::broadcast_signed_transaction,
+        rpc::commands::clear_sync_alert,
+        rpc::commands::consolidate_utxos,
         rpc::commands::current_wallet_address,
+        rpc::commands::decode_transaction,
+        rpc::commands::export_labels,
+        rpc::commands::export_ledger,
+        rpc::commands::export_saved_transaction,
         rpc::commands::forget_tx,
+        rpc::commands::freeze_utxos,
         rpc::commands::get_server_url,
         rpc::commands::get_tip_height,
         rpc::commands::history,
+        rpc::commands::import_labels,
+        rpc::commands::label_utxo,
+        rpc::commands::labels,
         rpc::commands::pending_transactions,
+        rpc::commands::prepare_unsigned_transaction,
         rpc::commands::run_rpc_server,
+        rpc::commands::saved_transactions,
+        rpc::commands::scan_blocks,
         rpc::commands::send_to_address,
+        rpc::commands::sent_payments,
+        rpc::commands::set_label,
+        rpc::commands::sign_unsigned_transaction,
         rpc::commands::stop_rpc_server,
         rpc::commands::sync_state,
+        rpc::commands::sync_states,
+        rpc::commands::transaction_history,
+        rpc::commands::unfreeze_utxos,
         rpc::commands::wallet_balance,
         os::is_win11,
         os::os_info,
@@ -65,10 +95,23 @@ pub fn add_commands<R: tauri::Runtime>(app: tauri::Builder<R>) -> tauri::Builder
         logger::get_logs,
         logger::log,
         logger::set_log_level,
-        session_store::command::persist_store_execute,
+        session_store::command::add_contact,
+        session_store::command::add_execution_history,
+        session_store::command::delete_contact,
+        session_store::command::delete_execution_history,
+        session_store::command::execution_history,
+        session_store::command::export_contacts,
+        session_store::command::import_contacts,
+        session_store::command::list_contacts,
+        session_store::command::persist_store_del,
+        session_store::command::persist_store_get,
+        session_store::command::persist_store_list,
+        session_store::command::persist_store_set,
+        session_store::command::search_contacts,
         session_store::command::session_store_del,
         session_store::command::session_store_get,
         session_store::command::session_store_set,
+        session_store::command::update_contact,
         service::app::get_build_info,
         service::app::update_info,
     ])
@@ -1,7 +1,12 @@
 use anyhow::Result;
 
+use super::AirgapData;
+use super::ConsolidateParams;
+use super::DecodeParams;
+use super::LedgerParams;
 use super::SendResponse;
 use super::SendToAddressParams;
+use super::TxHistoryParams;
 use crate::config;
 use crate::wallet::balance::WalletHistory;
 
@@ -35,6 +40,22 @@ impl RestRpcClient {
         Ok(resp)
     }
 
+    pub async fn tx_history(&self, params: &TxHistoryParams) -> Result<serde_json::Value> {
+        let url = format!("{}/rpc/wallet/tx_history", Self::api_url());
+
+        let resp = self
+            .client
+            .get(url)
+            .query(params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(resp)
+    }
+
     pub async fn send(&self, params: &SendToAddressParams) -> Result<String> {
         let url = format!("{}/rpc/send", Self::api_url());
 
@@ -49,4 +70,145 @@ impl RestRpcClient {
 
         Ok(resp.txid)
     }
+
+    pub async fn prepare(&self, params: &SendToAddressParams) -> Result<AirgapData> {
+        let url = format!("{}/rpc/airgap/prepare", Self::api_url());
+
+        let resp = self
+            .client
+            .post(url)
+            .json(&params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<AirgapData>()
+            .await?;
+
+        Ok(resp)
+    }
+
+    pub async fn broadcast(&self, data: String) -> Result<String> {
+        let url = format!("{}/rpc/airgap/broadcast", Self::api_url());
+
+        let resp = self
+            .client
+            .post(url)
+            .json(&AirgapData { txid: None, data })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<SendResponse>()
+            .await?;
+
+        Ok(resp.txid)
+    }
+
+    pub async fn export_saved(&self, txid: &str) -> Result<AirgapData> {
+        let url = format!("{}/rpc/saved_tx/{}", Self::api_url(), txid);
+
+        let resp = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<AirgapData>()
+            .await?;
+
+        Ok(resp)
+    }
+
+    pub async fn broadcast_saved(&self, txid: &str) -> Result<String> {
+        let url = format!("{}/rpc/saved_tx/{}/broadcast", Self::api_url(), txid);
+
+        let resp = self
+            .client
+            .post(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<SendResponse>()
+            .await?;
+
+        Ok(resp.txid)
+    }
+
+    pub async fn decode(&self, params: &DecodeParams) -> Result<serde_json::Value> {
+        let url = format!("{}/rpc/tx/decode", Self::api_url());
+
+        let resp = self
+            .client
+            .post(url)
+            .json(&params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(resp)
+    }
+
+    pub async fn consolidate(&self, params: &ConsolidateParams) -> Result<serde_json::Value> {
+        let url = format!("{}/rpc/wallet/consolidate", Self::api_url());
+
+        let resp = self
+            .client
+            .post(url)
+            .json(&params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(resp)
+    }
+
+    pub async fn export_labels(&self) -> Result<String> {
+        let url = format!("{}/rpc/wallet/labels/export", Self::api_url());
+
+        let resp = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(resp)
+    }
+
+    pub async fn import_labels(&self, jsonl: String) -> Result<usize> {
+        let url = format!("{}/rpc/wallet/labels/import", Self::api_url());
+
+        let resp = self
+            .client
+            .post(url)
+            .body(jsonl)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(resp)
+    }
+
+    pub async fn export_ledger(&self, params: &LedgerParams) -> Result<String> {
+        let url = format!("{}/rpc/wallet/ledger", Self::api_url());
+
+        let resp = self
+            .client
+            .post(url)
+            .json(&params)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(resp)
+    }
 }
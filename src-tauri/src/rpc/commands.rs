@@ -10,10 +10,22 @@ use crate::rpc::block::BlockInfoRpc;
 use crate::rpc::error::RestError;
 use crate::rpc::transaction_status::{TransactionStatus, TransactionStatusRpc};
 use crate::rpc::{
-    SendResponse, SendToAddressParams, Utxo, WalletBalance, WalletRpc, WalletRpcImpl,
+    AirgapData, BalanceAtParams, BalanceSeriesParams, ConsolidateParams, DecodeParams,
+    LedgerParams, SendResponse, SendToAddressParams, TxHistoryParams, Utxo, WalletBalance,
+    WalletRpc, WalletRpcImpl,
 };
+use crate::wallet::balance::BalancePoint;
 use crate::wallet::balance::WalletHistory;
-use crate::wallet::sync::{SyncState, SyncStatus};
+use crate::wallet::consolidate::ConsolidationPlan;
+use crate::wallet::decode::DecodedTransaction;
+use crate::wallet::history::TransactionHistoryPage;
+use crate::wallet::labels::Label;
+use crate::wallet::payments::SentPayment;
+use crate::wallet::rescan::RescanKeys;
+use crate::wallet::rescan::RescanResult;
+use crate::wallet::saved::SavedTransaction;
+use crate::wallet::sync::SyncStatus;
+use crate::wallet::sync_manager::SyncManager;
 
 #[cfg_attr(feature = "gui", tauri::command)]
 #[cfg_attr(not(feature = "gui"), allow(unused))]
@@ -61,15 +73,19 @@ pub async fn start_rpc_server_inner() -> Result<()> {
     }
     drop(rpc_handler);
 
-    if let Some(old) = crate::service::try_get_state::<Arc<SyncState>>() {
-        old.cancel_sync().await;
+    if let Some(old) = crate::service::try_get_state::<Arc<SyncManager>>() {
+        old.cancel_all().await;
     }
 
     let config = crate::service::get_state::<Arc<Config>>();
 
-    let sync_state = Arc::new(SyncState::new(&config).await.into_tauri_result()?);
-    crate::service::manage_or_replace(sync_state.clone());
-    sync_state.sync().await;
+    let sync_manager = Arc::new(
+        SyncManager::new(config.inner().clone())
+            .await
+            .into_tauri_result()?,
+    );
+    crate::service::manage_or_replace(sync_manager.clone());
+    sync_manager.start().await.into_tauri_result()?;
 
     super::start_rpc_server().await.into_tauri_result()?;
 
@@ -78,9 +94,9 @@ pub async fn start_rpc_server_inner() -> Result<()> {
 
 #[cfg_attr(feature = "gui", tauri::command)]
 pub async fn stop_rpc_server() -> Result<()> {
-    if let Some(sync_state) = crate::service::try_get_state::<Arc<SyncState>>() {
+    if let Some(sync_manager) = crate::service::try_get_state::<Arc<SyncManager>>() {
         super::stop_rpc_server().await.into_tauri_result()?;
-        sync_state.cancel_sync().await;
+        sync_manager.cancel_all().await;
     };
 
     Ok(())
@@ -95,52 +111,246 @@ impl<T> TauriCommandResultExt for std::result::Result<T, RestError> {
 }
 
 #[cfg_attr(feature = "gui", tauri::command)]
-pub async fn sync_state() -> SyncStatus {
-    WalletRpcImpl::sync_state().await
+pub async fn sync_state(wallet_id: Option<i64>) -> Result<SyncStatus> {
+    WalletRpcImpl::sync_state(wallet_id)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn sync_states() -> Vec<SyncStatus> {
+    WalletRpcImpl::sync_states().await
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn clear_sync_alert(wallet_id: Option<i64>) -> Result<()> {
+    WalletRpcImpl::clear_sync_alert(wallet_id)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn wallet_balance(wallet_id: Option<i64>) -> Result<WalletBalance> {
+    WalletRpcImpl::wallet_balance(wallet_id)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn current_wallet_address(wallet_id: Option<i64>, index: u64) -> Result<String> {
+    WalletRpcImpl::current_wallet_address(wallet_id, index)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn history(wallet_id: Option<i64>) -> Result<Vec<WalletHistory>> {
+    WalletRpcImpl::history(wallet_id).await.into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn transaction_history(
+    wallet_id: Option<i64>,
+    params: TxHistoryParams,
+) -> Result<TransactionHistoryPage> {
+    WalletRpcImpl::transaction_history(wallet_id, params)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn balance_at(wallet_id: Option<i64>, params: BalanceAtParams) -> Result<String> {
+    WalletRpcImpl::balance_at(wallet_id, params)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn balance_series(
+    wallet_id: Option<i64>,
+    params: BalanceSeriesParams,
+) -> Result<Vec<BalancePoint>> {
+    WalletRpcImpl::balance_series(wallet_id, params)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn sent_payments(wallet_id: Option<i64>) -> Result<Vec<SentPayment>> {
+    WalletRpcImpl::sent_payments(wallet_id)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn export_ledger(wallet_id: Option<i64>, params: LedgerParams) -> Result<String> {
+    WalletRpcImpl::export_ledger(wallet_id, params)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn avaliable_utxos(wallet_id: Option<i64>) -> Result<Vec<Utxo>> {
+    WalletRpcImpl::avaliable_utxos(wallet_id)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn freeze_utxos(wallet_id: Option<i64>, ids: Vec<i64>) -> Result<u64> {
+    WalletRpcImpl::freeze_utxos(wallet_id, ids, true)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn unfreeze_utxos(wallet_id: Option<i64>, ids: Vec<i64>) -> Result<u64> {
+    WalletRpcImpl::freeze_utxos(wallet_id, ids, false)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn label_utxo(wallet_id: Option<i64>, id: i64, label: Option<String>) -> Result<()> {
+    WalletRpcImpl::label_utxo(wallet_id, id, label)
+        .await
+        .into_tauri_result()
 }
 
 #[cfg_attr(feature = "gui", tauri::command)]
-pub async fn wallet_balance() -> Result<WalletBalance> {
-    WalletRpcImpl::wallet_balance().await.into_tauri_result()
+pub async fn labels(wallet_id: Option<i64>) -> Result<Vec<Label>> {
+    WalletRpcImpl::labels(wallet_id).await.into_tauri_result()
 }
 
 #[cfg_attr(feature = "gui", tauri::command)]
-pub async fn current_wallet_address(index: u64) -> Result<String> {
-    WalletRpcImpl::current_wallet_address(index)
+pub async fn set_label(wallet_id: Option<i64>, label: Label) -> Result<()> {
+    WalletRpcImpl::set_label(wallet_id, label)
         .await
         .into_tauri_result()
 }
 
 #[cfg_attr(feature = "gui", tauri::command)]
-pub async fn history() -> Result<Vec<WalletHistory>> {
-    WalletRpcImpl::history().await.into_tauri_result()
+pub async fn export_labels(wallet_id: Option<i64>) -> Result<String> {
+    WalletRpcImpl::export_labels(wallet_id)
+        .await
+        .into_tauri_result()
 }
 
 #[cfg_attr(feature = "gui", tauri::command)]
-pub async fn avaliable_utxos() -> Result<Vec<Utxo>> {
-    WalletRpcImpl::avaliable_utxos().await.into_tauri_result()
+pub async fn import_labels(wallet_id: Option<i64>, jsonl: String) -> Result<usize> {
+    WalletRpcImpl::import_labels(wallet_id, jsonl)
+        .await
+        .into_tauri_result()
 }
 
 #[cfg_attr(feature = "gui", tauri::command)]
-pub async fn send_to_address(params: SendToAddressParams) -> Result<SendResponse> {
-    WalletRpcImpl::send_to_address(params)
+pub async fn scan_blocks(
+    wallet_id: Option<i64>,
+    start: u64,
+    end: u64,
+    keys: Option<RescanKeys>,
+) -> Result<RescanResult> {
+    WalletRpcImpl::scan_blocks(wallet_id, start, end, keys)
         .await
         .into_tauri_result()
 }
 
 #[cfg_attr(feature = "gui", tauri::command)]
-pub async fn pending_transactions() -> Result<Vec<TransactionStatus>> {
-    WalletRpcImpl::pending_transactions()
+pub async fn send_to_address(
+    wallet_id: Option<i64>,
+    params: SendToAddressParams,
+) -> Result<SendResponse> {
+    WalletRpcImpl::send_to_address(wallet_id, params)
         .await
         .into_tauri_result()
 }
 
 #[cfg_attr(feature = "gui", tauri::command)]
-pub async fn forget_tx(txid: String) -> Result<()> {
-    WalletRpcImpl::forget_tx(txid).await.into_tauri_result()
+pub async fn consolidate_utxos(
+    wallet_id: Option<i64>,
+    params: ConsolidateParams,
+) -> Result<ConsolidationPlan> {
+    WalletRpcImpl::consolidate_utxos(wallet_id, params)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn prepare_unsigned_transaction(
+    wallet_id: Option<i64>,
+    params: SendToAddressParams,
+) -> Result<AirgapData> {
+    WalletRpcImpl::prepare_unsigned_transaction(wallet_id, params)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn sign_unsigned_transaction(wallet_id: Option<i64>, data: String) -> Result<AirgapData> {
+    WalletRpcImpl::sign_unsigned_transaction(wallet_id, data)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn broadcast_signed_transaction(
+    wallet_id: Option<i64>,
+    data: String,
+) -> Result<SendResponse> {
+    WalletRpcImpl::broadcast_signed_transaction(wallet_id, data)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn decode_transaction(
+    wallet_id: Option<i64>,
+    params: DecodeParams,
+) -> Result<DecodedTransaction> {
+    WalletRpcImpl::decode_transaction(wallet_id, params)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn saved_transactions(wallet_id: Option<i64>) -> Result<Vec<SavedTransaction>> {
+    WalletRpcImpl::saved_transactions(wallet_id)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn export_saved_transaction(wallet_id: Option<i64>, txid: String) -> Result<AirgapData> {
+    WalletRpcImpl::export_saved_transaction(wallet_id, txid)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn broadcast_saved_transaction(
+    wallet_id: Option<i64>,
+    txid: String,
+) -> Result<SendResponse> {
+    WalletRpcImpl::broadcast_saved_transaction(wallet_id, txid)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn pending_transactions(wallet_id: Option<i64>) -> Result<Vec<TransactionStatus>> {
+    WalletRpcImpl::pending_transactions(wallet_id)
+        .await
+        .into_tauri_result()
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn forget_tx(wallet_id: Option<i64>, txid: String) -> Result<()> {
+    WalletRpcImpl::forget_tx(wallet_id, txid)
+        .await
+        .into_tauri_result()
 }
 
 #[cfg_attr(feature = "gui", tauri::command)]
 pub async fn get_tip_height() -> Result<u64> {
     WalletRpcImpl::get_tip_height().await.into_tauri_result()
-}
\ No newline at end of file
+}
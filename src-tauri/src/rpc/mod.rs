@@ -4,10 +4,12 @@ use std::net::SocketAddrV4;
 use std::sync::Arc;
 
 use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Result;
 use axum::body::Body;
 use axum::extract::ConnectInfo;
 use axum::extract::Path;
+use axum::extract::Query;
 use axum::extract::Request;
 use axum::http;
 use axum::middleware::Next;
@@ -21,9 +23,12 @@ use block::get_tip_height;
 use error::RestError;
 use http::StatusCode;
 use neptune_privacy::api::export::NativeCurrencyAmount;
+use neptune_privacy::api::export::Network;
 use neptune_privacy::api::export::ReceivingAddress;
 use neptune_privacy::api::export::Timestamp;
+use neptune_privacy::protocol::consensus::transaction::Transaction;
 use neptune_privacy::state::wallet::utxo_notification::UtxoNotificationMedium;
+use num_traits::Zero;
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 use serde::Serialize;
@@ -40,9 +45,29 @@ use transaction_status::get_pending_transaction;
 use crate::config::consts::RPC_PORT;
 use crate::config::Config;
 use crate::service::get_state;
+use crate::wallet::airgap::SignedTransaction;
+use crate::wallet::airgap::UnsignedTransaction;
+use crate::wallet::balance::BalanceInterval;
+use crate::wallet::balance::BalancePoint;
 use crate::wallet::balance::WalletHistory;
+use crate::wallet::consolidate::ConsolidationKey;
+use crate::wallet::consolidate::ConsolidationPlan;
+use crate::wallet::consolidate::DEFAULT_MAX_CONSOLIDATION_INPUTS;
+use crate::wallet::decode::DecodedTransaction;
+use crate::wallet::history::TransactionHistoryPage;
+use crate::wallet::history::TxHistoryFilter;
+use crate::wallet::history::TxHistorySort;
+use crate::wallet::labels::Label;
+use crate::wallet::labels::LabelType;
+use crate::wallet::ledger::ledger_to_csv;
+use crate::wallet::ledger::LedgerFormat;
+use crate::wallet::payments::SentPayment;
+use crate::wallet::rescan::RescanKeys;
+use crate::wallet::rescan::RescanResult;
+use crate::wallet::saved::SavedTransaction;
 use crate::wallet::sync::SyncState;
 use crate::wallet::sync::SyncStatus;
+use crate::wallet::sync_manager::SyncManager;
 use crate::wallet::InputSelectionRule;
 // mod middleware;
 mod block;
@@ -98,40 +123,137 @@ pub struct WalletBalance {
 pub struct WalletRpcImpl;
 impl WalletRpc for WalletRpcImpl {}
 
+/// Returns the sync state of `wallet_id`, or of the current wallet if no id is given.
+pub async fn wallet_sync_state(wallet_id: Option<i64>) -> Result<Arc<SyncState>, RestError> {
+    let manager = get_state::<Arc<SyncManager>>();
+    let state = match wallet_id {
+        Some(id) => manager.get(id).await?,
+        None => manager.current().await?,
+    };
+    Ok(state)
+}
+
 //TODO: move to crate::command
 pub trait WalletRpc {
-    async fn sync_state() -> SyncStatus {
-        crate::service::get_state::<Arc<SyncState>>().status().await
+    async fn sync_state(wallet_id: Option<i64>) -> Result<SyncStatus, RestError> {
+        Ok(wallet_sync_state(wallet_id).await?.status().await)
+    }
+
+    async fn sync_states() -> Vec<SyncStatus> {
+        get_state::<Arc<SyncManager>>().statuses().await
+    }
+
+    /// Resume a sync paused by a cross check mismatch.
+    async fn clear_sync_alert(wallet_id: Option<i64>) -> Result<(), RestError> {
+        wallet_sync_state(wallet_id).await?.clear_alert().await?;
+        Ok(())
     }
 
-    async fn wallet_balance() -> Result<WalletBalance, RestError> {
-        let wallet = &get_state::<Arc<SyncState>>().wallet;
+    async fn wallet_balance(wallet_id: Option<i64>) -> Result<WalletBalance, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
         let (available_balance, total_balance) = wallet.get_all_balance().await?;
         Ok(WalletBalance {
             available_balance: available_balance.display_lossless(),
             total_balance: total_balance.display_lossless(),
         })
     }
-    async fn current_wallet_address(index: u64) -> Result<String, RestError> {
-        let wallet = &get_state::<Arc<SyncState>>().wallet;
+    async fn current_wallet_address(
+        wallet_id: Option<i64>,
+        index: u64,
+    ) -> Result<String, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
         let address = wallet.get_address(index).await?;
         Ok(address)
     }
-    async fn history() -> Result<Vec<WalletHistory>, RestError> {
-        let wallet = &get_state::<Arc<SyncState>>().wallet;
+    async fn history(wallet_id: Option<i64>) -> Result<Vec<WalletHistory>, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
         let history = wallet.get_balance_history().await?;
         Ok(history)
     }
-    async fn avaliable_utxos() -> Result<Vec<Utxo>, RestError> {
-        let wallet = &get_state::<Arc<SyncState>>().wallet;
+    /// History grouped by transaction, filtered, sorted and paginated.
+    async fn transaction_history(
+        wallet_id: Option<i64>,
+        params: TxHistoryParams,
+    ) -> Result<TransactionHistoryPage, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        Ok(wallet.get_transaction_history(params.parse()?).await?)
+    }
+    /// Accounting export of the history as CSV or JSON text.
+    async fn export_ledger(
+        wallet_id: Option<i64>,
+        params: LedgerParams,
+    ) -> Result<String, RestError> {
+        let format = match &params.format {
+            Some(format) => LedgerFormat::from_str(format)
+                .ok_or_else(|| RestError(format!("unknown format {}", format)))?,
+            None => LedgerFormat::default(),
+        };
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        let rows = wallet
+            .ledger(
+                params.from.map(Timestamp::millis),
+                params.to.map(Timestamp::millis),
+                params.prices.as_deref(),
+            )
+            .await?;
+
+        Ok(match format {
+            LedgerFormat::Csv => ledger_to_csv(&rows, params.prices.is_some()),
+            LedgerFormat::Json => {
+                serde_json::to_string_pretty(&rows).context("serialize ledger")?
+            }
+        })
+    }
+
+    /// Confirmed balance at a block height or else a time, time-locked UTXOs included.
+    async fn balance_at(
+        wallet_id: Option<i64>,
+        params: BalanceAtParams,
+    ) -> Result<String, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        let balance = match (params.height, params.timestamp) {
+            (Some(height), _) => wallet.balance_at_height(height).await?,
+            (None, Some(timestamp)) => wallet.balance_at_time(Timestamp::millis(timestamp)).await?,
+            (None, None) => return Err(RestError("height or timestamp is required".to_string())),
+        };
+        Ok(balance.display_lossless())
+    }
+    /// Balance per day or week over a range, for charts.
+    async fn balance_series(
+        wallet_id: Option<i64>,
+        params: BalanceSeriesParams,
+    ) -> Result<Vec<BalancePoint>, RestError> {
+        let interval = match &params.interval {
+            Some(interval) => BalanceInterval::from_str(interval)
+                .ok_or_else(|| RestError(format!("unknown interval {}", interval)))?,
+            None => BalanceInterval::default(),
+        };
+        let to = params
+            .to
+            .map(Timestamp::millis)
+            .unwrap_or_else(Timestamp::now);
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        Ok(wallet
+            .balance_series(Timestamp::millis(params.from), to, interval)
+            .await?)
+    }
+
+    /// Outputs paid to other wallets, newest first.
+    async fn sent_payments(wallet_id: Option<i64>) -> Result<Vec<SentPayment>, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        Ok(wallet.sent_payments(None).await?)
+    }
+    async fn avaliable_utxos(wallet_id: Option<i64>) -> Result<Vec<Utxo>, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
         let mut utxos = wallet.get_unspent_utxos().await?;
         utxos.sort_by_key(|v| v.recovery_data.utxo.get_native_currency_amount());
+        let labels = wallet.get_labels(LabelType::Output).await?;
+        let reserved = wallet.get_reserved_utxo_ids().await?;
         let now = Timestamp::now();
         let utxos = utxos
             .into_iter()
             .map(|v| Utxo {
                 id: v.id,
-                hash: v.hash,
                 confirm_timestamp: v.confirmed_in_block.timestamp,
                 confirm_height: v.confirm_height,
                 confirmed_txid: v.confirmed_txid,
@@ -144,50 +266,295 @@ pub trait WalletRpc {
                     Some(v) => v > now,
                     None => false,
                 },
+                frozen: v.frozen,
+                reserved: reserved.contains(&v.id),
+                label: labels.get(&v.hash).cloned(),
+                hash: v.hash,
             })
             .collect::<Vec<_>>();
         Ok(utxos)
     }
-    async fn send_to_address(params: SendToAddressParams) -> Result<SendResponse, RestError> {
-        let mut outputs = Vec::with_capacity(params.outputs.len());
 
-        let wallet = &get_state::<Arc<SyncState>>().wallet;
-        for output in params.outputs {
-            let address = ReceivingAddress::from_bech32m(&output.address, wallet.network)?;
-            let amount = NativeCurrencyAmount::coins_from_str(&output.amount)?;
-            outputs.push((address, amount));
-        }
+    /// Freeze or unfreeze UTXOs, returns how many unspent UTXOs changed.
+    async fn freeze_utxos(
+        wallet_id: Option<i64>,
+        ids: Vec<i64>,
+        frozen: bool,
+    ) -> Result<u64, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        Ok(wallet.set_utxos_frozen(&ids, frozen).await?)
+    }
+
+    async fn label_utxo(
+        wallet_id: Option<i64>,
+        id: i64,
+        label: Option<String>,
+    ) -> Result<(), RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        wallet.set_utxo_label(id, label).await?;
+        Ok(())
+    }
+
+    async fn labels(wallet_id: Option<i64>) -> Result<Vec<Label>, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        Ok(wallet.labels().await?)
+    }
+
+    /// Set the label of a transaction, address or UTXO hash, an empty label removes it.
+    async fn set_label(wallet_id: Option<i64>, label: Label) -> Result<(), RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        wallet
+            .set_label(label.label_type, &label.reference, label.label)
+            .await?;
+        Ok(())
+    }
+
+    /// Labels as BIP-329-like JSONL.
+    async fn export_labels(wallet_id: Option<i64>) -> Result<String, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        Ok(wallet.export_labels().await?)
+    }
+
+    async fn import_labels(wallet_id: Option<i64>, jsonl: String) -> Result<usize, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        Ok(wallet.import_labels(&jsonl).await?)
+    }
+
+    /// Rescan the blocks `start..=end` for the given keys, merging what is found.
+    async fn scan_blocks(
+        wallet_id: Option<i64>,
+        start: u64,
+        end: u64,
+        keys: Option<RescanKeys>,
+    ) -> Result<RescanResult, RestError> {
+        let range_end = end
+            .checked_add(1)
+            .filter(|_| start <= end)
+            .ok_or_else(|| RestError(format!("invalid range {}..={}", start, end)))?;
+        let state = wallet_sync_state(wallet_id).await?;
+        let keys = match keys {
+            Some(keys) => {
+                state.wallet.check_rescan_keys(&keys)?;
+                keys
+            }
+            None => state.wallet.window_rescan_keys(),
+        };
+        let result = state
+            .rescan(
+                std::range::Range {
+                    start,
+                    end: range_end,
+                },
+                keys,
+            )
+            .await?;
+        Ok(result)
+    }
+
+    async fn send_to_address(
+        wallet_id: Option<i64>,
+        mut params: SendToAddressParams,
+    ) -> Result<SendResponse, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        params.resolve_contacts(wallet.network).await?;
+        let (outputs, fee, rule) = params.parse(wallet.network)?;
 
         let utxo_notification_media = (
             UtxoNotificationMedium::OnChain,
             UtxoNotificationMedium::OnChain,
         );
 
+        let tx = if params.sweep {
+            let (address, _, release_date) = outputs.into_iter().next().unwrap();
+            wallet
+                .sweep_to_address(
+                    address,
+                    release_date,
+                    utxo_notification_media,
+                    fee,
+                    params.inputs,
+                )
+                .await
+        } else {
+            wallet
+                .send_to_address(outputs, utxo_notification_media, fee, rule, params.inputs)
+                .await
+        }
+        .map_err(|e| anyhow!("{}", e))?;
+
+        info!("proven tx {}", tx.txid());
+
+        Ok(SendResponse::from_transaction(&tx))
+    }
+
+    async fn consolidate_utxos(
+        wallet_id: Option<i64>,
+        params: ConsolidateParams,
+    ) -> Result<ConsolidationPlan, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+
+        let below = params
+            .below
+            .as_deref()
+            .map(NativeCurrencyAmount::coins_from_str)
+            .transpose()?;
         let fee = NativeCurrencyAmount::coins_from_str(&params.fee)?;
+        let rule = params
+            .input_rule
+            .as_deref()
+            .and_then(InputSelectionRule::from_str)
+            .unwrap_or(InputSelectionRule::Minimum);
+        let key = params
+            .key
+            .as_deref()
+            .and_then(ConsolidationKey::from_str)
+            .unwrap_or_default();
 
-        let rule = if let Some(input_rule) = params.input_rule {
-            InputSelectionRule::from_str(&input_rule).unwrap_or_default()
-        } else {
-            InputSelectionRule::default()
-        };
+        let plan = wallet
+            .consolidate_utxos(
+                params.count,
+                below,
+                rule,
+                params
+                    .max_inputs
+                    .unwrap_or(DEFAULT_MAX_CONSOLIDATION_INPUTS),
+                fee,
+                key,
+                params.dry_run,
+            )
+            .await?;
+        Ok(plan)
+    }
+
+    /// Select inputs for an offline signer, returns the hex encoded [UnsignedTransaction].
+    async fn prepare_unsigned_transaction(
+        wallet_id: Option<i64>,
+        mut params: SendToAddressParams,
+    ) -> Result<AirgapData, RestError> {
+        if params.sweep {
+            return Err(RestError(
+                "sweep is not supported for air-gapped transactions".to_string(),
+            ));
+        }
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        params.resolve_contacts(wallet.network).await?;
+        let (outputs, fee, rule) = params.parse(wallet.network)?;
+
+        let utxo_notification_media = (
+            UtxoNotificationMedium::OnChain,
+            UtxoNotificationMedium::OnChain,
+        );
+
+        let unsigned = wallet
+            .prepare_unsigned_transaction(
+                outputs,
+                utxo_notification_media,
+                fee,
+                rule,
+                params.inputs,
+            )
+            .await?;
+
+        Ok(AirgapData {
+            txid: None,
+            data: hex::encode(unsigned.to_bytes()?),
+        })
+    }
+
+    /// Prove a hex encoded [UnsignedTransaction], returns the hex encoded [SignedTransaction].
+    async fn sign_unsigned_transaction(
+        wallet_id: Option<i64>,
+        data: String,
+    ) -> Result<AirgapData, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        let unsigned =
+            UnsignedTransaction::from_bytes(&hex::decode(data.trim()).context("invalid hex")?)?;
+
+        let signed = wallet.sign_unsigned_transaction(unsigned).await?;
+
+        Ok(AirgapData {
+            txid: Some(signed.txid()),
+            data: hex::encode(signed.to_bytes()?),
+        })
+    }
+
+    async fn broadcast_signed_transaction(
+        wallet_id: Option<i64>,
+        data: String,
+    ) -> Result<SendResponse, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        let signed =
+            SignedTransaction::from_bytes(&hex::decode(data.trim()).context("invalid hex")?)?;
 
         let tx = wallet
-            .send_to_address(outputs, utxo_notification_media, fee, rule, params.inputs)
+            .broadcast_signed_transaction(signed)
             .await
             .map_err(|e| anyhow!("{}", e))?;
 
-        info!("proven tx {}", tx.txid());
+        info!("broadcast signed tx {}", tx.txid());
 
-        Ok(SendResponse {
-            txid: tx.txid().to_string(),
-            outputs: tx
-                .kernel
-                .outputs
-                .iter()
-                .map(|v| v.canonical_commitment.to_hex())
-                .collect::<Vec<_>>(),
+        Ok(SendResponse::from_transaction(&tx))
+    }
+
+    /// Decode a hex encoded transaction blob, or the saved or pending transaction `txid`.
+    async fn decode_transaction(
+        wallet_id: Option<i64>,
+        params: DecodeParams,
+    ) -> Result<DecodedTransaction, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        let bytes = match (params.data, params.txid) {
+            (Some(data), _) => hex::decode(data.trim()).context("invalid hex")?,
+            (None, Some(txid)) => match wallet.get_saved_transaction(&txid).await? {
+                Some((signed, _)) => signed.to_bytes()?,
+                None => wallet
+                    .get_pending_details(&txid)
+                    .await?
+                    .ok_or_else(|| RestError(format!("unknown transaction {}", txid)))?,
+            },
+            (None, None) => return Err(RestError("either data or txid is required".to_string())),
+        };
+
+        Ok(wallet.decode_transaction(&bytes).await?)
+    }
+
+    async fn saved_transactions(
+        wallet_id: Option<i64>,
+    ) -> Result<Vec<SavedTransaction>, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        Ok(wallet.saved_transactions().await?)
+    }
+
+    /// Returns a saved proven transaction hex encoded, in the format the broadcast accepts.
+    async fn export_saved_transaction(
+        wallet_id: Option<i64>,
+        txid: String,
+    ) -> Result<AirgapData, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        let (signed, _) = wallet
+            .get_saved_transaction(&txid)
+            .await?
+            .ok_or_else(|| RestError(format!("no saved transaction {}", txid)))?;
+
+        Ok(AirgapData {
+            txid: Some(txid),
+            data: hex::encode(signed.to_bytes()?),
         })
     }
+
+    async fn broadcast_saved_transaction(
+        wallet_id: Option<i64>,
+        txid: String,
+    ) -> Result<SendResponse, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
+        let tx = wallet
+            .broadcast_saved_transaction(&txid)
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+
+        info!("broadcast saved tx {}", tx.txid());
+
+        Ok(SendResponse::from_transaction(&tx))
+    }
 }
 
 pub async fn start_rpc_server() -> Result<(), anyhow::Error> {
@@ -210,13 +577,37 @@ pub async fn start_rpc_server() -> Result<(), anyhow::Error> {
         let routes = axum::Router::new()
             .route("/rpc/scan/{start}/{end}", get(scan_blocks))
             .route("/rpc/scan/state", get(sync_state))
+            .route("/rpc/scan/wallets", get(sync_states))
+            .route("/rpc/scan/clear_alert", post(clear_sync_alert))
             .route("/rpc/wallet/balance", get(wallet_balance))
             .route("/rpc/wallet/address/{index}", get(wallet_address))
             .route("/rpc/wallet/history", get(history))
+            .route("/rpc/wallet/balance_at", get(balance_at))
+            .route("/rpc/wallet/balance_series", get(balance_series))
             .route("/rpc/wallet/available_utxos", get(avaliable_utxos))
+            .route("/rpc/wallet/tx_history", get(transaction_history))
+            .route("/rpc/wallet/sent_payments", get(sent_payments))
+            .route("/rpc/wallet/ledger", post(export_ledger))
+            .route("/rpc/wallet/utxos/freeze", post(freeze_utxos))
+            .route("/rpc/wallet/utxos/unfreeze", post(unfreeze_utxos))
+            .route("/rpc/wallet/utxos/{id}/label", post(label_utxo))
+            .route("/rpc/wallet/labels", get(labels).post(set_label))
+            .route("/rpc/wallet/labels/export", get(export_labels))
+            .route("/rpc/wallet/labels/import", post(import_labels))
             .route("/rpc/mempool/pendingtx", get(get_pending_transaction))
             .route("/rpc/forget_tx/{id}", get(forget_tx))
             .route("/rpc/send", post(send_to_address))
+            .route("/rpc/wallet/consolidate", post(consolidate_utxos))
+            .route("/rpc/airgap/prepare", post(prepare_unsigned_transaction))
+            .route("/rpc/airgap/sign", post(sign_unsigned_transaction))
+            .route("/rpc/airgap/broadcast", post(broadcast_signed_transaction))
+            .route("/rpc/tx/decode", post(decode_transaction))
+            .route("/rpc/saved_tx", get(saved_transactions))
+            .route("/rpc/saved_tx/{txid}", get(export_saved_transaction))
+            .route(
+                "/rpc/saved_tx/{txid}/broadcast",
+                post(broadcast_saved_transaction),
+            )
             .route("/rpc/block/tip_height", get(get_tip_height));
 
         routes
@@ -261,7 +652,7 @@ async fn log_middleware(
 ) -> Result<Response, StatusCode> {
     let path = request.uri().path().to_string();
     match path.as_str() {
-        "/rpc/scan/state" | "/rpc/block/tip_height" => {}
+        "/rpc/scan/state" | "/rpc/scan/wallets" | "/rpc/block/tip_height" => {}
         _ => {
             info!(
                 "Received '{} {}' from '{addr}'",
@@ -315,26 +706,85 @@ async fn auth_middleware(req: Request<Body>, next: Next) -> Result<Response, Sta
     Ok(next.run(req).await)
 }
 
-async fn scan_blocks(Path((_start, end)): Path<(u64, u64)>) -> Result<ErasedJson, RestError> {
-    Ok(ErasedJson::pretty(end))
+/// Key set of a rescan, e.g. `?generation_start=0&generation_end=100`.
+/// Without any bounds all keys of the current window are used, bounds may reach ten gap
+/// windows past it.
+#[derive(Debug, Default, Deserialize)]
+pub struct ScanQuery {
+    pub wallet_id: Option<i64>,
+    pub generation_start: Option<u64>,
+    pub generation_end: Option<u64>,
+    pub symmetric_start: Option<u64>,
+    pub symmetric_end: Option<u64>,
 }
 
-async fn sync_state() -> Result<ErasedJson, RestError> {
-    Ok(ErasedJson::pretty(WalletRpcImpl::sync_state().await))
+impl ScanQuery {
+    fn keys(&self) -> Option<RescanKeys> {
+        if self.generation_start.is_none()
+            && self.generation_end.is_none()
+            && self.symmetric_start.is_none()
+            && self.symmetric_end.is_none()
+        {
+            return None;
+        }
+        Some(RescanKeys {
+            generation: self.generation_start.unwrap_or(0)..self.generation_end.unwrap_or(0),
+            symmetric: self.symmetric_start.unwrap_or(0)..self.symmetric_end.unwrap_or(0),
+        })
+    }
+}
+
+async fn scan_blocks(
+    Path((start, end)): Path<(u64, u64)>,
+    Query(query): Query<ScanQuery>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::scan_blocks(query.wallet_id, start, end, query.keys()).await?,
+    ))
+}
+
+/// Selects the wallet a request is addressed to, e.g. `?wallet_id=2`.
+/// Requests without it go to the current wallet.
+#[derive(Debug, Default, Deserialize)]
+pub struct WalletQuery {
+    pub wallet_id: Option<i64>,
 }
 
-async fn wallet_balance() -> Result<ErasedJson, RestError> {
-    Ok(ErasedJson::pretty(WalletRpcImpl::wallet_balance().await?))
+async fn sync_state(Query(query): Query<WalletQuery>) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::sync_state(query.wallet_id).await?,
+    ))
+}
+
+async fn sync_states() -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(WalletRpcImpl::sync_states().await))
 }
 
-async fn wallet_address(Path(index): Path<u64>) -> Result<ErasedJson, RestError> {
+async fn clear_sync_alert(Query(query): Query<WalletQuery>) -> Result<ErasedJson, RestError> {
     Ok(ErasedJson::pretty(
-        WalletRpcImpl::current_wallet_address(index).await?,
+        WalletRpcImpl::clear_sync_alert(query.wallet_id).await?,
     ))
 }
 
-async fn history() -> Result<ErasedJson, RestError> {
-    Ok(ErasedJson::pretty(WalletRpcImpl::history().await?))
+async fn wallet_balance(Query(query): Query<WalletQuery>) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::wallet_balance(query.wallet_id).await?,
+    ))
+}
+
+async fn wallet_address(
+    Path(index): Path<u64>,
+    Query(query): Query<WalletQuery>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::current_wallet_address(query.wallet_id, index).await?,
+    ))
+}
+
+async fn history(Query(query): Query<WalletQuery>) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::history(query.wallet_id).await?,
+    ))
 }
 
 #[derive(Serialize, Deserialize)]
@@ -344,12 +794,127 @@ pub struct SendToAddressParams {
     pub input_rule: Option<String>,
     #[serde(default)]
     pub inputs: Vec<i64>,
+    /// Spend all spendable UTXOs, or only `inputs`, to the single output.
+    /// The output amount is ignored, it is the swept total minus the fee.
+    #[serde(default)]
+    pub sweep: bool,
+}
+
+impl SendToAddressParams {
+    /// Replace contact ids by their address, the contact must be on `network`.
+    pub async fn resolve_contacts(&mut self, network: Network) -> Result<()> {
+        for output in self.outputs.iter_mut() {
+            let Some(contact_id) = output.contact_id else {
+                continue;
+            };
+            if !output.address.is_empty() {
+                return Err(anyhow!("output has both an address and a contact"));
+            }
+
+            #[cfg(feature = "gui")]
+            {
+                let store =
+                    crate::service::get_state::<crate::session_store::persist::PersisStore>();
+                let contact = store
+                    .get_contact(contact_id)
+                    .await?
+                    .with_context(|| format!("contact {} not found", contact_id))?;
+                if contact.network()? != network {
+                    return Err(anyhow!(
+                        "contact {} is on {}, wallet is on {}",
+                        contact.alias_name,
+                        contact.network,
+                        network
+                    ));
+                }
+                output.address = contact.address;
+            }
+            #[cfg(not(feature = "gui"))]
+            {
+                let _ = network;
+                return Err(anyhow!(
+                    "contact {} can not be used without the address book",
+                    contact_id
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse outputs, fee and input rule for `network`.
+    pub fn parse(
+        &self,
+        network: Network,
+    ) -> Result<(
+        Vec<(ReceivingAddress, NativeCurrencyAmount, Option<Timestamp>)>,
+        NativeCurrencyAmount,
+        InputSelectionRule,
+    )> {
+        if self.sweep && self.outputs.len() != 1 {
+            return Err(anyhow!("sweep needs exactly one output"));
+        }
+
+        let now = Timestamp::now();
+        let mut outputs = Vec::with_capacity(self.outputs.len());
+        for output in &self.outputs {
+            let address = ReceivingAddress::from_bech32m(&output.address, network)?;
+            let amount = if self.sweep {
+                NativeCurrencyAmount::zero()
+            } else {
+                NativeCurrencyAmount::coins_from_str(&output.amount)?
+            };
+            let release_date = output.release_date.map(Timestamp::millis);
+            if release_date.is_some_and(|date| date <= now) {
+                return Err(anyhow!(
+                    "release date of {} is not in the future",
+                    output.address
+                ));
+            }
+            outputs.push((address, amount, release_date));
+        }
+
+        let fee = NativeCurrencyAmount::coins_from_str(&self.fee)?;
+
+        let rule = if let Some(input_rule) = &self.input_rule {
+            InputSelectionRule::from_str(input_rule).unwrap_or_default()
+        } else {
+            InputSelectionRule::default()
+        };
+
+        Ok((outputs, fee, rule))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ConsolidateParams {
+    /// Consolidate at most this many UTXOs
+    pub count: usize,
+    /// Only UTXOs worth less than this
+    pub below: Option<String>,
+    /// `minimum` (default) or `oldest`
+    pub input_rule: Option<String>,
+    pub max_inputs: Option<usize>,
+    /// Fee of each batch
+    pub fee: String,
+    /// `symmetric` (default) or `generation`
+    pub key: Option<String>,
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Output {
+    /// Empty when the output is given by `contact_id`
+    #[serde(default)]
     pub address: String,
     pub amount: String,
+    /// Address book entry to send to instead of `address`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact_id: Option<i64>,
+    /// Unix milliseconds, the output can not be spent before
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_date: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -358,9 +923,109 @@ pub struct SendResponse {
     outputs: Vec<String>,
 }
 
-async fn send_to_address(Json(params): Json<SendToAddressParams>) -> Result<ErasedJson, RestError> {
+impl SendResponse {
+    pub fn from_transaction(tx: &Transaction) -> Self {
+        SendResponse {
+            txid: tx.txid().to_string(),
+            outputs: tx
+                .kernel
+                .outputs
+                .iter()
+                .map(|v| v.canonical_commitment.to_hex())
+                .collect::<Vec<_>>(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DecodeParams {
+    /// Hex encoded transaction blob
+    pub data: Option<String>,
+    pub txid: Option<String>,
+}
+
+/// A hex encoded air-gapped signing file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AirgapData {
+    pub txid: Option<String>,
+    pub data: String,
+}
+
+async fn send_to_address(
+    Query(query): Query<WalletQuery>,
+    Json(params): Json<SendToAddressParams>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::send_to_address(query.wallet_id, params).await?,
+    ))
+}
+
+async fn consolidate_utxos(
+    Query(query): Query<WalletQuery>,
+    Json(params): Json<ConsolidateParams>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::consolidate_utxos(query.wallet_id, params).await?,
+    ))
+}
+
+async fn prepare_unsigned_transaction(
+    Query(query): Query<WalletQuery>,
+    Json(params): Json<SendToAddressParams>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::prepare_unsigned_transaction(query.wallet_id, params).await?,
+    ))
+}
+
+async fn sign_unsigned_transaction(
+    Query(query): Query<WalletQuery>,
+    Json(params): Json<AirgapData>,
+) -> Result<ErasedJson, RestError> {
     Ok(ErasedJson::pretty(
-        WalletRpcImpl::send_to_address(params).await?,
+        WalletRpcImpl::sign_unsigned_transaction(query.wallet_id, params.data).await?,
+    ))
+}
+
+async fn broadcast_signed_transaction(
+    Query(query): Query<WalletQuery>,
+    Json(params): Json<AirgapData>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::broadcast_signed_transaction(query.wallet_id, params.data).await?,
+    ))
+}
+
+async fn decode_transaction(
+    Query(query): Query<WalletQuery>,
+    Json(params): Json<DecodeParams>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::decode_transaction(query.wallet_id, params).await?,
+    ))
+}
+
+async fn saved_transactions(Query(query): Query<WalletQuery>) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::saved_transactions(query.wallet_id).await?,
+    ))
+}
+
+async fn export_saved_transaction(
+    Path(txid): Path<String>,
+    Query(query): Query<WalletQuery>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::export_saved_transaction(query.wallet_id, txid).await?,
+    ))
+}
+
+async fn broadcast_saved_transaction(
+    Path(txid): Path<String>,
+    Query(query): Query<WalletQuery>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::broadcast_saved_transaction(query.wallet_id, txid).await?,
     ))
 }
 
@@ -374,8 +1039,193 @@ pub struct Utxo {
     pub confirmed_txid: Option<String>,
     pub amount: String,
     pub locked: bool,
+    pub frozen: bool,
+    /// Spent by a pending transaction or a saved one not broadcast yet
+    pub reserved: bool,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TxHistoryParams {
+    /// Zero based
+    pub page: Option<usize>,
+    pub page_size: Option<usize>,
+    /// Unix milliseconds, inclusive
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    /// Bounds on the absolute net amount
+    pub min_amount: Option<String>,
+    pub max_amount: Option<String>,
+    /// `time` (default) or `amount`
+    pub sort: Option<String>,
+    /// `desc` (default) or `asc`
+    pub order: Option<String>,
+}
+
+impl TxHistoryParams {
+    pub fn parse(&self) -> Result<TxHistoryFilter> {
+        let amount = |amount: &Option<String>| {
+            amount
+                .as_deref()
+                .map(NativeCurrencyAmount::coins_from_str)
+                .transpose()
+        };
+        let sort = match &self.sort {
+            Some(sort) => {
+                TxHistorySort::from_str(sort).ok_or_else(|| anyhow!("unknown sort {}", sort))?
+            }
+            None => TxHistorySort::default(),
+        };
+        let ascending = match self.order.as_deref() {
+            None | Some("desc") => false,
+            Some("asc") => true,
+            Some(order) => return Err(anyhow!("unknown order {}", order)),
+        };
+        let (offset, limit) = match self.page_size {
+            Some(page_size) => (self.page.unwrap_or(0) * page_size, Some(page_size)),
+            None => (0, None),
+        };
+
+        Ok(TxHistoryFilter {
+            from: self.from.map(Timestamp::millis),
+            to: self.to.map(Timestamp::millis),
+            min_amount: amount(&self.min_amount)?,
+            max_amount: amount(&self.max_amount)?,
+            sort,
+            ascending,
+            offset,
+            limit,
+        })
+    }
+}
+
+async fn transaction_history(
+    Query(query): Query<WalletQuery>,
+    Query(params): Query<TxHistoryParams>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::transaction_history(query.wallet_id, params).await?,
+    ))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LedgerParams {
+    /// `csv` (default) or `json`
+    pub format: Option<String>,
+    /// Unix milliseconds, inclusive
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    /// `date,price` CSV with `YYYY-MM-DD` dates, adds fiat columns
+    pub prices: Option<String>,
+}
+
+async fn export_ledger(
+    Query(query): Query<WalletQuery>,
+    Json(params): Json<LedgerParams>,
+) -> Result<String, RestError> {
+    WalletRpcImpl::export_ledger(query.wallet_id, params).await
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BalanceAtParams {
+    pub height: Option<u64>,
+    /// Unix milliseconds, used if no height is given
+    pub timestamp: Option<u64>,
+}
+
+async fn balance_at(
+    Query(query): Query<WalletQuery>,
+    Query(params): Query<BalanceAtParams>,
+) -> Result<String, RestError> {
+    WalletRpcImpl::balance_at(query.wallet_id, params).await
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BalanceSeriesParams {
+    /// Unix milliseconds, start of the first bucket
+    pub from: u64,
+    /// Unix milliseconds, now if not given
+    pub to: Option<u64>,
+    /// `day` (default) or `week`
+    pub interval: Option<String>,
+}
+
+async fn balance_series(
+    Query(query): Query<WalletQuery>,
+    Query(params): Query<BalanceSeriesParams>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::balance_series(query.wallet_id, params).await?,
+    ))
+}
+
+async fn sent_payments(Query(query): Query<WalletQuery>) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::sent_payments(query.wallet_id).await?,
+    ))
+}
+
+async fn avaliable_utxos(Query(query): Query<WalletQuery>) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::avaliable_utxos(query.wallet_id).await?,
+    ))
+}
+
+async fn freeze_utxos(
+    Query(query): Query<WalletQuery>,
+    Json(ids): Json<Vec<i64>>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::freeze_utxos(query.wallet_id, ids, true).await?,
+    ))
 }
 
-async fn avaliable_utxos() -> Result<ErasedJson, RestError> {
-    Ok(ErasedJson::pretty(WalletRpcImpl::avaliable_utxos().await?))
+async fn unfreeze_utxos(
+    Query(query): Query<WalletQuery>,
+    Json(ids): Json<Vec<i64>>,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::freeze_utxos(query.wallet_id, ids, false).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LabelParams {
+    pub label: Option<String>,
+}
+
+async fn label_utxo(
+    Path(id): Path<i64>,
+    Query(query): Query<WalletQuery>,
+    Json(params): Json<LabelParams>,
+) -> Result<ErasedJson, RestError> {
+    WalletRpcImpl::label_utxo(query.wallet_id, id, params.label).await?;
+    Ok(ErasedJson::pretty(true))
+}
+
+async fn labels(Query(query): Query<WalletQuery>) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::labels(query.wallet_id).await?,
+    ))
+}
+
+async fn set_label(
+    Query(query): Query<WalletQuery>,
+    Json(label): Json<Label>,
+) -> Result<ErasedJson, RestError> {
+    WalletRpcImpl::set_label(query.wallet_id, label).await?;
+    Ok(ErasedJson::pretty(true))
+}
+
+async fn export_labels(Query(query): Query<WalletQuery>) -> Result<String, RestError> {
+    WalletRpcImpl::export_labels(query.wallet_id).await
+}
+
+async fn import_labels(
+    Query(query): Query<WalletQuery>,
+    jsonl: String,
+) -> Result<ErasedJson, RestError> {
+    Ok(ErasedJson::pretty(
+        WalletRpcImpl::import_labels(query.wallet_id, jsonl).await?,
+    ))
 }
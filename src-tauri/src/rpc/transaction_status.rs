@@ -1,9 +1,7 @@
-use std::sync::Arc;
-
-use crate::{rpc::WalletRpcImpl, service::get_state, wallet::sync::SyncState};
+use crate::rpc::{wallet_sync_state, WalletQuery, WalletRpcImpl};
 
 use super::error::RestError;
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum_extra::response::ErasedJson;
 use serde::Serialize;
 
@@ -19,20 +17,27 @@ pub enum TransactionStatusEnum {
     // Proving,
     // Composing,
 }
-pub async fn get_pending_transaction() -> Result<ErasedJson, RestError> {
+pub async fn get_pending_transaction(
+    Query(query): Query<WalletQuery>,
+) -> Result<ErasedJson, RestError> {
     Ok(ErasedJson::pretty(
-        WalletRpcImpl::pending_transactions().await?,
+        WalletRpcImpl::pending_transactions(query.wallet_id).await?,
     ))
 }
 
-pub async fn forget_tx(Path(id): Path<String>) -> Result<ErasedJson, RestError> {
-    WalletRpcImpl::forget_tx(id).await?;
+pub async fn forget_tx(
+    Path(id): Path<String>,
+    Query(query): Query<WalletQuery>,
+) -> Result<ErasedJson, RestError> {
+    WalletRpcImpl::forget_tx(query.wallet_id, id).await?;
     Ok(ErasedJson::pretty(true))
 }
 
 pub trait TransactionStatusRpc {
-    async fn pending_transactions() -> Result<Vec<TransactionStatus>, RestError> {
-        let wallet = &get_state::<Arc<SyncState>>().wallet;
+    async fn pending_transactions(
+        wallet_id: Option<i64>,
+    ) -> Result<Vec<TransactionStatus>, RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
         let txs = wallet.get_pending_transactions().await?;
         let mut result = vec![];
         for tx in txs {
@@ -44,8 +49,8 @@ pub trait TransactionStatusRpc {
         }
         Ok(result)
     }
-    async fn forget_tx(txid: String) -> Result<(), RestError> {
-        let wallet = &get_state::<Arc<SyncState>>().wallet;
+    async fn forget_tx(wallet_id: Option<i64>, txid: String) -> Result<(), RestError> {
+        let wallet = &wallet_sync_state(wallet_id).await?.wallet;
         wallet.forget_tx(&txid).await?;
         Ok(())
     }
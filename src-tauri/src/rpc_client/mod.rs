@@ -1,10 +1,22 @@
-use std::sync::atomic::AtomicPtr;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::time::Instant;
 
+use anyhow::anyhow;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
+use itertools::Itertools;
+use neptune_privacy::api::export::Timestamp;
 use neptune_privacy::api::export::Transaction;
 use neptune_privacy::application::rest_server::ExportedBlock;
+use neptune_privacy::prelude::tasm_lib::prelude::Digest;
 use neptune_privacy::protocol::consensus::block::block_info::BlockInfo;
 use neptune_privacy::protocol::peer::transfer_transaction::TransferTransaction;
 use neptune_privacy::util_types::mutator_set::archival_mutator_set::ResponseMsMembershipProofPrivacyPreserving;
@@ -14,16 +26,115 @@ use reqwest;
 use serde::Deserialize;
 use serde::Serialize;
 use thiserror::Error;
-use tracing::info;
+use tracing::*;
 
 static NODE_RPC_CLIENT: Lazy<NodeRpcClient> = Lazy::new(|| NodeRpcClient::new(""));
 
+/// Failures in a row after which a server is skipped until [UNHEALTHY_COOLDOWN] passed.
+const MAX_CONSECUTIVE_ERRORS: u64 = 3;
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(60);
+/// Latency penalty per failure in a row when ranking servers.
+const ERROR_PENALTY_MS: u64 = 5000;
+/// Block downloads whose server is remembered for cross checks.
+const MAX_BLOCK_SOURCES: usize = 256;
+
 pub fn node_rpc_client() -> &'static NodeRpcClient {
     return &NODE_RPC_CLIENT;
 }
 
+/// Rest servers in the configured order, requests go to the best scoring one and
+/// fail over to the others.
 pub struct NodeRpcClient {
-    rest_server: AtomicPtr<String>,
+    servers: RwLock<Vec<Arc<RestServer>>>,
+    /// Height ranges of recent block downloads and the server that served them.
+    block_sources: Mutex<VecDeque<(std::ops::Range<u64>, String)>>,
+}
+
+struct RestServer {
+    url: String,
+    /// Moving average of the response time.
+    latency_ms: AtomicU64,
+    consecutive_errors: AtomicU64,
+    total_errors: AtomicU64,
+    total_requests: AtomicU64,
+    last_error_at: AtomicU64,
+    tip_height: AtomicU64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RestServerStatus {
+    pub url: String,
+    pub healthy: bool,
+    pub latency_ms: u64,
+    pub consecutive_errors: u64,
+    pub total_errors: u64,
+    pub total_requests: u64,
+    /// Tip height seen at the last health check.
+    pub tip_height: Option<u64>,
+}
+
+impl RestServer {
+    fn new(url: String) -> Self {
+        Self {
+            url,
+            latency_ms: AtomicU64::new(0),
+            consecutive_errors: AtomicU64::new(0),
+            total_errors: AtomicU64::new(0),
+            total_requests: AtomicU64::new(0),
+            last_error_at: AtomicU64::new(0),
+            tip_height: AtomicU64::new(0),
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.consecutive_errors.load(Ordering::Relaxed) < MAX_CONSECUTIVE_ERRORS
+            || now_millis().saturating_sub(self.last_error_at.load(Ordering::Relaxed))
+                > UNHEALTHY_COOLDOWN.as_millis() as u64
+    }
+
+    fn score(&self) -> u64 {
+        self.latency_ms.load(Ordering::Relaxed)
+            + self.consecutive_errors.load(Ordering::Relaxed) * ERROR_PENALTY_MS
+    }
+
+    fn record_success(&self, elapsed: Duration) {
+        let elapsed = elapsed.as_millis() as u64;
+        let latency = self.latency_ms.load(Ordering::Relaxed);
+        let latency = if latency == 0 {
+            elapsed
+        } else {
+            (latency * 4 + elapsed) / 5
+        };
+        self.latency_ms.store(latency, Ordering::Relaxed);
+        self.consecutive_errors.store(0, Ordering::Relaxed);
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_error(&self) {
+        self.consecutive_errors.fetch_add(1, Ordering::Relaxed);
+        self.total_errors.fetch_add(1, Ordering::Relaxed);
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+        self.last_error_at.store(now_millis(), Ordering::Relaxed);
+    }
+
+    fn status(&self) -> RestServerStatus {
+        RestServerStatus {
+            url: self.url.clone(),
+            healthy: self.is_healthy(),
+            latency_ms: self.latency_ms.load(Ordering::Relaxed),
+            consecutive_errors: self.consecutive_errors.load(Ordering::Relaxed),
+            total_errors: self.total_errors.load(Ordering::Relaxed),
+            total_requests: self.total_requests.load(Ordering::Relaxed),
+            tip_height: match self.tip_height.load(Ordering::Relaxed) {
+                0 => None,
+                height => Some(height),
+            },
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    Timestamp::now().to_millis()
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -39,21 +150,121 @@ struct ResponseSendTx {
 
 impl NodeRpcClient {
     pub fn new(rest_server: &str) -> Self {
-        Self {
-            rest_server: AtomicPtr::new(Box::into_raw(Box::new(rest_server.to_string()))),
-        }
+        let client = Self {
+            servers: RwLock::new(vec![]),
+            block_sources: Mutex::new(VecDeque::new()),
+        };
+        client.set_rest_servers(vec![rest_server.to_string()]);
+        client
     }
 
-    fn rest_server(&self) -> &str {
-        let url = unsafe { &*self.rest_server.load(Ordering::Relaxed) };
-        &url
+    pub fn set_rest_server(&self, rest: String) {
+        self.set_rest_servers(vec![rest]);
     }
 
-    pub fn set_rest_server(&self, rest: String) {
-        self.rest_server.store(
-            Box::into_raw(Box::new(rest)),
-            std::sync::atomic::Ordering::Relaxed,
-        );
+    /// Replace the server list, scores of servers that stay in the list are kept.
+    pub fn set_rest_servers(&self, rests: Vec<String>) {
+        let mut servers = self.servers.write().unwrap();
+        let new = rests
+            .into_iter()
+            .map(|url| url.trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty())
+            .unique()
+            .map(|url| {
+                servers
+                    .iter()
+                    .find(|server| server.url == url)
+                    .cloned()
+                    .unwrap_or_else(|| Arc::new(RestServer::new(url)))
+            })
+            .collect_vec();
+        *servers = new;
+    }
+
+    pub fn rest_servers(&self) -> Vec<RestServerStatus> {
+        self.servers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|server| server.status())
+            .collect()
+    }
+
+    /// Healthy servers by score, then the unhealthy ones as a last resort.
+    fn ranked_servers(&self) -> Vec<Arc<RestServer>> {
+        let servers = self.servers.read().unwrap();
+        servers
+            .iter()
+            .enumerate()
+            .sorted_by_key(|(index, server)| (!server.is_healthy(), server.score(), *index))
+            .map(|(_, server)| server.clone())
+            .collect()
+    }
+
+    /// Run `request` against the best server, failing over to the next one on error.
+    async fn with_failover<T, F, Fut>(&self, name: &str, request: F) -> Result<T>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let (_, v) = self.with_failover_from(name, request).await?;
+        Ok(v)
+    }
+
+    /// Like [Self::with_failover], also returns the server that answered.
+    async fn with_failover_from<T, F, Fut>(&self, name: &str, request: F) -> Result<(String, T)>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let servers = self.ranked_servers();
+        ensure!(!servers.is_empty(), "no rest server configured");
+
+        let mut last_error = None;
+        for server in servers {
+            let started = Instant::now();
+            match request(server.url.clone()).await {
+                Ok(v) => {
+                    server.record_success(started.elapsed());
+                    return Ok((server.url.clone(), v));
+                }
+                Err(e) => {
+                    warn!("{} on {} failed: {:?}", name, server.url, e);
+                    server.record_error();
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error
+            .unwrap()
+            .context(format!("{} failed on all servers", name)))
+    }
+
+    /// Ask every server for its tip, returns the highest tip of a healthy server.
+    pub async fn check_health(&self) -> Option<u64> {
+        let servers = self.servers.read().unwrap().clone();
+        let checks = servers.iter().map(|server| async move {
+            let started = Instant::now();
+            match Self::tip_info_from(&server.url).await {
+                Ok(tip) => {
+                    server.record_success(started.elapsed());
+                    let height: u64 = tip.map(|tip| tip.height.into()).unwrap_or(0);
+                    server.tip_height.store(height, Ordering::Relaxed);
+                    Some(height)
+                }
+                Err(e) => {
+                    debug!("health check of {} failed: {:?}", server.url, e);
+                    server.record_error();
+                    None
+                }
+            }
+        });
+        futures::future::join_all(checks)
+            .await
+            .into_iter()
+            .flatten()
+            .max()
     }
 
     fn get_client() -> reqwest::Client {
@@ -61,11 +272,42 @@ impl NodeRpcClient {
     }
 
     pub async fn request_block(&self, height: u64) -> Result<Option<ExportedBlock>> {
+        let (server, block) = self
+            .with_failover_from("request_block", |server| async move {
+                Self::block_from(&server, height).await
+            })
+            .await?;
+        if block.is_some() {
+            self.record_block_source(height..height + 1, server);
+        }
+        Ok(block)
+    }
+
+    fn record_block_source(&self, heights: std::ops::Range<u64>, server: String) {
+        let mut sources = self.block_sources.lock().unwrap();
+        sources.retain(|(range, _)| range.start >= heights.end || range.end <= heights.start);
+        sources.push_back((heights, server));
+        while sources.len() > MAX_BLOCK_SOURCES {
+            sources.pop_front();
+        }
+    }
+
+    /// Server that served the block at `height`, if it was downloaded recently.
+    fn block_source(&self, height: u64) -> Option<String> {
+        self.block_sources
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&height))
+            .map(|(_, server)| server.clone())
+    }
+
+    async fn block_from(server: &str, height: u64) -> Result<Option<ExportedBlock>> {
         let block = Self::get_client()
             .get(format!(
                 "{}/rpc/block/{}?include_proof=false",
-                self.rest_server(),
-                height
+                server, height
             ))
             .timeout(std::time::Duration::from_secs(30))
             .send()
@@ -73,13 +315,57 @@ impl NodeRpcClient {
             .error_for_status()?
             .json::<Option<ExportedBlock>>()
             .await?;
-
         Ok(block)
     }
 
+    /// Ask a server other than the one that served the block at `height` for its digest.
+    ///
+    /// Blocks read from a snapshot or cached by an earlier run have no known source, any
+    /// server can check them. Returns `None` if no second server is configured or none of
+    /// them has the block.
+    pub async fn second_opinion_digest(&self, height: u64) -> Result<Option<(String, Digest)>> {
+        let servers = self.ranked_servers();
+        if servers.len() < 2 {
+            return Ok(None);
+        }
+        let source = self.block_source(height);
+        let mut last_error = None;
+        for server in servers
+            .iter()
+            .filter(|server| Some(&server.url) != source.as_ref())
+            .filter(|server| server.is_healthy())
+        {
+            let started = Instant::now();
+            match Self::block_from(&server.url, height).await {
+                Ok(block) => {
+                    server.record_success(started.elapsed());
+                    if let Some(block) = block {
+                        return Ok(Some((server.url.clone(), block.hash())));
+                    }
+                }
+                Err(e) => {
+                    warn!("cross check on {} failed: {:?}", server.url, e);
+                    server.record_error();
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) => Err(e.context("cross check failed on all second servers")),
+            None => Ok(None),
+        }
+    }
+
     pub async fn get_tip_info(&self) -> Result<Option<BlockInfo>> {
+        self.with_failover("get_tip_info", |server| async move {
+            Self::tip_info_from(&server).await
+        })
+        .await
+    }
+
+    async fn tip_info_from(server: &str) -> Result<Option<BlockInfo>> {
         let block = Self::get_client()
-            .get(format!("{}/rpc/block_info/tip", self.rest_server()))
+            .get(format!("{}/rpc/block_info/tip", server))
             .timeout(std::time::Duration::from_secs(15))
             .send()
             .await?
@@ -90,35 +376,36 @@ impl NodeRpcClient {
     }
 
     pub async fn get_block_info(&self, digest: &str) -> Result<Option<BlockInfo>> {
-        let block = Self::get_client()
-            .get(format!(
-                "{}/rpc/block_info/{}",
-                self.rest_server(),
-                digest
-            ))
-            .timeout(std::time::Duration::from_secs(15))
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<Option<BlockInfo>>()
-            .await?;
-        Ok(block)
+        self.with_failover("get_block_info", |server| async move {
+            let block = Self::get_client()
+                .get(format!("{}/rpc/block_info/{}", server, digest))
+                .timeout(std::time::Duration::from_secs(15))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Option<BlockInfo>>()
+                .await?;
+            Ok(block)
+        })
+        .await
     }
 
     pub async fn request_block_by_digest(&self, digest: &str) -> Result<Option<ExportedBlock>> {
-        let block = Self::get_client()
-            .get(format!(
-                "{}/rpc/block/{}?include_proof=false",
-                self.rest_server(),
-                digest
-            ))
-            .timeout(std::time::Duration::from_secs(30))
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<Option<ExportedBlock>>()
-            .await?;
-        Ok(block)
+        self.with_failover("request_block_by_digest", |server| async move {
+            let block = Self::get_client()
+                .get(format!(
+                    "{}/rpc/block/{}?include_proof=false",
+                    server, digest
+                ))
+                .timeout(std::time::Duration::from_secs(30))
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Option<ExportedBlock>>()
+                .await?;
+            Ok(block)
+        })
+        .await
     }
 
     pub async fn request_block_by_height_range(
@@ -126,25 +413,33 @@ impl NodeRpcClient {
         height: u64,
         batch_size: u64,
     ) -> Result<Vec<ExportedBlock>> {
-        let body = Self::get_client()
-            .get(format!(
-                "{}/rpc/batch_block/{}/{}?include_proof=false",
-                self.rest_server(),
-                height,
-                batch_size
-            ))
-            .timeout(std::time::Duration::from_secs(120))
-            .send()
-            .await?
-            .error_for_status()?
-            .bytes()
-            .await?;
+        let (server, blocks) = self
+            .with_failover_from("request_block_by_height_range", |server| async move {
+                let body = Self::get_client()
+                    .get(format!(
+                        "{}/rpc/batch_block/{}/{}?include_proof=false",
+                        server, height, batch_size
+                    ))
+                    .timeout(std::time::Duration::from_secs(120))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?;
+
+                let blocks: Vec<ExportedBlock> = bincode::deserialize(&body)?;
 
-        let blocks: Vec<ExportedBlock> = bincode::deserialize(&body)?;
+                Ok(blocks)
+            })
+            .await?;
+        if !blocks.is_empty() {
+            self.record_block_source(height..height + blocks.len() as u64, server);
+        }
 
         Ok(blocks)
     }
 
+    /// Broadcast to every healthy server, succeeds if any of them accepted the transaction.
     pub async fn broadcast_transaction(&self, tx: &Transaction) -> Result<String, BroadcastError> {
         // Converting transaction to a `TransferTransaction` gives a type
         // guarantee that no secrets are being leaked, i.e. that a primitive
@@ -156,8 +451,57 @@ impl NodeRpcClient {
 
         info!("proven tx size: {}", tx_b.len());
 
+        let ranked = self.ranked_servers();
+        let mut servers = ranked
+            .iter()
+            .filter(|server| server.is_healthy())
+            .cloned()
+            .collect_vec();
+        if servers.is_empty() {
+            servers = ranked;
+        }
+        if servers.is_empty() {
+            return Err(BroadcastError::Internal(anyhow!(
+                "no rest server configured"
+            )));
+        }
+
+        let results = futures::future::join_all(servers.iter().map(|server| {
+            let tx_b = tx_b.clone();
+            async move {
+                let started = Instant::now();
+                let result = Self::broadcast_to(&server.url, tx_b).await;
+                match &result {
+                    Ok(()) | Err(BroadcastError::Busy) => server.record_success(started.elapsed()),
+                    Err(e) => {
+                        warn!("broadcast to {} failed: {}", server.url, e);
+                        server.record_error();
+                    }
+                }
+                result
+            }
+        }))
+        .await;
+
+        let mut error = None;
+        for result in results {
+            match result {
+                Ok(()) => return Ok(tx.txid().to_string()),
+                Err(BroadcastError::Busy) => error = Some(BroadcastError::Busy),
+                Err(e) => {
+                    // a rejection is final only if no server failed for another reason
+                    if error.as_ref().is_none_or(BroadcastError::is_rejected) {
+                        error = Some(e);
+                    }
+                }
+            }
+        }
+        Err(error.unwrap())
+    }
+
+    async fn broadcast_to(server: &str, tx_b: Vec<u8>) -> Result<(), BroadcastError> {
         let resp = Self::get_client()
-            .post(format!("{}/rpc/broadcast_tx", self.rest_server()))
+            .post(format!("{}/rpc/broadcast_tx", server))
             .body(tx_b)
             .send()
             .await?
@@ -169,9 +513,9 @@ impl NodeRpcClient {
             if resp.message == "proof machine is busy" {
                 return Err(BroadcastError::Busy);
             };
-            return Err(BroadcastError::Server(anyhow::anyhow!(resp.message)));
+            return Err(BroadcastError::Rejected(resp.message));
         }
-        Ok(tx.txid().to_string())
+        Ok(())
     }
 
     pub async fn restore_msmps(
@@ -180,19 +524,22 @@ impl NodeRpcClient {
     ) -> Result<ResponseMsMembershipProofPrivacyPreserving> {
         let body = bincode::serialize(&request)?;
 
-        let msmp_recovery = Self::get_client()
-            .post(format!(
-                "{}/rpc/generate_membership_proof",
-                self.rest_server()
-            ))
-            .body(body)
-            .send()
-            .await?
-            .error_for_status()?
-            .bytes()
-            .await?;
+        self.with_failover("restore_msmps", |server| {
+            let body = body.clone();
+            async move {
+                let msmp_recovery = Self::get_client()
+                    .post(format!("{}/rpc/generate_membership_proof", server))
+                    .body(body)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?;
 
-        Ok(bincode::deserialize(&msmp_recovery)?)
+                Ok(bincode::deserialize(&msmp_recovery)?)
+            }
+        })
+        .await
     }
 }
 
@@ -206,10 +553,19 @@ pub enum BroadcastError {
     Connection(reqwest::Error),
     #[error("Server error: {0}")]
     Server(anyhow::Error),
+    #[error("Rejected by server: {0}")]
+    Rejected(String),
     #[error("Internal error: {0}")]
     Internal(anyhow::Error),
 }
 
+impl BroadcastError {
+    /// The server refused the transaction itself, broadcasting it again will not help.
+    pub fn is_rejected(&self) -> bool {
+        matches!(self, BroadcastError::Rejected(_))
+    }
+}
+
 impl From<reqwest::Error> for BroadcastError {
     fn from(e: reqwest::Error) -> Self {
         if e.is_timeout() {
@@ -1,4 +1,16 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::contacts::{Contact, ContactsFormat};
+use super::history::ExecutionHistory;
 use super::{persist::PersisStore, Memstore};
+use crate::config::Config;
+
+async fn config_network() -> anyhow::Result<neptune_privacy::api::export::Network> {
+    crate::service::get_state::<Arc<Config>>()
+        .get_network()
+        .await
+}
 
 #[cfg_attr(feature = "gui", tauri::command)]
 pub async fn session_store_get(key: String) -> Option<String> {
@@ -21,7 +33,125 @@ pub async fn session_store_del(key: String) -> Option<String> {
 }
 
 #[cfg_attr(feature = "gui", tauri::command)]
-pub async fn persist_store_execute(sql: String) -> Result<Vec<serde_json::Value>, String> {
+pub async fn persist_store_get(namespace: String, key: String) -> Result<Option<String>, String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store
+        .kv_get(&namespace, &key)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn persist_store_set(
+    namespace: String,
+    key: String,
+    value: String,
+) -> Result<(), String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store
+        .kv_set(&namespace, &key, &value)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn persist_store_del(namespace: String, key: String) -> Result<(), String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store
+        .kv_delete(&namespace, &key)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn persist_store_list(namespace: String) -> Result<BTreeMap<String, String>, String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store.kv_list(&namespace).await.map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn execution_history(address_id: i64) -> Result<Vec<ExecutionHistory>, String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store
+        .execution_history(address_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn add_execution_history(history: ExecutionHistory) -> Result<(), String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store
+        .add_execution_history(&history)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn delete_execution_history(txid: String) -> Result<(), String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store
+        .delete_execution_history(&txid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn list_contacts() -> Result<Vec<Contact>, String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store.list_contacts().await.map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn search_contacts(query: String) -> Result<Vec<Contact>, String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store
+        .search_contacts(&query)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn add_contact(contact: Contact) -> Result<i64, String> {
+    let store = crate::service::get_state::<PersisStore>();
+    let network = config_network().await.map_err(|e| e.to_string())?;
+    store
+        .add_contact(contact, network)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn update_contact(contact: Contact) -> Result<(), String> {
+    let store = crate::service::get_state::<PersisStore>();
+    let network = config_network().await.map_err(|e| e.to_string())?;
+    store
+        .update_contact(contact, network)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn delete_contact(id: i64) -> Result<(), String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store.delete_contact(id).await.map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn export_contacts(format: ContactsFormat) -> Result<String, String> {
+    let store = crate::service::get_state::<PersisStore>();
+    store
+        .export_contacts(format)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg_attr(feature = "gui", tauri::command)]
+pub async fn import_contacts(format: ContactsFormat, data: String) -> Result<usize, String> {
     let store = crate::service::get_state::<PersisStore>();
-    store.execute(&sql).await.map_err(|e| e.to_string())
+    let network = config_network().await.map_err(|e| e.to_string())?;
+    store
+        .import_contacts(format, &data, network)
+        .await
+        .map_err(|e| e.to_string())
 }
@@ -0,0 +1,442 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use neptune_privacy::api::export::Network;
+use neptune_privacy::api::export::ReceivingAddress;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+use sqlx::SqliteConnection;
+
+use super::persist::PersisStore;
+
+/// An address book entry, field names are the ones of the frontend `contacts` table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Contact {
+    #[serde(default)]
+    pub id: i64,
+    pub alias_name: String,
+    pub address: String,
+    #[serde(rename = "type", default)]
+    pub contact_type: String,
+    #[serde(default)]
+    pub remark: String,
+    #[serde(default)]
+    pub created_time: i64,
+    /// Network the address belongs to, the configured one if empty
+    #[serde(default)]
+    pub network: String,
+}
+
+impl Contact {
+    fn from_row(row: &SqliteRow) -> Self {
+        Self {
+            id: row.get("id"),
+            alias_name: row.get("aliasName"),
+            address: row.get("address"),
+            contact_type: row.get::<Option<String>, _>("type").unwrap_or_default(),
+            remark: row.get::<Option<String>, _>("remark").unwrap_or_default(),
+            created_time: row.get::<Option<i64>, _>("createdTime").unwrap_or_default(),
+            network: row.get("network"),
+        }
+    }
+
+    pub fn network(&self) -> Result<Network> {
+        Network::from_str(&self.network).map_err(|e| anyhow!("{}", e))
+    }
+
+    /// The address as a [ReceivingAddress] of the contact network.
+    pub fn receiving_address(&self) -> Result<ReceivingAddress> {
+        ReceivingAddress::from_bech32m(&self.address, self.network()?)
+            .with_context(|| format!("invalid {} address for {}", self.network, self.alias_name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContactsFormat {
+    Json,
+    Csv,
+}
+
+const CSV_HEADER: &str = "aliasName,address,type,remark,createdTime,network";
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Split CSV text into records, quoted fields may hold commas, quotes and newlines.
+fn csv_records(data: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = data.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated quoted field");
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records
+        .into_iter()
+        .filter(|record| !(record.len() == 1 && record[0].trim().is_empty()))
+        .collect())
+}
+
+impl PersisStore {
+    pub async fn list_contacts(&self) -> Result<Vec<Contact>> {
+        let rows = sqlx::query("SELECT * FROM contacts ORDER BY aliasName")
+            .fetch_all(&self.db)
+            .await?;
+        Ok(rows.iter().map(Contact::from_row).collect())
+    }
+
+    pub async fn get_contact(&self, id: i64) -> Result<Option<Contact>> {
+        let row = sqlx::query("SELECT * FROM contacts WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await?;
+        Ok(row.as_ref().map(Contact::from_row))
+    }
+
+    /// Contacts whose alias, address or remark contains `query`, case insensitive.
+    pub async fn search_contacts(&self, query: &str) -> Result<Vec<Contact>> {
+        let pattern = format!(
+            "%{}%",
+            query
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let rows = sqlx::query(
+            "SELECT * FROM contacts WHERE aliasName LIKE ?1 ESCAPE '\\' OR address LIKE ?1 ESCAPE '\\' OR remark LIKE ?1 ESCAPE '\\' ORDER BY aliasName",
+        )
+        .bind(pattern)
+        .fetch_all(&self.db)
+        .await?;
+        Ok(rows.iter().map(Contact::from_row).collect())
+    }
+
+    /// Validate the address for the contact network and reject an address already saved
+    /// for that network by another contact.
+    async fn check_contact(
+        tx: &mut SqliteConnection,
+        contact: &mut Contact,
+        default_network: Network,
+    ) -> Result<()> {
+        contact.alias_name = contact.alias_name.trim().to_string();
+        contact.address = contact.address.trim().to_string();
+        if contact.alias_name.is_empty() {
+            bail!("contact name is empty");
+        }
+        if contact.network.is_empty() {
+            contact.network = default_network.to_string();
+        }
+        contact.receiving_address()?;
+
+        let duplicate: Option<String> = sqlx::query_scalar(
+            "SELECT aliasName FROM contacts WHERE address = ? AND network = ? AND id != ?",
+        )
+        .bind(&contact.address)
+        .bind(&contact.network)
+        .bind(contact.id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(name) = duplicate {
+            bail!("address is already saved as {}", name);
+        }
+
+        Ok(())
+    }
+
+    pub async fn add_contact(&self, mut contact: Contact, default_network: Network) -> Result<i64> {
+        contact.id = 0;
+        let mut conn = self.db.acquire().await?;
+        Self::check_contact(&mut conn, &mut contact, default_network).await?;
+        if contact.created_time == 0 {
+            contact.created_time = chrono::Utc::now().timestamp_millis();
+        }
+
+        let result = sqlx::query(
+            "INSERT INTO contacts (aliasName, address, type, remark, createdTime, network) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&contact.alias_name)
+        .bind(&contact.address)
+        .bind(&contact.contact_type)
+        .bind(&contact.remark)
+        .bind(contact.created_time)
+        .bind(&contact.network)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn update_contact(
+        &self,
+        mut contact: Contact,
+        default_network: Network,
+    ) -> Result<()> {
+        let mut conn = self.db.acquire().await?;
+        Self::check_contact(&mut conn, &mut contact, default_network).await?;
+
+        let result = sqlx::query(
+            "UPDATE contacts SET aliasName = ?, address = ?, type = ?, remark = ?, network = ? WHERE id = ?",
+        )
+        .bind(&contact.alias_name)
+        .bind(&contact.address)
+        .bind(&contact.contact_type)
+        .bind(&contact.remark)
+        .bind(&contact.network)
+        .bind(contact.id)
+        .execute(&mut *conn)
+        .await?;
+        if result.rows_affected() == 0 {
+            bail!("contact {} not found", contact.id);
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete_contact(&self, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM contacts WHERE id = ?")
+            .bind(id)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn export_contacts(&self, format: ContactsFormat) -> Result<String> {
+        let contacts = self.list_contacts().await?;
+        match format {
+            ContactsFormat::Json => Ok(serde_json::to_string_pretty(&contacts)?),
+            ContactsFormat::Csv => {
+                let mut csv = String::from(CSV_HEADER);
+                csv.push('\n');
+                for contact in contacts {
+                    let fields = [
+                        csv_field(&contact.alias_name),
+                        csv_field(&contact.address),
+                        csv_field(&contact.contact_type),
+                        csv_field(&contact.remark),
+                        contact.created_time.to_string(),
+                        csv_field(&contact.network),
+                    ];
+                    csv.push_str(&fields.join(","));
+                    csv.push('\n');
+                }
+                Ok(csv)
+            }
+        }
+    }
+
+    /// Import contacts, all or nothing: any invalid or duplicate address aborts the import.
+    pub async fn import_contacts(
+        &self,
+        format: ContactsFormat,
+        data: &str,
+        default_network: Network,
+    ) -> Result<usize> {
+        let contacts: Vec<Contact> = match format {
+            ContactsFormat::Json => serde_json::from_str(data)?,
+            ContactsFormat::Csv => {
+                let mut records = csv_records(data)?.into_iter();
+                let header = records.next().unwrap_or_default();
+                let column = |name: &str| header.iter().position(|h| h.trim() == name);
+                let alias_name = column("aliasName").context("missing aliasName column")?;
+                let address = column("address").context("missing address column")?;
+                let (contact_type, remark, created_time, network) = (
+                    column("type"),
+                    column("remark"),
+                    column("createdTime"),
+                    column("network"),
+                );
+
+                let mut contacts = vec![];
+                for (n, record) in records.enumerate() {
+                    let get = |i: Option<usize>| {
+                        i.and_then(|i| record.get(i)).cloned().unwrap_or_default()
+                    };
+                    contacts.push(Contact {
+                        id: 0,
+                        alias_name: get(Some(alias_name)),
+                        address: get(Some(address)),
+                        contact_type: get(contact_type),
+                        remark: get(remark),
+                        created_time: match get(created_time).trim() {
+                            "" => 0,
+                            t => t
+                                .parse()
+                                .with_context(|| format!("invalid createdTime on row {}", n + 2))?,
+                        },
+                        network: get(network),
+                    });
+                }
+                contacts
+            }
+        };
+
+        let mut imported = 0;
+        let mut tx = self.db.begin().await?;
+        for mut contact in contacts {
+            contact.id = 0;
+            // sees the contacts imported before it, so duplicates in the data are caught too
+            Self::check_contact(&mut tx, &mut contact, default_network)
+                .await
+                .with_context(|| format!("contact {}", contact.alias_name))?;
+            if contact.created_time == 0 {
+                contact.created_time = chrono::Utc::now().timestamp_millis();
+            }
+            sqlx::query(
+                "INSERT INTO contacts (aliasName, address, type, remark, createdTime, network) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&contact.alias_name)
+            .bind(&contact.address)
+            .bind(&contact.contact_type)
+            .bind(&contact.remark)
+            .bind(contact.created_time)
+            .bind(&contact.network)
+            .execute(&mut *tx)
+            .await?;
+            imported += 1;
+        }
+        tx.commit().await?;
+
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use neptune_privacy::api::export::SpendingKey;
+    use neptune_privacy::state::wallet::wallet_entropy::WalletEntropy;
+
+    use super::*;
+
+    fn address(index: u64, network: Network) -> String {
+        let key = WalletEntropy::devnet_wallet().nth_generation_spending_key(index);
+        SpendingKey::from(key)
+            .to_address()
+            .to_bech32m(network)
+            .unwrap()
+    }
+
+    fn contact(alias_name: &str, address: String) -> Contact {
+        Contact {
+            id: 0,
+            alias_name: alias_name.to_string(),
+            address,
+            contact_type: String::new(),
+            remark: String::new(),
+            created_time: 0,
+            network: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_csv_records() {
+        let records = csv_records(
+            "aliasName,remark\r\n\"Smith, John\",\"said \"\"hi\"\"\nand left\"\n\nbob,\n",
+        )
+        .unwrap();
+        assert_eq!(
+            records,
+            vec![
+                vec!["aliasName", "remark"],
+                vec!["Smith, John", "said \"hi\"\nand left"],
+                vec!["bob", ""],
+            ]
+        );
+
+        // the last record needs no trailing newline
+        assert_eq!(csv_records("a,b").unwrap(), vec![vec!["a", "b"]]);
+
+        assert!(csv_records("a,\"b\n").is_err());
+
+        // exported fields read back unchanged
+        let field = "a \"quoted\", multi\r\nline remark";
+        let records = csv_records(&format!("{},x\n", csv_field(field))).unwrap();
+        assert_eq!(records, vec![vec![field, "x"]]);
+    }
+
+    #[tokio::test]
+    async fn test_import_contacts_rejects_duplicates() {
+        let store = PersisStore::new(&PathBuf::new(), Network::Main)
+            .await
+            .unwrap();
+        store
+            .add_contact(contact("alice", address(0, Network::Main)), Network::Main)
+            .await
+            .unwrap();
+
+        // already saved
+        let data = serde_json::to_string(&[contact("bob", address(0, Network::Main))]).unwrap();
+        let err = store
+            .import_contacts(ContactsFormat::Json, &data, Network::Main)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("already saved as alice"));
+
+        // twice in the imported data, nothing of it is kept
+        let data = format!(
+            "{}\ncarol,{}\ndave,{}\n",
+            CSV_HEADER,
+            address(1, Network::Main),
+            address(1, Network::Main)
+        );
+        assert!(store
+            .import_contacts(ContactsFormat::Csv, &data, Network::Main)
+            .await
+            .is_err());
+        assert_eq!(store.list_contacts().await.unwrap().len(), 1);
+
+        // the same address on another network is another contact
+        let data = format!(
+            "{}\ncarol,{},,,,{}\n",
+            CSV_HEADER,
+            address(0, Network::RegTest),
+            Network::RegTest
+        );
+        assert_eq!(
+            store
+                .import_contacts(ContactsFormat::Csv, &data, Network::Main)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(store.list_contacts().await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+use tracing::*;
+
+use super::persist::PersisStore;
+
+/// One output of a batch send, as entered in the batch form.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchOutput {
+    pub index: i64,
+    pub to_address: String,
+    pub amount: String,
+}
+
+/// A transaction sent from the app, kept until the wallet history shows it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionHistory {
+    pub txid: String,
+    pub timestamp: i64,
+    pub height: i64,
+    pub address_id: i64,
+    pub address: String,
+    pub fee: String,
+    pub priority_fee: String,
+    #[serde(default)]
+    pub outputs: Vec<String>,
+    #[serde(default)]
+    pub batch_output: Vec<BatchOutput>,
+}
+
+/// Old rows hold `""`, `"[]"` or `"undefined"` for empty lists.
+fn parse_json_list<T: serde::de::DeserializeOwned>(txid: &str, value: Option<String>) -> Vec<T> {
+    match value.as_deref().map(str::trim) {
+        None | Some("") | Some("[]") | Some("undefined") => vec![],
+        Some(value) => serde_json::from_str(value).unwrap_or_else(|e| {
+            warn!("invalid list in execution history {}: {}", txid, e);
+            vec![]
+        }),
+    }
+}
+
+impl ExecutionHistory {
+    fn from_row(row: &SqliteRow) -> Self {
+        let txid: String = row.get("txid");
+        Self {
+            timestamp: row.get("timestamp"),
+            height: row.get("height"),
+            address_id: row.get("addressId"),
+            address: row.get("address"),
+            fee: row.get("fee"),
+            priority_fee: row.get("priorityFee"),
+            // outputs were always stored in the status column
+            outputs: parse_json_list(&txid, row.get("status")),
+            batch_output: parse_json_list(&txid, row.get("batchOutput")),
+            txid,
+        }
+    }
+}
+
+impl PersisStore {
+    /// Sent transactions of the wallet `address_id`, newest first.
+    pub async fn execution_history(&self, address_id: i64) -> Result<Vec<ExecutionHistory>> {
+        let rows = sqlx::query(
+            "SELECT * FROM execution_history WHERE addressId = ? ORDER BY timestamp DESC",
+        )
+        .bind(address_id)
+        .fetch_all(&self.db)
+        .await?;
+        Ok(rows.iter().map(ExecutionHistory::from_row).collect())
+    }
+
+    pub async fn add_execution_history(&self, history: &ExecutionHistory) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO execution_history (txid, timestamp, height, addressId, address, fee, priorityFee, status, batchOutput) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&history.txid)
+        .bind(history.timestamp)
+        .bind(history.height)
+        .bind(history.address_id)
+        .bind(&history.address)
+        .bind(&history.fee)
+        .bind(&history.priority_fee)
+        .bind(serde_json::to_string(&history.outputs)?)
+        .bind(serde_json::to_string(&history.batch_output)?)
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_execution_history(&self, txid: &str) -> Result<()> {
+        sqlx::query("DELETE FROM execution_history WHERE txid = ?")
+            .bind(txid)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use anyhow::Result;
+use sqlx::Row;
+
+use super::persist::PersisStore;
+
+fn check_namespace(namespace: &str) -> Result<()> {
+    if namespace.is_empty() {
+        bail!("empty namespace");
+    }
+    Ok(())
+}
+
+/// Persistent string values grouped by namespace, for frontend settings and small state.
+impl PersisStore {
+    pub async fn kv_get(&self, namespace: &str, key: &str) -> Result<Option<String>> {
+        check_namespace(namespace)?;
+        let value =
+            sqlx::query_scalar("SELECT value FROM kv_store WHERE namespace = ? AND key = ?")
+                .bind(namespace)
+                .bind(key)
+                .fetch_optional(&self.db)
+                .await?;
+        Ok(value)
+    }
+
+    pub async fn kv_set(&self, namespace: &str, key: &str, value: &str) -> Result<()> {
+        check_namespace(namespace)?;
+        sqlx::query("INSERT OR REPLACE INTO kv_store (namespace, key, value) VALUES (?, ?, ?)")
+            .bind(namespace)
+            .bind(key)
+            .bind(value)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn kv_delete(&self, namespace: &str, key: &str) -> Result<()> {
+        check_namespace(namespace)?;
+        sqlx::query("DELETE FROM kv_store WHERE namespace = ? AND key = ?")
+            .bind(namespace)
+            .bind(key)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn kv_list(&self, namespace: &str) -> Result<BTreeMap<String, String>> {
+        check_namespace(namespace)?;
+        let rows = sqlx::query("SELECT key, value FROM kv_store WHERE namespace = ?")
+            .bind(namespace)
+            .fetch_all(&self.db)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("key"), row.get("value")))
+            .collect())
+    }
+}
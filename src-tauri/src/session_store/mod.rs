@@ -4,6 +4,9 @@ use serde::{de::DeserializeOwned, Serialize};
 use tokio::sync::RwLock;
 
 pub mod command;
+pub mod contacts;
+pub mod history;
+pub mod kv;
 pub mod persist;
 
 #[derive(Debug, Clone)]
@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
-use sqlx::{Column, Row, SqlitePool, TypeInfo};
+use neptune_privacy::api::export::Network;
+use sqlx::SqlitePool;
 use sqlx_migrator::{Info, Migrate, Migrator, Plan};
 
 struct CreateContactsMigration;
@@ -23,6 +24,19 @@ sqlx_migrator::sqlite_migration!(
     )]
 );
 
+struct AddContactsNetworkMigration;
+
+sqlx_migrator::sqlite_migration!(
+    AddContactsNetworkMigration,
+    "contacts",
+    "add_contacts_network",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        "ALTER TABLE contacts ADD COLUMN network TEXT NOT NULL DEFAULT ''", //up
+        "ALTER TABLE contacts DROP COLUMN network"                          //down
+    )]
+);
+
 struct CreateExecutionHistoryMigration;
 
 sqlx_migrator::sqlite_migration!(
@@ -46,89 +60,98 @@ sqlx_migrator::sqlite_migration!(
     )]
 );
 
+struct CreateKvStoreMigration;
+
+sqlx_migrator::sqlite_migration!(
+    CreateKvStoreMigration,
+    "kv",
+    "create_kv_store",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        "CREATE TABLE IF NOT EXISTS kv_store (
+            namespace TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (namespace, key)
+        );", //up
+        "DROP TABLE kv_store" //down
+    )]
+);
+
 #[derive(Debug, Clone)]
 pub struct PersisStore {
-    db: SqlitePool,
+    pub(super) db: SqlitePool,
 }
 
 impl PersisStore {
-    pub async fn new(data_dir: &PathBuf) -> anyhow::Result<Self> {
-        let db_path = data_dir.join("store.db");
-
-        let options = sqlx::sqlite::SqliteConnectOptions::new()
-            .filename(db_path)
-            .create_if_missing(true);
-
-        let pool = sqlx::SqlitePool::connect_with(options)
-            .await
-            .map_err(|err| anyhow::anyhow!("Could not connect to database: {err}"))?;
+    /// Open the store, contacts saved before they had a network get `network`.
+    pub async fn new(data_dir: &PathBuf, network: Network) -> anyhow::Result<Self> {
+        #[cfg(not(test))]
+        let pool = {
+            let db_path = data_dir.join("store.db");
+
+            let options = sqlx::sqlite::SqliteConnectOptions::new()
+                .filename(db_path)
+                .create_if_missing(true);
+
+            sqlx::SqlitePool::connect_with(options)
+                .await
+                .map_err(|err| anyhow::anyhow!("Could not connect to database: {err}"))?
+        };
+
+        #[cfg(test)]
+        let pool = {
+            let _ = data_dir;
+            sqlx::SqlitePool::connect("sqlite::memory:").await?
+        };
 
         let store = Self { db: pool };
-        store.migrate().await?;
+        store.migrate(network).await?;
         Ok(store)
     }
 
-    async fn migrate(&self) -> anyhow::Result<()> {
+    async fn migrate(&self, network: Network) -> anyhow::Result<()> {
         let mut migrator = Migrator::default();
         // Adding migration can fail if another migration with same app and name and different values gets added
         // Adding migrations add its parents, replaces and not before as well
         migrator.add_migration(Box::new(CreateExecutionHistoryMigration))?;
         migrator.add_migration(Box::new(CreateContactsMigration))?;
+        migrator.add_migration(Box::new(AddContactsNetworkMigration))?;
+        migrator.add_migration(Box::new(CreateKvStoreMigration))?;
 
         let mut conn = self.db.acquire().await?;
         // use apply all to apply all pending migration
         migrator.run(&mut *conn, &Plan::apply_all()).await?;
 
+        sqlx::query("UPDATE contacts SET network = ? WHERE network = ''")
+            .bind(network.to_string())
+            .execute(&mut *conn)
+            .await?;
+
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_migrate_contacts_network() {
+        let store = PersisStore::new(&PathBuf::new(), Network::RegTest)
+            .await
+            .unwrap();
+        // a contact saved before contacts had a network
+        sqlx::query("INSERT INTO contacts (aliasName, address) VALUES ('alice', 'address')")
+            .execute(&store.db)
+            .await
+            .unwrap();
 
-    pub async fn execute(&self, query: &str) -> Result<Vec<serde_json::Value>, sqlx::Error> {
-        let res = sqlx::query(query).fetch_all(&self.db).await?;
-        let json = res
-            .into_iter()
-            .map(|row| {
-                let col = row.columns();
-                let mut json_row = serde_json::Map::new();
-                for col in col.iter() {
-                    let type_info = col.type_info();
-                    let name = col.name();
-                    match type_info.name() {
-                        "TEXT" => {
-                            let value = row.get::<String, _>(name);
-                            json_row.insert(name.to_owned(), serde_json::Value::String(value));
-                        }
-                        "INTEGER" => {
-                            let value = row.get::<i64, _>(name);
-                            json_row
-                                .insert(name.to_owned(), serde_json::Value::Number(value.into()));
-                        }
-                        "NULL" => {
-                            json_row.insert(name.to_owned(), serde_json::Value::Null);
-                        }
-                        "BLOB" => {
-                            let value = row.get::<Vec<u8>, _>(name);
-
-                            json_row.insert(
-                                name.to_owned(),
-                                serde_json::Value::String(hex::encode(value)),
-                            );
-                        }
-                        "BOOLEAN" => {
-                            let value = row.get::<bool, _>(name);
-                            json_row.insert(name.to_owned(), serde_json::Value::Bool(value));
-                        }
-                        "REAL" => {
-                            let value = row.get::<f64, _>(name);
-                            let number = serde_json::Number::from_f64(value).unwrap();
-                            json_row.insert(name.to_owned(), serde_json::Value::Number(number));
-                        }
-                        _ => {}
-                    }
-                }
-                serde_json::Value::Object(json_row)
-            })
-            .collect::<Vec<_>>();
-
-        Ok(json)
+        store.migrate(Network::RegTest).await.unwrap();
+        let network: String = sqlx::query_scalar("SELECT network FROM contacts")
+            .fetch_one(&store.db)
+            .await
+            .unwrap();
+        assert_eq!(network, Network::RegTest.to_string());
     }
 }
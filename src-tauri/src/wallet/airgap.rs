@@ -0,0 +1,305 @@
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use neptune_privacy::api::export::Network;
+use neptune_privacy::api::export::Timestamp;
+use neptune_privacy::api::export::Tip5;
+use neptune_privacy::api::export::TxProvingCapability;
+use neptune_privacy::protocol::consensus::block::block_height::BlockHeight;
+use neptune_privacy::protocol::consensus::transaction::Transaction;
+use neptune_privacy::protocol::consensus::type_scripts::native_currency_amount::NativeCurrencyAmount;
+use neptune_privacy::protocol::peer::transfer_transaction::TransferTransaction;
+use neptune_privacy::state::wallet::address::ReceivingAddress;
+use neptune_privacy::state::wallet::address::SpendingKey;
+use neptune_privacy::state::wallet::expected_utxo::ExpectedUtxo;
+use neptune_privacy::state::wallet::expected_utxo::UtxoNotifier;
+use neptune_privacy::state::wallet::unlocked_utxo::UnlockedUtxo;
+use neptune_privacy::state::wallet::utxo_notification::UtxoNotificationMedium;
+use neptune_privacy::util_types::mutator_set::ms_membership_proof::MsMembershipProof;
+use neptune_privacy::util_types::mutator_set::mutator_set_accumulator::MutatorSetAccumulator;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::*;
+
+use super::input::InputSelectionRule;
+use super::spend::SendError;
+use super::UtxoRecoveryData;
+
+/// A transaction prepared by an online wallet, to be proven by the wallet holding the seed.
+///
+/// Carries no spending secrets, a watch-only wallet can create it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnsignedTransaction {
+    pub network: Network,
+    pub inputs: Vec<UnsignedInput>,
+    pub outputs: Vec<(ReceivingAddress, NativeCurrencyAmount, Option<Timestamp>)>,
+    pub owned_utxo_notification_medium: UtxoNotificationMedium,
+    pub unowned_utxo_notification_medium: UtxoNotificationMedium,
+    pub fee: NativeCurrencyAmount,
+    pub tip_mutator_set: MutatorSetAccumulator,
+    pub tip_height: BlockHeight,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnsignedInput {
+    pub utxo_id: i64,
+    /// Key the UTXO is locked to, the offline wallet has not seen it and derives it directly.
+    pub key: KeyIndex,
+    pub recovery_data: UtxoRecoveryData,
+    pub membership_proof: MsMembershipProof,
+}
+
+/// Derivation index of a wallet key.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum KeyIndex {
+    Generation(u64),
+    Symmetric(u64),
+}
+
+/// A proven transaction coming back from the offline wallet, ready to broadcast.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignedTransaction {
+    pub network: Network,
+    pub transaction: TransferTransaction,
+    pub input_ids: Vec<i64>,
+    /// Off-chain notified outputs the online wallet has to expect.
+    pub expected_utxos: Vec<ExpectedUtxo>,
+    /// Requested outputs, recorded as sent payments once the transaction is broadcast.
+    pub payments: Vec<(ReceivingAddress, NativeCurrencyAmount, Option<Timestamp>)>,
+    pub payment_medium: UtxoNotificationMedium,
+}
+
+impl UnsignedTransaction {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::deserialize(bytes).context("invalid unsigned transaction")
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+}
+
+impl SignedTransaction {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::deserialize(bytes).context("invalid signed transaction")
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn txid(&self) -> String {
+        self.transaction.kernel.txid().to_string()
+    }
+}
+
+impl super::WalletState {
+    /// Select inputs and fetch their membership proofs, the first of the air-gapped steps.
+    ///
+    /// The membership proofs are only valid at the current tip, the transaction has to be
+    /// proven and broadcast before the mutator set moves too far.
+    pub async fn prepare_unsigned_transaction(
+        &self,
+        outputs: Vec<(ReceivingAddress, NativeCurrencyAmount, Option<Timestamp>)>,
+        utxo_notification_media: (UtxoNotificationMedium, UtxoNotificationMedium),
+        fee: NativeCurrencyAmount,
+        rule: InputSelectionRule,
+        must_include_utxos: Vec<i64>,
+    ) -> Result<UnsignedTransaction> {
+        let _spend_guard = self.spend_lock.lock().await;
+
+        let (inputs, db_ids) = self
+            .select_inputs(&outputs, fee, rule, must_include_utxos)
+            .await?;
+        let (membership_proofs, tip_mutator_set, tip_height) =
+            self.restore_membership_proofs(&inputs).await?;
+
+        let inputs = db_ids
+            .into_iter()
+            .zip(inputs)
+            .zip(membership_proofs)
+            .map(|((utxo_id, recovery_data), membership_proof)| {
+                let key = self
+                    .find_key_index(&recovery_data.utxo)
+                    .with_context(|| format!("utxo {} is not locked to a wallet key", utxo_id))?;
+                Ok(UnsignedInput {
+                    utxo_id,
+                    key,
+                    recovery_data,
+                    membership_proof,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let (owned_utxo_notification_medium, unowned_utxo_notification_medium) =
+            utxo_notification_media;
+
+        Ok(UnsignedTransaction {
+            network: self.network,
+            inputs,
+            outputs,
+            owned_utxo_notification_medium,
+            unowned_utxo_notification_medium,
+            fee,
+            tip_mutator_set,
+            tip_height,
+        })
+    }
+
+    /// Prove an [UnsignedTransaction] with the wallet keys, needs no server.
+    pub async fn sign_unsigned_transaction(
+        &self,
+        unsigned: UnsignedTransaction,
+    ) -> Result<SignedTransaction> {
+        ensure!(!self.is_watch_only(), SendError::WatchOnly);
+        ensure!(
+            unsigned.network == self.network,
+            "transaction is for {}, wallet is on {}",
+            unsigned.network,
+            self.network
+        );
+
+        let mut tx_inputs = Vec::with_capacity(unsigned.inputs.len());
+        let mut input_ids = Vec::with_capacity(unsigned.inputs.len());
+        for input in unsigned.inputs {
+            ensure!(
+                unsigned.tip_mutator_set.verify(
+                    Tip5::hash(&input.recovery_data.utxo),
+                    &input.membership_proof
+                ),
+                "membership proof of input {} does not match the mutator set",
+                input.utxo_id
+            );
+            let spending_key = self
+                .spending_key_at(input.key)
+                .context("watch-only wallet can not spend")?;
+            ensure!(
+                spending_key.lock_script_hash() == input.recovery_data.utxo.lock_script_hash(),
+                "input {} is not locked to key {:?}",
+                input.utxo_id,
+                input.key
+            );
+            tx_inputs.push(UnlockedUtxo::unlock(
+                input.recovery_data.utxo,
+                spending_key.lock_script_and_witness(),
+                input.membership_proof,
+            ));
+            input_ids.push(input.utxo_id);
+        }
+
+        let total_input: NativeCurrencyAmount = tx_inputs
+            .iter()
+            .map(|input| input.utxo.get_native_currency_amount())
+            .sum();
+        let total_spend: NativeCurrencyAmount = unsigned
+            .outputs
+            .iter()
+            .map(|(_, amount, _)| *amount)
+            .sum::<NativeCurrencyAmount>()
+            + unsigned.fee;
+        ensure!(
+            total_input >= total_spend,
+            "inputs of {} do not cover {}",
+            total_input,
+            total_spend
+        );
+
+        let change_key = SpendingKey::Symmetric(self.key.entropy()?.nth_symmetric_key(0));
+        let payments = unsigned.outputs.clone();
+        let tx_outputs = self
+            .generate_tx_outputs(
+                unsigned.outputs,
+                unsigned.owned_utxo_notification_medium,
+                unsigned.unowned_utxo_notification_medium,
+                unsigned.tip_height,
+            )
+            .await?;
+
+        let (transaction, _, maybe_change_output) = self
+            .create_transaction_with_prover_capability(
+                tx_outputs.clone(),
+                tx_inputs,
+                change_key,
+                unsigned.owned_utxo_notification_medium,
+                unsigned.fee,
+                Timestamp::now(),
+                TxProvingCapability::ProofCollection,
+                unsigned.tip_mutator_set,
+                unsigned.tip_height,
+            )
+            .await?;
+        info!("proven air-gapped tx {}", transaction.txid());
+
+        let mut full_outputs = tx_outputs;
+        if let Some(change_output) = maybe_change_output {
+            full_outputs.push(change_output);
+        }
+        let expected_utxos = self.extract_expected_utxos(&full_outputs, UtxoNotifier::Myself);
+
+        let transaction: TransferTransaction = (&transaction)
+            .try_into()
+            .map_err(|e| anyhow!("transaction is not transferable: {:?}", e))?;
+
+        Ok(SignedTransaction {
+            network: self.network,
+            transaction,
+            input_ids,
+            expected_utxos,
+            payments,
+            payment_medium: unsigned.unowned_utxo_notification_medium,
+        })
+    }
+
+    /// Save and broadcast a [SignedTransaction], its inputs are tracked as pending.
+    pub async fn broadcast_signed_transaction(
+        &self,
+        signed: SignedTransaction,
+    ) -> Result<Transaction, SendError> {
+        let _spend_guard = self.spend_lock.lock().await;
+
+        self.save_proven_transaction(&signed, None).await?;
+        self.broadcast_proven_transaction(signed, None).await
+    }
+
+    /// The inputs of the transaction must be exactly the UTXOs it claims to spend.
+    pub(super) async fn check_signed_transaction(
+        &self,
+        signed: &SignedTransaction,
+    ) -> Result<Transaction> {
+        ensure!(
+            signed.network == self.network,
+            "transaction is for {}, wallet is on {}",
+            signed.network,
+            self.network
+        );
+
+        let transaction = Transaction::try_from(signed.transaction.clone())
+            .map_err(|e| anyhow!("invalid transfer transaction: {:?}", e))?;
+
+        let inputs = self
+            .get_unspent_inputs_with_ids(&signed.input_ids)
+            .await
+            .context("transaction spends utxos that are unknown or already spent")?;
+        ensure!(
+            inputs.len() == transaction.kernel.inputs.len(),
+            "transaction has {} inputs, expected {}",
+            transaction.kernel.inputs.len(),
+            inputs.len()
+        );
+        for input in inputs {
+            let index_set = input.recovery_data.abs_i();
+            ensure!(
+                transaction
+                    .kernel
+                    .inputs
+                    .iter()
+                    .any(|removal_record| removal_record.absolute_indices == index_set),
+                "transaction does not spend utxo {}",
+                input.id
+            );
+        }
+
+        Ok(transaction)
+    }
+}
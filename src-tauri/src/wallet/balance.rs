@@ -1,8 +1,68 @@
+use anyhow::bail;
 use anyhow::Result;
 use neptune_privacy::api::export::Timestamp;
 use neptune_privacy::protocol::consensus::type_scripts::native_currency_amount::NativeCurrencyAmount;
 use serde::Deserialize;
 use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+use super::labels::LabelType;
+
+/// Upper bound on the number of points of a [BalanceInterval] series.
+const MAX_BALANCE_SERIES_POINTS: u64 = 10_000;
+
+const LIMB_SUMS: &str = "SUM(amount_0), SUM(amount_1), SUM(amount_2), SUM(amount_3)";
+
+/// Split an amount into the four 32 bit limbs stored with each UTXO.
+///
+/// SQLite integers are 64 bit, the limbs let `SUM` add up to 2^31 amounts without overflow.
+pub(super) fn amount_limbs(nau: i128) -> [i64; 4] {
+    let nau = nau as u128;
+    std::array::from_fn(|i| ((nau >> (32 * i)) & u32::MAX as u128) as i64)
+}
+
+/// The amount from the sums of the columns of [LIMB_SUMS], starting at `offset`.
+fn sum_limbs(row: &SqliteRow, offset: usize) -> i128 {
+    (0..4)
+        .map(|i| (row.get::<Option<i64>, _>(offset + i).unwrap_or(0) as i128) << (32 * i))
+        .sum()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BalanceInterval {
+    #[default]
+    Day,
+    Week,
+}
+
+impl BalanceInterval {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "day" => Some(BalanceInterval::Day),
+            "week" => Some(BalanceInterval::Week),
+            _ => None,
+        }
+    }
+
+    fn millis(&self) -> u64 {
+        match self {
+            BalanceInterval::Day => 24 * 60 * 60 * 1000,
+            BalanceInterval::Week => 7 * 24 * 60 * 60 * 1000,
+        }
+    }
+}
+
+/// Confirmed balance at the end of a bucket, time-locked UTXOs included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalancePoint {
+    /// Start of the bucket
+    pub timestamp: Timestamp,
+    /// Last millisecond counted, the last bucket is cut off at the end of the series
+    pub end: Timestamp,
+    pub balance: String,
+}
 
 impl super::WalletState {
     pub async fn get_balance(&self) -> Result<NativeCurrencyAmount> {
@@ -23,11 +83,22 @@ impl super::WalletState {
         Ok(NativeCurrencyAmount::from_nau(balance))
     }
 
+    /// Every receive and spend, labelled by the transaction label or else the UTXO label.
     pub async fn get_balance_history(&self) -> Result<Vec<WalletHistory>> {
         let utxos = self.get_utxos().await?;
+        let tx_labels = self.get_labels(LabelType::Tx).await?;
+        let output_labels = self.get_labels(LabelType::Output).await?;
+        let label_of = |txid: &Option<String>, hash: &String| {
+            txid.as_ref()
+                .and_then(|txid| tx_labels.get(txid))
+                .or_else(|| output_labels.get(hash))
+                .cloned()
+        };
+
         let mut history = Vec::new();
         for utxo in utxos {
             history.push(WalletHistory {
+                label: label_of(&utxo.confirmed_txid, &utxo.hash),
                 amount: utxo
                     .recovery_data
                     .utxo
@@ -41,6 +112,7 @@ impl super::WalletState {
             });
             if let Some(spent_in_block) = utxo.spent_in_block {
                 history.push(WalletHistory {
+                    label: label_of(&utxo.spent_txid, &utxo.hash),
                     amount: "-".to_string()
                         + &utxo
                             .recovery_data
@@ -83,6 +155,89 @@ impl super::WalletState {
     }
 }
 
+impl super::WalletState {
+    /// Confirmed balance after block `height`, time-locked UTXOs included.
+    pub async fn balance_at_height(&self, height: u64) -> Result<NativeCurrencyAmount> {
+        let row = sqlx::query(&format!(
+            "SELECT {LIMB_SUMS} FROM wallet_state_utxos WHERE confirm_height <= ?1 AND (spent_height IS NULL OR spent_height > ?1)"
+        ))
+        .bind(height as i64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(NativeCurrencyAmount::from_nau(sum_limbs(&row, 0)))
+    }
+
+    /// Confirmed balance at `timestamp` by block time, time-locked UTXOs included.
+    pub async fn balance_at_time(&self, timestamp: Timestamp) -> Result<NativeCurrencyAmount> {
+        let row = sqlx::query(&format!(
+            "SELECT {LIMB_SUMS} FROM wallet_state_utxos WHERE confirm_timestamp <= ?1 AND (spent_timestamp IS NULL OR spent_timestamp > ?1)"
+        ))
+        .bind(timestamp.to_millis() as i64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(NativeCurrencyAmount::from_nau(sum_limbs(&row, 0)))
+    }
+
+    /// Balance at the end of each `interval` from `from` to `to`, buckets start at `from`.
+    ///
+    /// The last bucket is partial unless `to` is its last millisecond, its balance is
+    /// taken at `to`.
+    pub async fn balance_series(
+        &self,
+        from: Timestamp,
+        to: Timestamp,
+        interval: BalanceInterval,
+    ) -> Result<Vec<BalancePoint>> {
+        let (from, to) = (from.to_millis(), to.to_millis());
+        if to < from {
+            bail!("series ends before it starts");
+        }
+        let width = interval.millis();
+        let points = (to - from) / width + 1;
+        if points > MAX_BALANCE_SERIES_POINTS {
+            bail!(
+                "series of {} points is too long, use a larger interval",
+                points
+            );
+        }
+
+        // receives and spends per bucket, everything before `from` in bucket -1
+        let mut deltas = vec![0i128; points as usize + 1];
+        for (column, sign) in [("confirm_timestamp", 1), ("spent_timestamp", -1)] {
+            let rows = sqlx::query(&format!(
+                "SELECT CASE WHEN {column} < ?1 THEN -1 ELSE ({column} - ?1) / ?2 END AS bucket, {LIMB_SUMS} FROM wallet_state_utxos WHERE {column} <= ?3 GROUP BY bucket"
+            ))
+            .bind(from as i64)
+            .bind(width as i64)
+            .bind(to as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+            for row in rows {
+                let bucket = row.get::<i64, _>("bucket") + 1;
+                deltas[bucket as usize] += sign * sum_limbs(&row, 1);
+            }
+        }
+
+        let mut balance = deltas[0];
+        Ok(deltas[1..]
+            .iter()
+            .enumerate()
+            .map(|(i, delta)| {
+                balance += delta;
+                let start = from + i as u64 * width;
+                BalancePoint {
+                    timestamp: Timestamp::millis(start),
+                    end: Timestamp::millis((start + width - 1).min(to)),
+                    balance: NativeCurrencyAmount::from_nau(balance).display_lossless(),
+                }
+            })
+            .collect())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct WalletHistory {
     pub amount: String,
@@ -91,4 +246,147 @@ pub struct WalletHistory {
     pub index: u64,
     pub release_date: Option<Timestamp>,
     pub txid: Option<String>,
+    pub label: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::test_wallet_state;
+    use crate::wallet::WalletState;
+
+    const DAY: u64 = 24 * 60 * 60 * 1000;
+
+    #[tokio::test]
+    async fn test_amount_limbs() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let amounts = [
+            0,
+            1,
+            u32::MAX as i128,
+            1 << 32,
+            NativeCurrencyAmount::coins(1_000_000).to_nau(),
+            i128::MAX / 3,
+        ];
+
+        for a in amounts {
+            for b in amounts {
+                let (a_limbs, b_limbs) = (amount_limbs(a), amount_limbs(b));
+                let row = sqlx::query("SELECT ?1 + ?5, ?2 + ?6, ?3 + ?7, ?4 + ?8")
+                    .bind(a_limbs[0])
+                    .bind(a_limbs[1])
+                    .bind(a_limbs[2])
+                    .bind(a_limbs[3])
+                    .bind(b_limbs[0])
+                    .bind(b_limbs[1])
+                    .bind(b_limbs[2])
+                    .bind(b_limbs[3])
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+                assert_eq!(sum_limbs(&row, 0), a + b);
+            }
+        }
+    }
+
+    async fn add_utxo(wallet_state: &WalletState, coins: u32, confirmed: u64, spent: Option<u64>) {
+        let limbs = amount_limbs(NativeCurrencyAmount::coins(coins).to_nau());
+        sqlx::query(
+            "INSERT INTO wallet_state_utxos (hash, recovery_data, confirmed_in_block, confirm_height, amount_0, amount_1, amount_2, amount_3, confirm_timestamp, spent_timestamp) VALUES (?, x'00', '', 0, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(format!("{}-{}", coins, confirmed))
+        .bind(limbs[0])
+        .bind(limbs[1])
+        .bind(limbs[2])
+        .bind(limbs[3])
+        .bind(confirmed as i64)
+        .bind(spent.map(|spent| spent as i64))
+        .execute(&wallet_state.pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_balance_series() {
+        let wallet_state = test_wallet_state().await;
+        let (from, to) = (10 * DAY, 12 * DAY);
+
+        // received and spent before the series
+        add_utxo(&wallet_state, 32, DAY, Some(9 * DAY)).await;
+        // received before the series, counts from the first bucket
+        add_utxo(&wallet_state, 1, 5 * DAY, None).await;
+        // first millisecond of the first bucket
+        add_utxo(&wallet_state, 2, from, None).await;
+        // last millisecond of the first bucket, spent on the first of the second
+        add_utxo(&wallet_state, 4, from + DAY - 1, Some(from + DAY)).await;
+        // the last bucket starts at `to` and includes it
+        add_utxo(&wallet_state, 8, to, None).await;
+        // after the series
+        add_utxo(&wallet_state, 16, to + 1, None).await;
+
+        let series = wallet_state
+            .balance_series(
+                Timestamp::millis(from),
+                Timestamp::millis(to),
+                BalanceInterval::Day,
+            )
+            .await
+            .unwrap();
+        let points = series
+            .iter()
+            .map(|point| {
+                (
+                    point.timestamp.to_millis(),
+                    point.end.to_millis(),
+                    point.balance.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let balance = |coins| NativeCurrencyAmount::coins(coins).display_lossless();
+        assert_eq!(
+            points,
+            vec![
+                (from, from + DAY - 1, balance(7)),
+                (from + DAY, to - 1, balance(3)),
+                // partial, the UTXO right after `to` is not counted
+                (to, to, balance(11)),
+            ]
+        );
+
+        // a single point series ends where it starts
+        let series = wallet_state
+            .balance_series(
+                Timestamp::millis(from),
+                Timestamp::millis(from),
+                BalanceInterval::Week,
+            )
+            .await
+            .unwrap();
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].end.to_millis(), from);
+        assert_eq!(series[0].balance, balance(3));
+
+        // a partial last bucket half a day long
+        let series = wallet_state
+            .balance_series(
+                Timestamp::millis(from),
+                Timestamp::millis(from + DAY + DAY / 2),
+                BalanceInterval::Day,
+            )
+            .await
+            .unwrap();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[1].timestamp.to_millis(), from + DAY);
+        assert_eq!(series[1].end.to_millis(), from + DAY + DAY / 2);
+        assert_eq!(series[1].balance, balance(3));
+
+        assert!(wallet_state
+            .balance_series(
+                Timestamp::millis(to),
+                Timestamp::millis(from),
+                BalanceInterval::Day
+            )
+            .await
+            .is_err());
+    }
 }
@@ -292,14 +292,16 @@ impl BlockCache for PersistBlockCache {
             .map_err(|err| anyhow::anyhow!("Could not flush block file: {err}"))?;
 
         let mut conn = self.pool.acquire().await?;
-        sqlx::query("INSERT INTO block_cache (height, hash, pos, length) VALUES (?, ?, ?, ?)")
-            .bind(height as i64)
-            .bind(&hash)
-            .bind(pos)
-            .bind(length)
-            .execute(&mut *conn)
-            .await
-            .map_err(|err| anyhow::anyhow!("Could not insert block into database: {err}"))?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO block_cache (height, hash, pos, length) VALUES (?, ?, ?, ?)",
+        )
+        .bind(height as i64)
+        .bind(&hash)
+        .bind(pos)
+        .bind(length)
+        .execute(&mut *conn)
+        .await
+        .map_err(|err| anyhow::anyhow!("Could not insert block into database: {err}"))?;
 
         Ok(())
     }
@@ -328,6 +330,21 @@ impl BlockCache for PersistBlockCache {
         Ok(())
     }
 
+    async fn delete_blocks_between(&self, start_height: u64, end_height: u64) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("DELETE FROM block_cache WHERE height >= ? AND height < ?")
+            .bind(start_height as i64)
+            .bind(end_height as i64)
+            .execute(&mut *conn)
+            .await
+            .map_err(|err| anyhow::anyhow!("Could not delete blocks from database: {err}"))?;
+
+        self.memory_cache
+            .delete_blocks_between(start_height, end_height)
+            .await?;
+        Ok(())
+    }
+
     async fn has_block_by_height(&self, height: u64) -> Result<bool> {
         self.find_block_by_height(height)
             .await
@@ -436,6 +453,15 @@ impl BlockCache for MemoryBlockCache {
         cache.retain(|b| b.kernel.header.height < start_height.into());
         Ok(())
     }
+
+    async fn delete_blocks_between(&self, start_height: u64, end_height: u64) -> Result<()> {
+        let mut cache = self.cache.lock().await;
+        cache.retain(|b| {
+            let height: u64 = b.kernel.header.height.into();
+            height < start_height || height >= end_height
+        });
+        Ok(())
+    }
 }
 
 #[enum_dispatch(BlockCacheImpl)]
@@ -447,6 +473,8 @@ pub(super) trait BlockCache {
     async fn get_block_by_height(&self, height: u64) -> Result<Option<ExportedBlock>>;
     async fn get_block_by_digest(&self, digest: Digest) -> Result<Option<ExportedBlock>>;
     async fn delete_block_by_start_height(&self, start_height: u64) -> Result<()>;
+    /// Delete the blocks in `start_height..end_height`.
+    async fn delete_blocks_between(&self, start_height: u64, end_height: u64) -> Result<()>;
 }
 
 #[enum_dispatch]
@@ -0,0 +1,166 @@
+use anyhow::Context;
+use anyhow::Result;
+use neptune_privacy::api::export::NativeCurrencyAmount;
+use neptune_privacy::api::export::Timestamp;
+use neptune_privacy::state::wallet::utxo_notification::UtxoNotificationMedium;
+use serde::Serialize;
+use tracing::*;
+
+use super::input::InputSelectionRule;
+
+/// Inputs per consolidation transaction if none is given.
+pub const DEFAULT_MAX_CONSOLIDATION_INPUTS: usize = 10;
+
+/// Which of the wallet's own keys receives the consolidated UTXOs.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ConsolidationKey {
+    #[default]
+    Symmetric,
+    Generation,
+}
+
+impl ConsolidationKey {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "symmetric" => Some(ConsolidationKey::Symmetric),
+            "generation" => Some(ConsolidationKey::Generation),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsolidationBatch {
+    pub utxo_ids: Vec<i64>,
+    pub total: String,
+    pub fee: String,
+    /// Amount of the consolidated UTXO, total minus fee
+    pub amount: String,
+    pub txid: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsolidationPlan {
+    pub address: String,
+    pub batches: Vec<ConsolidationBatch>,
+    pub projected_fee: String,
+}
+
+impl super::WalletState {
+    /// Merge up to `count` small UTXOs into one UTXO per batch of `max_inputs`.
+    ///
+    /// UTXOs are taken in `rule` order, only those below `below` if given. Every batch is
+    /// a sweep to our own key paying `fee`, tracked as a pending transaction. With `dry_run`
+    /// only the plan is returned.
+    #[expect(clippy::too_many_arguments)]
+    pub async fn consolidate_utxos(
+        &self,
+        count: usize,
+        below: Option<NativeCurrencyAmount>,
+        rule: InputSelectionRule,
+        max_inputs: usize,
+        fee: NativeCurrencyAmount,
+        key: ConsolidationKey,
+        dry_run: bool,
+    ) -> Result<ConsolidationPlan> {
+        let spending_key = match key {
+            ConsolidationKey::Symmetric => self.key.nth_symmetric_key(0),
+            ConsolidationKey::Generation => self.key.nth_generation_spending_key(0),
+        }
+        .context("wallet has no key to consolidate to")?;
+        let address = spending_key.to_address();
+
+        let now = Timestamp::now();
+        let utxos = self
+            .get_unreserved_utxos()
+            .await?
+            .into_iter()
+            .filter(|utxo| !utxo.frozen)
+            .filter(|utxo| {
+                utxo.recovery_data
+                    .utxo
+                    .release_date()
+                    .is_none_or(|release| release <= now)
+            })
+            .filter(|utxo| {
+                below.is_none_or(|below| {
+                    utxo.recovery_data.utxo.get_native_currency_amount() < below
+                })
+            })
+            .collect();
+        let utxos = rule
+            .apply(utxos)
+            .into_iter()
+            .take(count)
+            .collect::<Vec<_>>();
+
+        let mut batches = vec![];
+        for chunk in utxos.chunks(max_inputs.max(2)) {
+            let total: NativeCurrencyAmount = chunk
+                .iter()
+                .map(|utxo| utxo.recovery_data.utxo.get_native_currency_amount())
+                .sum();
+            // a single UTXO or one eaten by the fee is not worth a transaction
+            if chunk.len() < 2 || total <= fee {
+                debug!(
+                    "skipping consolidation batch of {} worth {}",
+                    chunk.len(),
+                    total
+                );
+                continue;
+            }
+            batches.push(ConsolidationBatch {
+                utxo_ids: chunk.iter().map(|utxo| utxo.id).collect(),
+                total: total.to_string(),
+                fee: fee.to_string(),
+                amount: (total - fee).to_string(),
+                txid: None,
+                error: None,
+            });
+        }
+
+        let projected_fee: NativeCurrencyAmount = batches.iter().map(|_| fee).sum();
+        info!(
+            "consolidating {} utxos in {} batches, projected fee {}",
+            batches
+                .iter()
+                .map(|batch| batch.utxo_ids.len())
+                .sum::<usize>(),
+            batches.len(),
+            projected_fee
+        );
+
+        if !dry_run {
+            for batch in batches.iter_mut() {
+                match self
+                    .sweep_to_address(
+                        address.clone(),
+                        None,
+                        (
+                            UtxoNotificationMedium::OnChain,
+                            UtxoNotificationMedium::OnChain,
+                        ),
+                        fee,
+                        batch.utxo_ids.clone(),
+                    )
+                    .await
+                {
+                    Ok(tx) => batch.txid = Some(tx.txid().to_string()),
+                    Err(e) => {
+                        // later batches would fail the same way
+                        error!("consolidation batch failed: {}", e);
+                        batch.error = Some(e.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(ConsolidationPlan {
+            address: address.to_bech32m(self.network)?,
+            batches,
+            projected_fee: projected_fee.to_string(),
+        })
+    }
+}
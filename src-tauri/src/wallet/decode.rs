@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Result;
+use bincode::Options;
+use neptune_privacy::api::export::TransactionDetails;
+use neptune_privacy::api::export::TransactionProof;
+use neptune_privacy::protocol::consensus::transaction::primitive_witness::PrimitiveWitness;
+use neptune_privacy::protocol::consensus::transaction::transaction_kernel::TransactionKernel;
+use neptune_privacy::protocol::consensus::transaction::Transaction;
+use neptune_privacy::protocol::peer::transfer_transaction::TransferTransaction;
+use neptune_privacy::util_types::mutator_set::addition_record::AdditionRecord;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::airgap::SignedTransaction;
+
+/// Human readable view of a transaction blob.
+#[derive(Debug, Serialize)]
+pub struct DecodedTransaction {
+    /// `signed_transaction`, `transfer_transaction` or `transaction_details`
+    pub kind: &'static str,
+    pub txid: String,
+    pub timestamp: u64,
+    pub fee: String,
+    pub mutator_set_hash: String,
+    pub inputs: Vec<DecodedInput>,
+    pub outputs: Vec<DecodedOutput>,
+    /// None for transaction details, they are not proven yet
+    pub proof_type: Option<String>,
+    pub proof_size: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DecodedInput {
+    /// Database id of the spent UTXO if it belongs to the wallet
+    pub utxo_id: Option<i64>,
+    pub amount: Option<String>,
+    pub owned: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DecodedOutput {
+    pub commitment: String,
+    pub amount: Option<String>,
+    pub owned: bool,
+}
+
+/// Deserialize `bytes` as exactly one `T`, bincode would accept a prefix otherwise.
+fn decode_exact<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    bincode::options()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(bytes)
+        .ok()
+}
+
+impl super::WalletState {
+    /// Decode a bincode [SignedTransaction], [TransferTransaction] or [TransactionDetails].
+    ///
+    /// Inputs are matched against the wallet UTXOs, outputs against the wallet keys and the
+    /// expected UTXOs.
+    pub async fn decode_transaction(&self, bytes: &[u8]) -> Result<DecodedTransaction> {
+        if let Some(signed) = decode_exact::<SignedTransaction>(bytes) {
+            let known_outputs = signed
+                .expected_utxos
+                .iter()
+                .map(|eu| {
+                    (
+                        eu.addition_record,
+                        eu.utxo.get_native_currency_amount().to_string(),
+                    )
+                })
+                .collect();
+            return self
+                .decode_transfer(signed.transaction, known_outputs, "signed_transaction")
+                .await;
+        }
+        if let Some(transfer) = decode_exact::<TransferTransaction>(bytes) {
+            return self
+                .decode_transfer(transfer, HashMap::new(), "transfer_transaction")
+                .await;
+        }
+        if let Some(details) = decode_exact::<TransactionDetails>(bytes) {
+            return self.decode_details(details).await;
+        }
+        bail!("not a signed transaction, transfer transaction or transaction details")
+    }
+
+    async fn decode_transfer(
+        &self,
+        transfer: TransferTransaction,
+        mut known_outputs: HashMap<AdditionRecord, String>,
+        kind: &'static str,
+    ) -> Result<DecodedTransaction> {
+        let transaction = Transaction::try_from(transfer)
+            .map_err(|e| anyhow!("invalid transfer transaction: {:?}", e))?;
+
+        for key in self.get_known_scan_keys() {
+            for incoming in key.scan_for_announced_utxos(&transaction.kernel) {
+                known_outputs.insert(
+                    incoming.addition_record(),
+                    incoming.utxo.get_native_currency_amount().to_string(),
+                );
+            }
+        }
+        for expected in self.expected_utxos().await? {
+            known_outputs.insert(
+                expected.expected_utxo.addition_record,
+                expected
+                    .expected_utxo
+                    .utxo
+                    .get_native_currency_amount()
+                    .to_string(),
+            );
+        }
+
+        let outputs = transaction
+            .kernel
+            .outputs
+            .iter()
+            .map(|output| {
+                let amount = known_outputs.get(output).cloned();
+                DecodedOutput {
+                    commitment: output.canonical_commitment.to_hex(),
+                    owned: amount.is_some(),
+                    amount,
+                }
+            })
+            .collect();
+
+        let proof_type = match &transaction.proof {
+            TransactionProof::Witness(_) => "witness",
+            TransactionProof::SingleProof(_) => "single_proof",
+            TransactionProof::ProofCollection(_) => "proof_collection",
+        };
+        let proof_size = bincode::serialize(&transaction.proof)?.len();
+
+        let mut decoded = self
+            .decode_kernel(&transaction.kernel, outputs, kind)
+            .await?;
+        decoded.proof_type = Some(proof_type.to_string());
+        decoded.proof_size = Some(proof_size);
+        Ok(decoded)
+    }
+
+    async fn decode_details(&self, details: TransactionDetails) -> Result<DecodedTransaction> {
+        let kernel = PrimitiveWitness::from_transaction_details(&details).kernel;
+
+        let outputs = details
+            .tx_outputs
+            .iter()
+            .map(|output| DecodedOutput {
+                commitment: output.addition_record().canonical_commitment.to_hex(),
+                amount: Some(output.utxo().get_native_currency_amount().to_string()),
+                owned: self.can_unlock(&output.utxo()),
+            })
+            .collect();
+
+        self.decode_kernel(&kernel, outputs, "transaction_details")
+            .await
+    }
+
+    /// Decode the kernel, inputs are matched by their absolute index sets.
+    async fn decode_kernel(
+        &self,
+        kernel: &TransactionKernel,
+        outputs: Vec<DecodedOutput>,
+        kind: &'static str,
+    ) -> Result<DecodedTransaction> {
+        let utxos = self.get_utxos().await?;
+        let own_inputs = utxos
+            .iter()
+            .map(|utxo| (utxo.recovery_data.abs_i(), utxo))
+            .collect::<Vec<_>>();
+
+        let inputs = kernel
+            .inputs
+            .iter()
+            .map(|input| {
+                own_inputs
+                    .iter()
+                    .find(|(index_set, _)| *index_set == input.absolute_indices)
+                    .map(|(_, utxo)| *utxo)
+            })
+            .map(|utxo| match utxo {
+                Some(utxo) => DecodedInput {
+                    utxo_id: Some(utxo.id),
+                    amount: Some(
+                        utxo.recovery_data
+                            .utxo
+                            .get_native_currency_amount()
+                            .to_string(),
+                    ),
+                    owned: true,
+                },
+                None => DecodedInput {
+                    utxo_id: None,
+                    amount: None,
+                    owned: false,
+                },
+            })
+            .collect();
+
+        Ok(DecodedTransaction {
+            kind,
+            txid: kernel.txid().to_string(),
+            timestamp: kernel.timestamp.to_millis(),
+            fee: kernel.fee.to_string(),
+            mutator_set_hash: kernel.mutator_set_hash.to_hex(),
+            inputs,
+            outputs,
+            proof_type: None,
+            proof_size: None,
+        })
+    }
+}
@@ -5,15 +5,23 @@ use std::io::SeekFrom;
 use std::io::Write;
 use std::path::PathBuf;
 use std::range::Range;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering as AtomicOrdering;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
 
 use anyhow::bail;
 use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use neptune_privacy::api::export::Network;
+use neptune_privacy::api::export::Timestamp;
 use neptune_privacy::application::rest_server::ExportedBlock;
 use neptune_privacy::prelude::tasm_lib::prelude::Digest;
+use neptune_privacy::protocol::consensus::block::Block;
+use neptune_privacy::util_types::mutator_set::mutator_set_accumulator::MutatorSetAccumulator;
 use serde::Deserialize;
 use serde::Serialize;
 use tokio::fs::File;
@@ -25,12 +33,27 @@ use tracing::*;
 use crate::rpc_client;
 use crate::wallet::block_cache::BlockCache;
 use crate::wallet::block_cache::BlockCacheImpl;
+use crate::wallet::prefetch::Prefetcher;
+use crate::wallet::progress::SyncPhase;
+use crate::wallet::progress::SyncProgress;
+use crate::wallet::validate::validate_batch;
+use crate::wallet::validate::QuarantinedBlock;
+
+/// Number of quarantined blocks kept for inspection.
+const MAX_QUARANTINED_BLOCKS: usize = 20;
 
 #[derive(Clone)]
 pub struct FakeArchivalState {
-    block_cache: Arc<BlockCacheImpl>,
+    pub(super) block_cache: Arc<BlockCacheImpl>,
     snapshot_reader: Arc<Option<SnapshotReader>>,
     network: Network,
+    pub(super) prefetcher: Arc<Prefetcher>,
+    /// Snapshot read timings, shared by all wallets.
+    progress: Arc<SyncProgress>,
+    snapshot_reads: Arc<AtomicUsize>,
+    /// Highest block height the server is known to have, 0 if unknown.
+    server_tip: Arc<AtomicU64>,
+    quarantined: Arc<Mutex<Vec<QuarantinedBlock>>>,
 }
 
 impl FakeArchivalState {
@@ -43,37 +66,154 @@ impl FakeArchivalState {
             block_cache: Arc::new(cache),
             snapshot_reader: Arc::new(snapshot_reader),
             network,
+            prefetcher: Arc::new(Prefetcher::new()),
+            progress: Arc::new(SyncProgress::new()),
+            snapshot_reads: Arc::new(AtomicUsize::new(0)),
+            server_tip: Arc::new(AtomicU64::new(0)),
+            quarantined: Arc::new(Mutex::new(vec![])),
         }
     }
 
     pub async fn prepare(&self, height: u64, batch_size: u64) -> Result<()> {
+        self.fetch_batch(height, batch_size).await?;
+        Ok(())
+    }
+
+    /// Cache the blocks in `height..height + batch_size`, returns how many blocks exist.
+    pub(super) async fn fetch_batch(&self, height: u64, batch_size: u64) -> Result<usize> {
         if self.block_cache.is_persist() && self.block_cache.has_block_by_height(height).await? {
             debug!("Block {height} already in cache, skipping request");
-            return Ok(());
+            return Ok(batch_size as usize);
         }
 
         if let Some(blocks) = self.read_block_from_snapshot(height, batch_size).await {
+            self.validate_batch(height, &blocks)
+                .await
+                .context("invalid snapshot blocks")?;
             // add to temp cache as it is already in snapshot
+            let count = blocks.len();
             self.block_cache.add_blocks_temp(blocks.into_iter()).await?;
-            return Ok(());
+            return Ok(count);
         }
 
         let blocks = rpc_client::node_rpc_client()
             .request_block_by_height_range(height, batch_size)
             .await?;
+        self.validate_batch(height, &blocks)
+            .await
+            .context("invalid blocks from server")?;
+        let count = blocks.len();
+        if count > 0 {
+            self.observe_height(height + count as u64 - 1);
+        }
         self.block_cache.add_blocks(blocks.into_iter()).await?;
+        Ok(count)
+    }
+
+    /// Check a downloaded batch before it is cached, the failing block is quarantined.
+    async fn validate_batch(&self, height: u64, blocks: &[ExportedBlock]) -> Result<()> {
+        let Err(invalid) = validate_batch(height, blocks) else {
+            return Ok(());
+        };
+        let failed = blocks
+            .iter()
+            .find(|block| u64::from(block.kernel.header.height) == invalid.height())
+            .unwrap_or(&blocks[0]);
+        self.quarantine(failed, invalid.to_string()).await?;
+        Err(invalid.into())
+    }
+
+    /// Drop a block that failed validation and everything cached above it,
+    /// so it is downloaded again instead of being scanned.
+    ///
+    /// A block of a batch that was never cached only shows up in [Self::quarantined],
+    /// the failed batch restarts the prefetcher on its own.
+    pub async fn quarantine(&self, block: &ExportedBlock, reason: String) -> Result<()> {
+        let height: u64 = block.kernel.header.height.into();
+        error!(
+            "quarantine block {} {}: {}",
+            height,
+            block.hash().to_hex(),
+            reason
+        );
+
+        {
+            let mut quarantined = self.quarantined.lock().unwrap();
+            quarantined.push(QuarantinedBlock {
+                height,
+                digest: block.hash().to_hex(),
+                reason,
+                timestamp: Timestamp::now().to_millis(),
+            });
+            let len = quarantined.len();
+            if len > MAX_QUARANTINED_BLOCKS {
+                quarantined.drain(..len - MAX_QUARANTINED_BLOCKS);
+            }
+        }
+
+        if !self.block_cache.has_block_by_height(height).await? {
+            return Ok(());
+        }
+        self.reset_to_height(height.saturating_sub(1)).await
+    }
+
+    /// The most recently quarantined blocks, oldest first.
+    pub fn quarantined(&self) -> Vec<QuarantinedBlock> {
+        self.quarantined.lock().unwrap().clone()
+    }
+
+    /// Highest block height the server is known to have.
+    pub fn server_tip_height(&self) -> Option<u64> {
+        match self.server_tip.load(AtomicOrdering::Relaxed) {
+            0 => None,
+            height => Some(height),
+        }
+    }
+
+    /// Health check the rest servers and take the highest tip among them,
+    /// the tip may move back after a reorganization.
+    pub async fn refresh_server_tip(&self) -> Result<()> {
+        let tip = rpc_client::node_rpc_client()
+            .check_health()
+            .await
+            .context("no rest server reachable")?;
+        self.server_tip.store(tip, AtomicOrdering::Relaxed);
         Ok(())
     }
 
+    fn observe_height(&self, height: u64) {
+        self.server_tip.fetch_max(height, AtomicOrdering::Relaxed);
+    }
+
+    /// Whether a batch is being read from a snapshot right now.
+    pub fn is_reading_snapshot(&self) -> bool {
+        self.snapshot_reads.load(AtomicOrdering::Relaxed) > 0
+    }
+
+    /// Cumulative time spent reading snapshots.
+    pub fn snapshot_read_time(&self) -> u64 {
+        self.progress.timings().snapshot_read_ms
+    }
+
+    pub fn cancel_prefetch(&self) {
+        self.prefetcher.cancel();
+    }
+
     async fn read_block_from_snapshot(
         &self,
         height: u64,
         batch_size: u64,
     ) -> Option<Vec<ExportedBlock>> {
         if let Some(reader) = self.snapshot_reader.as_ref() {
-            return reader
+            self.snapshot_reads.fetch_add(1, AtomicOrdering::Relaxed);
+            let started = Instant::now();
+            let blocks = reader
                 .read_blocks(self.network, (height..height + batch_size).into())
                 .await;
+            self.progress
+                .add_time(SyncPhase::SnapshotRead, started.elapsed());
+            self.snapshot_reads.fetch_sub(1, AtomicOrdering::Relaxed);
+            return blocks;
         }
         None
     }
@@ -85,6 +225,9 @@ impl FakeArchivalState {
 
         debug!("get_block_by_height: requesting block {height} from rest server");
         let result = rpc_client::node_rpc_client().request_block(height).await?;
+        if result.is_some() {
+            self.observe_height(height);
+        }
         if self.block_cache.is_persist() {
             if let Some(block) = &result {
                 self.block_cache.add_block(block.clone()).await?;
@@ -94,6 +237,27 @@ impl FakeArchivalState {
         Ok(result)
     }
 
+    /// The mutator set accumulator before the block at `height` was applied.
+    pub async fn mutator_set_accumulator_before(
+        &self,
+        height: u64,
+    ) -> Result<MutatorSetAccumulator> {
+        Ok(match height {
+            0 => MutatorSetAccumulator::default(),
+            1 => Block::genesis(self.network).mutator_set_accumulator_after()?,
+            _ => {
+                let context = format!(
+                    "Prev block does not exist. Could not get block with height {}",
+                    height - 1
+                );
+                self.get_block_by_height(height - 1)
+                    .await?
+                    .context(context)?
+                    .mutator_set_accumulator_after()
+            }
+        })
+    }
+
     #[allow(dead_code)]
     pub async fn get_block_by_digest(&self, digest: Digest) -> Result<Option<ExportedBlock>> {
         if let Some(block) = self.block_cache.get_block_by_digest(digest).await? {
@@ -110,11 +274,23 @@ impl FakeArchivalState {
     }
 
     pub async fn reset_to_height(&self, height: u64) -> Result<()> {
+        self.prefetcher.cancel();
         self.block_cache
             .delete_block_by_start_height(height + 1)
             .await?;
         Ok(())
     }
+
+    /// Drop the cached blocks in `start..end` that a wallet found to be on an orphaned chain.
+    ///
+    /// Blocks outside the range and running downloads stay, other wallets keep using them.
+    pub async fn drop_orphaned_blocks(&self, start: u64, end: u64) -> Result<()> {
+        if start < end {
+            info!("drop orphaned blocks {}..{}", start, end);
+            self.block_cache.delete_blocks_between(start, end).await?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1,13 +1,71 @@
 use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use neptune_privacy::application::rest_server::ExportedBlock;
 use neptune_privacy::prelude::tasm_lib::prelude::Digest;
-use tracing::debug;
+use neptune_privacy::protocol::consensus::block::difficulty_control::ProofOfWork;
+use sqlx::Row;
+use sqlx::SqliteConnection;
+use tracing::*;
 
+use super::validate::BlockValidationError;
 use crate::rpc_client;
 
+/// Headers kept below the newest one, a deeper reorganization can not be followed.
+const MAX_REORG_DEPTH: u64 = 1000;
+
+/// A block of the locally validated chain, kept in `wallet_state_headers`.
+#[derive(Debug, Clone)]
+pub struct BlockHeaderEntry {
+    pub height: u64,
+    pub digest: Digest,
+    pub prev_digest: Digest,
+    pub cumulative_proof_of_work: ProofOfWork,
+}
+
+impl BlockHeaderEntry {
+    /// The cumulative work extends the one of `parent`, the local header of the previous block,
+    /// by the difficulty of `block`. Without a parent it starts from the work the header
+    /// claims for the blocks before it.
+    pub fn from_block(block: &ExportedBlock, parent: Option<&BlockHeaderEntry>) -> Self {
+        let header = &block.kernel.header;
+        let cumulative_proof_of_work = match parent {
+            Some(parent) if parent.digest == header.prev_block_digest => {
+                parent.cumulative_proof_of_work + header.difficulty
+            }
+            _ => header.cumulative_proof_of_work + header.difficulty,
+        };
+
+        Self {
+            height: header.height.into(),
+            digest: block.hash(),
+            prev_digest: header.prev_block_digest,
+            cumulative_proof_of_work,
+        }
+    }
+
+    fn from_row(row: sqlx::sqlite::SqliteRow) -> Result<Self> {
+        Ok(Self {
+            height: row.get::<i64, _>("height") as u64,
+            digest: Digest::try_from_hex(&row.get::<String, _>("digest"))?,
+            prev_digest: Digest::try_from_hex(&row.get::<String, _>("prev_digest"))?,
+            cumulative_proof_of_work: serde_json::from_str(
+                &row.get::<String, _>("cumulative_pow"),
+            )?,
+        })
+    }
+}
+
 impl super::WalletState {
+    /// Check whether `block` extends the wallet tip.
+    ///
+    /// If it does not, the new branch is walked back by digest until it meets the local
+    /// header chain, returns the common ancestor. Every fetched block must hash to the digest
+    /// its child commits to, so the server can not make up the branch. The work of the branch
+    /// is the work of the common ancestor plus the difficulties of the branch blocks, a branch
+    /// without more work than the local tip is rejected.
     pub async fn check_fork(&self, block: &ExportedBlock) -> Result<Option<(u64, Digest)>> {
         if block.kernel.header.height.value() <= 1 {
             return Ok(None);
@@ -20,37 +78,167 @@ impl super::WalletState {
             block.kernel.header.prev_block_digest.to_hex()
         );
 
-        if let Some((prev_height, prev_digest)) = self.get_tip().await.context("get tip")? {
-            debug!("prev digest: {} {:?}", prev_height, prev_digest.to_hex());
-            //prev is forked
-            if block.kernel.header.prev_block_digest != prev_digest {
-                let mut prev_digest = block.kernel.header.prev_block_digest;
-                loop {
-                    let prev = rpc_client::node_rpc_client()
-                        .get_block_info(&prev_digest.to_hex())
-                        .await
-                        .context("try get_prev_block_info")?;
-
-                    match prev {
-                        Some(prev) => {
-                            if prev.is_canonical {
-                                let blk_before_fork = rpc_client::node_rpc_client()
-                                    .get_block_info(&prev.prev_block_digest.to_hex())
-                                    .await?
-                                    .context("try get_block_info before fork")?;
-                                return Ok(Some((
-                                    blk_before_fork.height.into(),
-                                    blk_before_fork.digest,
-                                )));
-                            } else {
-                                prev_digest = prev.prev_block_digest;
-                            }
-                        }
-                        None => return Err(anyhow!("Block not found")),
+        let Some((tip_height, tip_digest)) = self.get_tip().await.context("get tip")? else {
+            return Ok(None);
+        };
+        debug!("prev digest: {} {:?}", tip_height, tip_digest.to_hex());
+        if block.kernel.header.prev_block_digest == tip_digest {
+            return Ok(None);
+        }
+
+        let Some(local_tip) = self.get_header(tip_height).await? else {
+            // synced before the header chain was kept
+            warn!("no local header at tip {}, trusting the server", tip_height);
+            return self.check_fork_by_server(block).await;
+        };
+
+        let oldest = self
+            .oldest_header_height()
+            .await?
+            .context("local header chain is empty")?;
+
+        let mut child = BlockHeaderEntry::from_block(block, None);
+        let mut difficulties = vec![block.kernel.header.difficulty];
+        loop {
+            let height = child.height - 1;
+            let digest = child.prev_digest;
+
+            if let Some(local) = self.get_header(height).await? {
+                if local.digest == digest {
+                    let branch_work = difficulties
+                        .into_iter()
+                        .fold(local.cumulative_proof_of_work, |work, difficulty| {
+                            work + difficulty
+                        });
+                    if branch_work <= local_tip.cumulative_proof_of_work {
+                        warn!(
+                            "reorganization rejected, local tip {} has at least as much work",
+                            local_tip.digest.to_hex()
+                        );
+                        return Err(BlockValidationError::InsufficientWork {
+                            height: block.kernel.header.height.into(),
+                            digest: block.hash().to_hex(),
+                        })?;
+                    }
+
+                    info!("common ancestor at {} {}", height, digest.to_hex());
+                    return Ok(Some((height, digest)));
+                }
+            }
+            if height <= oldest {
+                bail!(
+                    "no common ancestor with the new branch above height {}",
+                    oldest
+                );
+            }
+
+            let prev = rpc_client::node_rpc_client()
+                .request_block_by_digest(&digest.to_hex())
+                .await
+                .context("request block of the new branch")?
+                .ok_or_else(|| anyhow!("block {} not found", digest.to_hex()))?;
+            difficulties.push(prev.kernel.header.difficulty);
+            let prev = BlockHeaderEntry::from_block(&prev, None);
+            ensure!(
+                prev.digest == digest && prev.height == height,
+                "server returned block {} at {} for {} at {}",
+                prev.digest.to_hex(),
+                prev.height,
+                digest.to_hex(),
+                height
+            );
+            child = prev;
+        }
+    }
+
+    /// Legacy fork detection following the server's canonical flags.
+    async fn check_fork_by_server(&self, block: &ExportedBlock) -> Result<Option<(u64, Digest)>> {
+        let mut prev_digest = block.kernel.header.prev_block_digest;
+        loop {
+            let prev = rpc_client::node_rpc_client()
+                .get_block_info(&prev_digest.to_hex())
+                .await
+                .context("try get_prev_block_info")?;
+
+            match prev {
+                Some(prev) => {
+                    if prev.is_canonical {
+                        let blk_before_fork = rpc_client::node_rpc_client()
+                            .get_block_info(&prev.prev_block_digest.to_hex())
+                            .await?
+                            .context("try get_block_info before fork")?;
+                        return Ok(Some((
+                            blk_before_fork.height.into(),
+                            blk_before_fork.digest,
+                        )));
+                    } else {
+                        prev_digest = prev.prev_block_digest;
                     }
                 }
+                None => return Err(anyhow!("Block not found")),
             }
         }
-        return Ok(None);
+    }
+
+    /// Append the header of the new tip and drop the ones more than [MAX_REORG_DEPTH] below.
+    pub async fn append_header(
+        &self,
+        tx: &mut SqliteConnection,
+        block: &ExportedBlock,
+    ) -> Result<()> {
+        let parent = match block.kernel.header.height.value().checked_sub(1) {
+            Some(height) => sqlx::query("SELECT * FROM wallet_state_headers WHERE height = ?")
+                .bind(height as i64)
+                .fetch_optional(&mut *tx)
+                .await?
+                .map(BlockHeaderEntry::from_row)
+                .transpose()?,
+            None => None,
+        };
+        let header = BlockHeaderEntry::from_block(block, parent.as_ref());
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO wallet_state_headers (height, digest, prev_digest, cumulative_pow) VALUES (?, ?, ?, ?)",
+        )
+        .bind(header.height as i64)
+        .bind(header.digest.to_hex())
+        .bind(header.prev_digest.to_hex())
+        .bind(serde_json::to_string(&header.cumulative_proof_of_work)?)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM wallet_state_headers WHERE height < ?")
+            .bind(header.height.saturating_sub(MAX_REORG_DEPTH) as i64)
+            .execute(&mut *tx)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_header(&self, height: u64) -> Result<Option<BlockHeaderEntry>> {
+        sqlx::query("SELECT * FROM wallet_state_headers WHERE height = ?")
+            .bind(height as i64)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(BlockHeaderEntry::from_row)
+            .transpose()
+    }
+
+    async fn oldest_header_height(&self) -> Result<Option<u64>> {
+        let row = sqlx::query("SELECT MIN(height) FROM wallet_state_headers")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.get::<Option<i64>, _>(0).map(|h| h as u64))
+    }
+
+    pub(super) async fn delete_headers_above(
+        &self,
+        tx: &mut SqliteConnection,
+        height: u64,
+    ) -> Result<()> {
+        sqlx::query("DELETE FROM wallet_state_headers WHERE height > ?")
+            .bind(height as i64)
+            .execute(&mut *tx)
+            .await?;
+        Ok(())
     }
 }
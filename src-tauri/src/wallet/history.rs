@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use neptune_privacy::api::export::Timestamp;
+use neptune_privacy::api::export::TransactionDetails;
+use neptune_privacy::protocol::consensus::type_scripts::native_currency_amount::NativeCurrencyAmount;
+use serde::Serialize;
+
+use super::labels::LabelType;
+use super::payments::SentPayment;
+use super::wallet_state_table::UtxoBlockInfo;
+
+/// One wallet transaction, every UTXO it received and spent taken together.
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionHistory {
+    /// None for UTXOs whose transaction is unknown, they are grouped per block
+    pub txid: Option<String>,
+    pub height: u64,
+    pub timestamp: Timestamp,
+    pub confirmations: u64,
+    pub received: String,
+    pub spent: String,
+    /// Net change of the wallet balance, received minus spent
+    pub delta: String,
+    /// Only known for transactions sent by this wallet
+    pub fee: Option<String>,
+    /// Outputs to other wallets, only known for transactions sent by this wallet
+    pub counterparty_outputs: Option<usize>,
+    /// Recipients of a transaction sent by this wallet
+    pub payments: Vec<SentPayment>,
+    pub received_utxos: Vec<i64>,
+    pub spent_utxos: Vec<i64>,
+    /// Latest release date of the time-locked UTXOs received
+    pub release_date: Option<Timestamp>,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TxHistorySort {
+    #[default]
+    Time,
+    /// By the absolute net amount
+    Amount,
+}
+
+impl TxHistorySort {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "time" => Some(TxHistorySort::Time),
+            "amount" => Some(TxHistorySort::Amount),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TxHistoryFilter {
+    pub from: Option<Timestamp>,
+    pub to: Option<Timestamp>,
+    /// Bounds on the absolute net amount
+    pub min_amount: Option<NativeCurrencyAmount>,
+    pub max_amount: Option<NativeCurrencyAmount>,
+    pub sort: TxHistorySort,
+    pub ascending: bool,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionHistoryPage {
+    /// Matching transactions before pagination
+    pub total: usize,
+    pub items: Vec<TransactionHistory>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum HistoryKey {
+    Tx(String),
+    Block(u64),
+}
+
+struct HistoryEntry {
+    height: u64,
+    timestamp: Timestamp,
+    received: i128,
+    spent: i128,
+    received_utxos: Vec<i64>,
+    spent_utxos: Vec<i64>,
+    release_date: Option<Timestamp>,
+    output_label: Option<String>,
+}
+
+impl HistoryEntry {
+    fn new(block: &UtxoBlockInfo) -> Self {
+        Self {
+            height: block.block_height,
+            timestamp: block.timestamp,
+            received: 0,
+            spent: 0,
+            received_utxos: vec![],
+            spent_utxos: vec![],
+            release_date: None,
+            output_label: None,
+        }
+    }
+}
+
+impl super::WalletState {
+    /// Wallet history grouped by transaction, see [TransactionHistory].
+    pub async fn get_transaction_history(
+        &self,
+        filter: TxHistoryFilter,
+    ) -> Result<TransactionHistoryPage> {
+        let utxos = self.get_utxos().await?;
+        let tip_height = self.get_tip().await?.map(|(height, _)| height).unwrap_or(0);
+        let tx_labels = self.get_labels(LabelType::Tx).await?;
+        let output_labels = self.get_labels(LabelType::Output).await?;
+
+        let mut entries: BTreeMap<HistoryKey, HistoryEntry> = BTreeMap::new();
+        for utxo in utxos {
+            let amount = utxo
+                .recovery_data
+                .utxo
+                .get_native_currency_amount()
+                .to_nau();
+
+            let block = &utxo.confirmed_in_block;
+            let key = match &utxo.confirmed_txid {
+                Some(txid) => HistoryKey::Tx(txid.clone()),
+                None => HistoryKey::Block(block.block_height),
+            };
+            let entry = entries
+                .entry(key)
+                .or_insert_with(|| HistoryEntry::new(block));
+            entry.received += amount;
+            entry.received_utxos.push(utxo.id);
+            if let Some(release_date) = utxo.recovery_data.utxo.release_date() {
+                if entry.release_date.is_none_or(|date| date < release_date) {
+                    entry.release_date = Some(release_date);
+                }
+            }
+            if entry.output_label.is_none() {
+                entry.output_label = output_labels.get(&utxo.hash).cloned();
+            }
+
+            if let Some(block) = &utxo.spent_in_block {
+                let key = match &utxo.spent_txid {
+                    Some(txid) => HistoryKey::Tx(txid.clone()),
+                    None => HistoryKey::Block(block.block_height),
+                };
+                let entry = entries
+                    .entry(key)
+                    .or_insert_with(|| HistoryEntry::new(block));
+                entry.spent += amount;
+                entry.spent_utxos.push(utxo.id);
+            }
+        }
+
+        let mut items = Vec::with_capacity(entries.len());
+        for (key, entry) in entries {
+            let delta = entry.received - entry.spent;
+            let timestamp = entry.timestamp;
+
+            if filter.from.is_some_and(|from| timestamp < from)
+                || filter.to.is_some_and(|to| timestamp > to)
+                || filter
+                    .min_amount
+                    .is_some_and(|min| delta.abs() < min.to_nau())
+                || filter
+                    .max_amount
+                    .is_some_and(|max| delta.abs() > max.to_nau())
+            {
+                continue;
+            }
+
+            let txid = match key {
+                HistoryKey::Tx(txid) => Some(txid),
+                HistoryKey::Block(_) => None,
+            };
+            let label = txid
+                .as_ref()
+                .and_then(|txid| tx_labels.get(txid).cloned())
+                .or(entry.output_label);
+
+            items.push((
+                delta.abs(),
+                TransactionHistory {
+                    txid,
+                    height: entry.height,
+                    timestamp,
+                    confirmations: (tip_height + 1).saturating_sub(entry.height),
+                    received: NativeCurrencyAmount::from_nau(entry.received).display_lossless(),
+                    spent: NativeCurrencyAmount::from_nau(entry.spent).display_lossless(),
+                    delta: NativeCurrencyAmount::from_nau(delta).display_lossless(),
+                    fee: None,
+                    counterparty_outputs: None,
+                    payments: vec![],
+                    received_utxos: entry.received_utxos,
+                    spent_utxos: entry.spent_utxos,
+                    release_date: entry.release_date,
+                    label,
+                },
+            ));
+        }
+
+        match filter.sort {
+            TxHistorySort::Time => {
+                items.sort_by_key(|(_, item)| (item.height, item.timestamp.to_millis()))
+            }
+            TxHistorySort::Amount => items.sort_by_key(|(amount, _)| *amount),
+        }
+        if !filter.ascending {
+            items.reverse();
+        }
+
+        let total = items.len();
+        let mut items = items
+            .into_iter()
+            .skip(filter.offset)
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .map(|(_, item)| item)
+            .collect::<Vec<_>>();
+
+        // only what we spent can be ours, looked up for the returned page only
+        for item in items.iter_mut() {
+            if let (Some(txid), false) = (&item.txid, item.spent_utxos.is_empty()) {
+                let (fee, counterparty_outputs) = self
+                    .sent_fee_and_outputs(txid, item.received_utxos.len())
+                    .await?;
+                item.fee = fee.map(|fee| fee.display_lossless());
+                item.counterparty_outputs = counterparty_outputs;
+                item.payments = self.sent_payments(Some(txid)).await?;
+            }
+        }
+
+        Ok(TransactionHistoryPage { total, items })
+    }
+
+    /// Fee and counterparty output count of a transaction we sent, from the stored
+    /// [TransactionDetails] or the saved proven transaction.
+    async fn sent_fee_and_outputs(
+        &self,
+        txid: &str,
+        own_outputs: usize,
+    ) -> Result<(Option<NativeCurrencyAmount>, Option<usize>)> {
+        if let Some(details) = self.updater.get_details(txid).await? {
+            let details: TransactionDetails = bincode::deserialize(&details)?;
+            let counterparty = details
+                .tx_outputs
+                .iter()
+                .filter(|output| !self.can_unlock(&output.utxo()))
+                .count();
+            return Ok((Some(details.fee), Some(counterparty)));
+        }
+
+        if let Some((signed, _)) = self.get_saved_transaction(txid).await? {
+            let kernel = &signed.transaction.kernel;
+            return Ok((
+                Some(kernel.fee),
+                Some(kernel.outputs.len().saturating_sub(own_outputs)),
+            ));
+        }
+
+        Ok((None, None))
+    }
+}
@@ -10,9 +10,10 @@ use neptune_privacy::api::export::Timestamp;
 use neptune_privacy::api::export::Tip5;
 use neptune_privacy::api::export::Utxo;
 use neptune_privacy::state::wallet::unlocked_utxo::UnlockedUtxo;
+use neptune_privacy::util_types::mutator_set::ms_membership_proof::MsMembershipProof;
 use neptune_privacy::util_types::mutator_set::mutator_set_accumulator::MutatorSetAccumulator;
-use neptune_privacy::util_types::mutator_set::removal_record::absolute_index_set::AbsoluteIndexSet;
 use rand::seq::SliceRandom;
+use tracing::debug;
 use tracing::trace;
 
 use super::wallet_state_table::UtxoDbData;
@@ -73,7 +74,7 @@ impl InputSelectionRule {
 impl super::WalletState {
     pub async fn create_input(
         &self,
-        outputs: &[(ReceivingAddress, NativeCurrencyAmount)],
+        outputs: &[(ReceivingAddress, NativeCurrencyAmount, Option<Timestamp>)],
         fee: NativeCurrencyAmount,
         rule: InputSelectionRule,
         must_include_inputs: Vec<i64>,
@@ -83,26 +84,47 @@ impl super::WalletState {
         MutatorSetAccumulator,
         BlockHeight,
     )> {
-        let mut utxos = self.get_unspent_utxos().await?;
-        trace!("Num unspent utxos (not mined): {}", utxos.len());
+        let (inputs, db_idxs) = self
+            .select_inputs(outputs, fee, rule, must_include_inputs)
+            .await?;
 
-        let pending_utxos = self.updater.get_pending_spent_utxos().await?;
-        utxos.retain(|utxo| !pending_utxos.contains(&utxo.id));
-        trace!(
-            "Num unspent utxos (not mined and not in mempool): {}",
-            utxos.len()
+        trace!("Selected a total of {} inputs", inputs.len());
+        let (inputs, tip_msa, tip_height) = self.unlock_utxos(inputs).await?;
+        trace!("Managed to unlock {} inputs", inputs.len());
+
+        trace!("Inputs length is: {}", inputs.len());
+        trace!("db_idxs.len() = {}", db_idxs.len());
+        ensure!(
+            inputs.len() == db_idxs.len(),
+            "Inputs and db_idxs must have the same length"
         );
 
+        Ok((inputs, db_idxs, tip_msa, tip_height))
+    }
+
+    /// Choose the UTXOs to spend, does not need the spending keys.
+    ///
+    /// Returns the recovery data of the inputs and their database ids.
+    pub async fn select_inputs(
+        &self,
+        outputs: &[(ReceivingAddress, NativeCurrencyAmount, Option<Timestamp>)],
+        fee: NativeCurrencyAmount,
+        rule: InputSelectionRule,
+        must_include_inputs: Vec<i64>,
+    ) -> anyhow::Result<(Vec<UtxoRecoveryData>, Vec<i64>)> {
+        let utxos = self.get_unreserved_utxos().await?;
+
         let utxos = rule.apply(utxos);
         let unspent: Vec<_> = utxos
             .into_iter()
+            .filter(|utxo| !utxo.frozen)
             .filter(|utxo| !must_include_inputs.contains(&utxo.id))
             .collect();
         trace!("Choosing inputs from {} UTXOs", unspent.len());
 
         let total_amount = outputs
             .iter()
-            .map(|(_, amount)| amount.to_nau())
+            .map(|(_, amount, _)| amount.to_nau())
             .sum::<i128>()
             + fee.to_nau();
         trace!(
@@ -144,18 +166,72 @@ impl super::WalletState {
             db_idxs.push(utxo.id);
         }
 
-        trace!("Selected a total of {} inputs", inputs.len());
-        let (inputs, tip_msa, tip_height) = self.unlock_utxos(inputs).await?;
-        trace!("Managed to unlock {} inputs", inputs.len());
+        Ok((inputs, db_idxs))
+    }
 
-        trace!("Inputs length is: {}", inputs.len());
-        trace!("db_idxs.len() = {}", db_idxs.len());
-        ensure!(
-            inputs.len() == db_idxs.len(),
-            "Inputs and db_idxs must have the same length"
+    /// Choose every spendable UTXO, or only the `ids` among them, to be spent without change.
+    ///
+    /// Time-locked UTXOs are skipped, asking for one of them explicitly is an error. Frozen
+    /// UTXOs are only swept when asked for.
+    pub async fn select_sweep_inputs(
+        &self,
+        ids: Vec<i64>,
+    ) -> anyhow::Result<(Vec<UtxoRecoveryData>, Vec<i64>)> {
+        let now = Timestamp::now();
+        let mut utxos = self.get_unreserved_utxos().await?;
+
+        if !ids.is_empty() {
+            for id in &ids {
+                let utxo = utxos
+                    .iter()
+                    .find(|utxo| utxo.id == *id)
+                    .with_context(|| format!("utxo {} is spent or pending", id))?;
+                if let Some(release) = utxo.recovery_data.utxo.release_date() {
+                    ensure!(release <= now, "utxo {} is locked until {}", id, release);
+                }
+            }
+            utxos.retain(|utxo| ids.contains(&utxo.id));
+        } else {
+            utxos.retain(|utxo| !utxo.frozen);
+        }
+
+        let (inputs, db_idxs): (Vec<_>, Vec<_>) = utxos
+            .into_iter()
+            .filter(|utxo| match utxo.recovery_data.utxo.release_date() {
+                Some(release) if release > now => {
+                    debug!("skipping utxo {} locked until {}", utxo.id, release);
+                    false
+                }
+                _ => true,
+            })
+            .map(|utxo| (utxo.recovery_data, utxo.id))
+            .unzip();
+        ensure!(!inputs.is_empty(), "no spendable utxos");
+        trace!("Sweeping {} inputs", inputs.len());
+
+        Ok((inputs, db_idxs))
+    }
+
+    /// Ids of the UTXOs spent by pending transactions or by saved ones not broadcast yet.
+    pub async fn get_reserved_utxo_ids(&self) -> anyhow::Result<Vec<i64>> {
+        let mut reserved = self.updater.get_pending_spent_utxos().await?;
+        reserved.extend(self.get_saved_spent_utxos().await?);
+        Ok(reserved)
+    }
+
+    /// Unspent UTXOs that no pending or saved transaction spends.
+    pub(super) async fn get_unreserved_utxos(&self) -> anyhow::Result<Vec<UtxoDbData>> {
+        let mut utxos = self.get_unspent_utxos().await?;
+        trace!("Num unspent utxos (not mined): {}", utxos.len());
+
+        let reserved = self.get_reserved_utxo_ids().await?;
+        utxos.retain(|utxo| !reserved.contains(&utxo.id));
+        trace!(
+            "Num unspent utxos (not mined and not in mempool): {}",
+            utxos.len()
         );
 
-        Ok((inputs, db_idxs, tip_msa, tip_height))
+        Ok(utxos)
     }
 
     /// Returns triple (list of unlocked UTXOs, tip mutator set, tip height)
@@ -163,19 +239,21 @@ impl super::WalletState {
         &self,
         utxos: Vec<UtxoRecoveryData>,
     ) -> anyhow::Result<(Vec<UnlockedUtxo>, MutatorSetAccumulator, BlockHeight)> {
-        let mut index_sets = Vec::with_capacity(utxos.len());
+        let (membership_proofs, tip_msa, tip_height) =
+            self.restore_membership_proofs(&utxos).await?;
+        let unlocked = self.unlock_with_proofs(utxos, membership_proofs)?;
 
-        for utxo in &utxos {
-            let item = Tip5::hash(&utxo.utxo);
-            let index_set = AbsoluteIndexSet::compute(
-                item,
-                utxo.sender_randomness,
-                utxo.receiver_preimage,
-                utxo.aocl_index,
-            );
+        Ok((unlocked, tip_msa, tip_height))
+    }
 
-            index_sets.push(index_set);
-        }
+    /// Ask the server for the mutator set membership proofs of `utxos` at its tip.
+    ///
+    /// Returns triple (membership proofs, tip mutator set, tip height)
+    pub async fn restore_membership_proofs(
+        &self,
+        utxos: &[UtxoRecoveryData],
+    ) -> anyhow::Result<(Vec<MsMembershipProof>, MutatorSetAccumulator, BlockHeight)> {
+        let index_sets = utxos.iter().map(|utxo| utxo.abs_i()).collect::<Vec<_>>();
 
         trace!("Requesting {} ms membership proofs", index_sets.len());
         let msmps_recovery_data = rpc_client::node_rpc_client()
@@ -185,13 +263,15 @@ impl super::WalletState {
             "Received {} ms membership proofs",
             msmps_recovery_data.membership_proofs.len()
         );
+        ensure!(
+            msmps_recovery_data.membership_proofs.len() == utxos.len(),
+            "Server returned {} membership proofs for {} inputs",
+            msmps_recovery_data.membership_proofs.len(),
+            utxos.len()
+        );
 
-        let mut unlocked = Vec::with_capacity(utxos.len());
+        let mut membership_proofs = Vec::with_capacity(utxos.len());
         for (recovery_data, utxo) in msmps_recovery_data.membership_proofs.into_iter().zip(utxos) {
-            let spending_key = self
-                .find_spending_key_for_utxo(&utxo.utxo)
-                .context("No spending key found for utxo")?;
-
             let membership_proof = match recovery_data.extract_ms_membership_proof(
                 utxo.aocl_index,
                 utxo.sender_randomness,
@@ -203,6 +283,27 @@ impl super::WalletState {
                     err.to_string()
                 ),
             };
+            membership_proofs.push(membership_proof);
+        }
+
+        Ok((
+            membership_proofs,
+            msmps_recovery_data.tip_mutator_set,
+            msmps_recovery_data.tip_height,
+        ))
+    }
+
+    /// Attach the lock script witnesses of the wallet keys, fails if a UTXO is not ours.
+    pub fn unlock_with_proofs(
+        &self,
+        utxos: Vec<UtxoRecoveryData>,
+        membership_proofs: Vec<MsMembershipProof>,
+    ) -> anyhow::Result<Vec<UnlockedUtxo>> {
+        let mut unlocked = Vec::with_capacity(utxos.len());
+        for (utxo, membership_proof) in utxos.into_iter().zip(membership_proofs) {
+            let spending_key = self
+                .find_spending_key_for_utxo(&utxo.utxo)
+                .context("No spending key found for utxo")?;
 
             unlocked.push(UnlockedUtxo::unlock(
                 utxo.utxo,
@@ -211,11 +312,7 @@ impl super::WalletState {
             ));
         }
 
-        Ok((
-            unlocked,
-            msmps_recovery_data.tip_mutator_set,
-            msmps_recovery_data.tip_height,
-        ))
+        Ok(unlocked)
     }
 
     // returns Some(SpendingKey) if the utxo can be unlocked by one of the known
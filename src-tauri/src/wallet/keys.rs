@@ -1,41 +1,90 @@
-use std::ops::Deref;
 use std::range::Range;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
+use anyhow::Context;
 use anyhow::Result;
 use neptune_privacy::api::export::SpendingKey;
+use neptune_privacy::api::export::Utxo;
 use rayon::prelude::*;
 
+use super::airgap::KeyIndex;
+use crate::config::view_key::ScanKey;
+
 impl super::WalletState {
     pub async fn get_address(&self, index: u64) -> Result<String> {
-        let symmetric_key = self.key.nth_generation_spending_key(index);
-        let spending_key = SpendingKey::from(symmetric_key);
+        let address = self
+            .key
+            .nth_receiving_address(index)
+            .with_context(|| format!("watch-only wallet has no address {}", index))?;
 
-        spending_key.to_address().to_bech32m(self.network)
+        address.to_bech32m(self.network)
     }
 
+    /// Spending keys of the used indices, empty for watch-only wallets.
     pub fn get_known_spending_keys(&self) -> Vec<SpendingKey> {
-        let spending_keys = self.get_future_generation_spending_keys(Range {
+        let key = &self.key;
+        let spending_keys = (0..self.num_generation_spending_keys() + 1)
+            .into_par_iter()
+            .filter_map(|i| key.nth_generation_spending_key(i));
+        let symmetric_keys = (0..self.num_symmetric_keys() + 1)
+            .into_par_iter()
+            .filter_map(|i| key.nth_symmetric_key(i));
+
+        spending_keys.chain(symmetric_keys).collect()
+    }
+
+    /// Scan keys of the used indices.
+    pub fn get_known_scan_keys(&self) -> Vec<Arc<ScanKey>> {
+        let generation_keys = self.get_generation_scan_keys(Range {
             start: 0,
             end: self.num_generation_spending_keys() + 1,
         });
-        let spending_keys = spending_keys.iter().map(|v| v.1.deref().clone());
-
-        let symmetric_keys = self.get_future_symmetric_keys(Range {
+        let symmetric_keys = self.get_symmetric_scan_keys(Range {
             start: 0,
             end: self.num_symmetric_keys() + 1,
         });
-        let symmetric_keys = symmetric_keys.iter().map(|v| v.1.deref().clone());
-
-        // let raw_hash_keys = self.get_known_raw_hash_keys();
 
-        spending_keys
+        generation_keys
+            .into_iter()
             .chain(symmetric_keys)
-            // .chain(raw_hash_keys)
+            .map(|(_, key)| key)
             .collect()
     }
 
+    /// Derivation index of the key in the window that a UTXO is locked to.
+    pub fn find_key_index(&self, utxo: &Utxo) -> Option<KeyIndex> {
+        let (generation_end, symmetric_end) = self.key_window();
+        let lock_script_hash = utxo.lock_script_hash();
+
+        let generation = self
+            .get_generation_scan_keys(Range {
+                start: 0,
+                end: generation_end,
+            })
+            .into_iter()
+            .find(|(_, key)| key.lock_script_hash() == lock_script_hash)
+            .map(|(i, _)| KeyIndex::Generation(i));
+
+        generation.or_else(|| {
+            self.get_symmetric_scan_keys(Range {
+                start: 0,
+                end: symmetric_end,
+            })
+            .into_iter()
+            .find(|(_, key)| key.lock_script_hash() == lock_script_hash)
+            .map(|(i, _)| KeyIndex::Symmetric(i))
+        })
+    }
+
+    /// Spending key at a derivation index, none for watch-only wallets.
+    pub fn spending_key_at(&self, index: KeyIndex) -> Option<SpendingKey> {
+        match index {
+            KeyIndex::Generation(i) => self.key.nth_generation_spending_key(i),
+            KeyIndex::Symmetric(i) => self.key.nth_symmetric_key(i),
+        }
+    }
+
     pub fn num_symmetric_keys(&self) -> u64 {
         self.num_symmetric_keys.load(Ordering::Relaxed)
     }
@@ -48,36 +97,34 @@ impl super::WalletState {
         self.num_future_keys.load(Ordering::Relaxed)
     }
 
-    pub fn get_future_symmetric_keys(&self, range: Range<u64>) -> Vec<(u64, Arc<SpendingKey>)> {
+    /// Symmetric scan keys in `range`, a watch-only wallet has none past its exported keys.
+    pub fn get_symmetric_scan_keys(&self, range: Range<u64>) -> Vec<(u64, Arc<ScanKey>)> {
         let key = &self.key;
         (range.start..range.end)
             .into_par_iter()
-            .map(|i| {
+            .filter_map(|i| {
                 if let Some(key) = self.key_cache.get_symmetric_key(i) {
-                    return (i, key);
+                    return Some((i, key));
                 }
-                let new_key = Arc::new(SpendingKey::from(key.nth_symmetric_key(i)));
+                let new_key = Arc::new(key.nth_symmetric_scan_key(i)?);
                 self.key_cache.add_symmetric_key(i, new_key.clone());
-                (i, new_key)
+                Some((i, new_key))
             })
             .collect()
     }
 
-    pub fn get_future_generation_spending_keys(
-        &self,
-        range: Range<u64>,
-    ) -> Vec<(u64, Arc<SpendingKey>)> {
+    /// Generation scan keys in `range`, a watch-only wallet has none past its exported keys.
+    pub fn get_generation_scan_keys(&self, range: Range<u64>) -> Vec<(u64, Arc<ScanKey>)> {
         let key = &self.key;
         (range.start..range.end)
             .into_par_iter()
-            .map(|i| {
-                if let Some(key) = self.key_cache.get_generation_spending_key(i) {
-                    return (i, key);
+            .filter_map(|i| {
+                if let Some(key) = self.key_cache.get_generation_key(i) {
+                    return Some((i, key));
                 }
-                let new_key = Arc::new(SpendingKey::from(key.nth_generation_spending_key(i)));
-                self.key_cache
-                    .add_generation_spending_key(i, new_key.clone());
-                (i, new_key)
+                let new_key = Arc::new(key.nth_generation_scan_key(i)?);
+                self.key_cache.add_generation_key(i, new_key.clone());
+                Some((i, new_key))
             })
             .collect()
     }
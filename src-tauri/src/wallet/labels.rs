@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use neptune_privacy::state::wallet::address::ReceivingAddress;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::SqliteConnection;
+use tracing::*;
+
+/// What a label is attached to, named as in BIP-329.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelType {
+    /// A transaction, by txid
+    Tx,
+    /// A receiving address, by bech32m
+    Addr,
+    /// A UTXO, by its hash
+    Output,
+}
+
+impl LabelType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LabelType::Tx => "tx",
+            LabelType::Addr => "addr",
+            LabelType::Output => "output",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "tx" => Some(LabelType::Tx),
+            "addr" => Some(LabelType::Addr),
+            "output" => Some(LabelType::Output),
+            _ => None,
+        }
+    }
+}
+
+/// One line of a BIP-329-like JSONL label export.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Label {
+    #[serde(rename = "type")]
+    pub label_type: LabelType,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    /// `None` removes the label when setting it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Outputs only, false for frozen UTXOs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spendable: Option<bool>,
+}
+
+impl super::WalletState {
+    /// Set or clear (`None` or empty) a label, addresses must belong to the wallet network.
+    pub async fn set_label(
+        &self,
+        label_type: LabelType,
+        reference: &str,
+        label: Option<String>,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        self.write_label(&mut conn, label_type, reference, label)
+            .await
+    }
+
+    async fn write_label(
+        &self,
+        tx: &mut SqliteConnection,
+        label_type: LabelType,
+        reference: &str,
+        label: Option<String>,
+    ) -> Result<()> {
+        let reference = reference.trim();
+        if reference.is_empty() {
+            bail!("empty {} reference", label_type.as_str());
+        }
+        if label_type == LabelType::Addr {
+            ReceivingAddress::from_bech32m(reference, self.network)
+                .with_context(|| format!("invalid address for {}", self.network))?;
+        }
+
+        match label.filter(|label| !label.trim().is_empty()) {
+            Some(label) => {
+                sqlx::query(
+                    "INSERT OR REPLACE INTO wallet_state_labels (type, ref, label) VALUES (?, ?, ?)",
+                )
+                .bind(label_type.as_str())
+                .bind(reference)
+                .bind(label)
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM wallet_state_labels WHERE type = ? AND ref = ?")
+                    .bind(label_type.as_str())
+                    .bind(reference)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set or clear the label of a UTXO by its database id.
+    pub async fn set_utxo_label(&self, id: i64, label: Option<String>) -> Result<()> {
+        let hash: String = sqlx::query("SELECT hash FROM wallet_state_utxos WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .with_context(|| format!("utxo {} not found", id))?
+            .get(0);
+
+        self.set_label(LabelType::Output, &hash, label).await
+    }
+
+    pub async fn labels(&self) -> Result<Vec<Label>> {
+        let rows = sqlx::query(
+            "SELECT l.type, l.ref, l.label, u.frozen FROM wallet_state_labels l LEFT JOIN wallet_state_utxos u ON l.type = 'output' AND u.hash = l.ref ORDER BY l.type, l.ref",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut labels = Vec::with_capacity(rows.len());
+        for row in rows {
+            let label_type: String = row.get("type");
+            let Some(label_type) = LabelType::from_str(&label_type) else {
+                warn!("unknown label type {} in database", label_type);
+                continue;
+            };
+            labels.push(Label {
+                label_type,
+                reference: row.get("ref"),
+                label: row.get("label"),
+                spendable: row.get::<Option<bool>, _>("frozen").map(|frozen| !frozen),
+            });
+        }
+
+        Ok(labels)
+    }
+
+    /// Labels of one type keyed by reference, to join into responses.
+    pub async fn get_labels(&self, label_type: LabelType) -> Result<HashMap<String, String>> {
+        let rows = sqlx::query("SELECT ref, label FROM wallet_state_labels WHERE type = ?")
+            .bind(label_type.as_str())
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("ref"), row.get("label")))
+            .collect())
+    }
+
+    /// All labels as JSONL, one [Label] per line.
+    pub async fn export_labels(&self) -> Result<String> {
+        let mut jsonl = String::new();
+        for label in self.labels().await? {
+            jsonl.push_str(&serde_json::to_string(&label)?);
+            jsonl.push('\n');
+        }
+        Ok(jsonl)
+    }
+
+    /// Import JSONL labels, returns how many were set.
+    ///
+    /// Types other than [LabelType] are skipped like BIP-329 asks, `spendable: false` freezes
+    /// the UTXO if the wallet has it. Nothing is imported if any line is invalid.
+    pub async fn import_labels(&self, jsonl: &str) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        let mut imported = 0;
+        for (n, line) in jsonl.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let value: serde_json::Value =
+                serde_json::from_str(line).with_context(|| format!("line {}", n + 1))?;
+            let known_type = value
+                .get("type")
+                .and_then(|t| t.as_str())
+                .and_then(LabelType::from_str);
+            if known_type.is_none() {
+                debug!("skipping label line {} of unknown type", n + 1);
+                continue;
+            }
+            let label: Label =
+                serde_json::from_value(value).with_context(|| format!("line {}", n + 1))?;
+
+            self.write_label(&mut tx, label.label_type, &label.reference, label.label)
+                .await
+                .with_context(|| format!("line {}", n + 1))?;
+            if label.label_type == LabelType::Output {
+                if let Some(spendable) = label.spendable {
+                    sqlx::query("UPDATE wallet_state_utxos SET frozen = ? WHERE hash = ? AND spent_in_block IS NULL")
+                        .bind(!spendable)
+                        .bind(label.reference.trim())
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+            imported += 1;
+        }
+        tx.commit().await?;
+
+        info!("imported {} labels", imported);
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::test_wallet_state;
+
+    #[tokio::test]
+    async fn test_import_labels() {
+        let wallet_state = test_wallet_state().await;
+
+        let jsonl = concat!(
+            r#"{"type":"tx","ref":"aa","label":"rent"}"#,
+            "\n\n",
+            r#"{"type":"xpub","ref":"bb","label":"skipped"}"#,
+            "\n",
+            r#"{"type":"output","ref":"cc","label":"change","spendable":false}"#,
+            "\n",
+        );
+        assert_eq!(wallet_state.import_labels(jsonl).await.unwrap(), 2);
+        let labels = wallet_state.get_labels(LabelType::Tx).await.unwrap();
+        assert_eq!(labels.get("aa").map(String::as_str), Some("rent"));
+
+        // an invalid line rolls back the lines before it
+        let jsonl = concat!(
+            r#"{"type":"tx","ref":"dd","label":"food"}"#,
+            "\n",
+            r#"{"type":"addr","ref":"not an address","label":"shop"}"#,
+            "\n",
+        );
+        assert!(wallet_state.import_labels(jsonl).await.is_err());
+        let labels = wallet_state.get_labels(LabelType::Tx).await.unwrap();
+        assert_eq!(labels.len(), 1);
+        assert!(!labels.contains_key("dd"));
+    }
+}
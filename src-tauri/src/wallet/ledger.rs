@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::DateTime;
+use chrono::NaiveDate;
+use chrono::Utc;
+use neptune_privacy::api::export::Timestamp;
+use neptune_privacy::protocol::consensus::type_scripts::native_currency_amount::NativeCurrencyAmount;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::history::TxHistoryFilter;
+use super::history::TxHistorySort;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl LedgerFormat {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "csv" => Some(LedgerFormat::Csv),
+            "json" => Some(LedgerFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// One transaction of the accounting export.
+#[derive(Debug, Clone, Serialize)]
+pub struct LedgerRow {
+    /// RFC 3339, UTC
+    pub date: String,
+    pub height: u64,
+    pub txid: Option<String>,
+    /// `in`, `out`, or `self` for a net change of zero
+    pub direction: &'static str,
+    /// Net amount, negative for `out`, fee included
+    pub amount: String,
+    pub fee: Option<String>,
+    /// Balance after this transaction, time-locked UTXOs included
+    pub balance: String,
+    pub release_date: Option<String>,
+    pub label: Option<String>,
+    /// Price of the day from the price file
+    pub price: Option<f64>,
+    pub fiat_amount: Option<f64>,
+    pub fiat_fee: Option<f64>,
+}
+
+fn format_date(timestamp: Timestamp) -> String {
+    DateTime::<Utc>::from_timestamp_millis(timestamp.to_millis() as i64)
+        .unwrap_or_default()
+        .to_rfc3339()
+}
+
+fn signed_nau(amount: &str) -> Result<i128> {
+    let (negative, amount) = match amount.strip_prefix('-') {
+        Some(amount) => (true, amount),
+        None => (false, amount),
+    };
+    let nau = NativeCurrencyAmount::coins_from_str(amount)?.to_nau();
+    Ok(if negative { -nau } else { nau })
+}
+
+fn to_f64(amount: &str) -> f64 {
+    amount.parse().unwrap_or_default()
+}
+
+/// Daily prices from `date,price` lines, dates as `YYYY-MM-DD`. A header line is skipped.
+fn parse_prices(csv: &str) -> Result<BTreeMap<NaiveDate, f64>> {
+    let mut prices = BTreeMap::new();
+    for (n, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (date, price) = line
+            .split_once(',')
+            .ok_or_else(|| anyhow!("price line {} is not date,price", n + 1))?;
+        let Ok(date) = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d") else {
+            if n == 0 {
+                continue;
+            }
+            bail!("invalid date on price line {}", n + 1);
+        };
+        let price = price
+            .trim()
+            .parse::<f64>()
+            .with_context(|| format!("invalid price on line {}", n + 1))?;
+        prices.insert(date, price);
+    }
+    Ok(prices)
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn ledger_to_csv(rows: &[LedgerRow], with_prices: bool) -> String {
+    let mut csv = String::from("date,height,txid,direction,amount,fee,balance,release_date,label");
+    if with_prices {
+        csv.push_str(",price,fiat_amount,fiat_fee");
+    }
+    csv.push('\n');
+
+    let optional = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+    for row in rows {
+        let mut fields = vec![
+            row.date.clone(),
+            row.height.to_string(),
+            row.txid.clone().unwrap_or_default(),
+            row.direction.to_string(),
+            row.amount.clone(),
+            row.fee.clone().unwrap_or_default(),
+            row.balance.clone(),
+            row.release_date.clone().unwrap_or_default(),
+            csv_field(row.label.as_deref().unwrap_or_default()),
+        ];
+        if with_prices {
+            fields.push(optional(row.price));
+            fields.push(optional(row.fiat_amount));
+            fields.push(optional(row.fiat_fee));
+        }
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+impl super::WalletState {
+    /// The full history as ledger rows, oldest first, limited to `from..=to`.
+    ///
+    /// The running balance counts every earlier transaction, also those before `from`.
+    /// `prices` is a `date,price` CSV, days without a price get no fiat values.
+    pub async fn ledger(
+        &self,
+        from: Option<Timestamp>,
+        to: Option<Timestamp>,
+        prices: Option<&str>,
+    ) -> Result<Vec<LedgerRow>> {
+        let prices = prices.map(parse_prices).transpose()?;
+        let history = self
+            .get_transaction_history(TxHistoryFilter {
+                sort: TxHistorySort::Time,
+                ascending: true,
+                ..Default::default()
+            })
+            .await?;
+
+        let mut balance = 0i128;
+        let mut rows = vec![];
+        for item in history.items {
+            let delta = signed_nau(&item.delta)?;
+            balance += delta;
+
+            if from.is_some_and(|from| item.timestamp < from)
+                || to.is_some_and(|to| item.timestamp > to)
+            {
+                continue;
+            }
+
+            let direction = match delta.signum() {
+                1 => "in",
+                -1 => "out",
+                _ => "self",
+            };
+            let price = prices.as_ref().and_then(|prices| {
+                let date =
+                    DateTime::<Utc>::from_timestamp_millis(item.timestamp.to_millis() as i64)?
+                        .date_naive();
+                prices.get(&date).copied()
+            });
+
+            rows.push(LedgerRow {
+                date: format_date(item.timestamp),
+                height: item.height,
+                txid: item.txid,
+                direction,
+                price,
+                fiat_amount: price.map(|price| to_f64(&item.delta) * price),
+                fiat_fee: price
+                    .zip(item.fee.as_deref())
+                    .map(|(price, fee)| to_f64(fee) * price),
+                amount: item.delta,
+                fee: item.fee,
+                balance: NativeCurrencyAmount::from_nau(balance).display_lossless(),
+                release_date: item.release_date.map(format_date),
+                label: item.label,
+            });
+        }
+
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_prices() {
+        let prices = parse_prices("date,price\n2025-01-02, 1.5\n\n 2025-01-01 ,2\n").unwrap();
+        assert_eq!(
+            prices.into_iter().collect::<Vec<_>>(),
+            vec![
+                (NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(), 2.0),
+                (NaiveDate::from_ymd_opt(2025, 1, 2).unwrap(), 1.5),
+            ]
+        );
+
+        // without a header the first line is a price too
+        assert_eq!(parse_prices("2025-01-01,3").unwrap().len(), 1);
+
+        // only the first line may be a header
+        assert!(parse_prices("2025-01-01,3\ndate,price").is_err());
+        assert!(parse_prices("2025-01-01;3").is_err());
+        assert!(parse_prices("2025-01-01,three").is_err());
+    }
+
+    #[test]
+    fn test_signed_nau() {
+        let nau = signed_nau("1.5").unwrap();
+        assert!(nau > 0);
+        assert_eq!(signed_nau("-1.5").unwrap(), -nau);
+        assert_eq!(signed_nau("3").unwrap(), 2 * nau);
+        assert_eq!(signed_nau("0").unwrap(), 0);
+        assert!(signed_nau("one").is_err());
+    }
+
+    #[test]
+    fn test_ledger_to_csv() {
+        let row = LedgerRow {
+            date: "2025-01-01T00:00:00+00:00".to_string(),
+            height: 7,
+            txid: None,
+            direction: "in",
+            amount: "1.5".to_string(),
+            fee: None,
+            balance: "1.5".to_string(),
+            release_date: None,
+            label: Some("rent, \"march\"\nsplit".to_string()),
+            price: Some(2.0),
+            fiat_amount: Some(3.0),
+            fiat_fee: None,
+        };
+
+        assert_eq!(
+            ledger_to_csv(std::slice::from_ref(&row), false),
+            "date,height,txid,direction,amount,fee,balance,release_date,label\n\
+             2025-01-01T00:00:00+00:00,7,,in,1.5,,1.5,,\"rent, \"\"march\"\"\nsplit\"\n"
+        );
+        assert_eq!(
+            ledger_to_csv(&[LedgerRow { label: None, ..row }], true),
+            "date,height,txid,direction,amount,fee,balance,release_date,label,price,fiat_amount,fiat_fee\n\
+             2025-01-01T00:00:00+00:00,7,,in,1.5,,1.5,,,2,3,\n"
+        );
+    }
+}
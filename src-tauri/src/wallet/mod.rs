@@ -18,10 +18,11 @@ use neptune_privacy::prelude::tasm_lib::prelude::Digest;
 use neptune_privacy::protocol::consensus::block::mutator_set_update::MutatorSetUpdate;
 use neptune_privacy::protocol::proof_abstractions::mast_hash::MastHash;
 use neptune_privacy::state::wallet::incoming_utxo::IncomingUtxo;
-use neptune_privacy::state::wallet::wallet_entropy::WalletEntropy;
 use neptune_privacy::util_types::mutator_set::mutator_set_accumulator::MutatorSetAccumulator;
 use neptune_privacy::util_types::mutator_set::removal_record::absolute_index_set::AbsoluteIndexSet;
 use pending::TransactionUpdater;
+use progress::SyncPhase;
+use progress::SyncProgress;
 use rayon::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
@@ -34,25 +35,41 @@ use wallet_state_table::UtxoDbData;
 
 use crate::config::wallet::ScanConfig;
 use crate::config::wallet::WalletConfig;
+use crate::config::wallet::WalletKey;
 use crate::config::Config;
 
 // mod archive_state;
+pub mod airgap;
 pub mod balance;
 pub mod fake_archival_state;
 pub mod fork;
+pub mod history;
 mod input;
 pub use input::InputSelectionRule;
 pub mod block_cache;
+pub mod consolidate;
+pub mod decode;
 mod key_cache;
 mod keys;
+pub mod labels;
+pub mod ledger;
+pub mod paranoid;
+pub mod payments;
 mod pending;
+mod prefetch;
+pub mod progress;
+pub mod rescan;
+pub mod saved;
+mod scanner;
 mod spend;
 pub mod sync;
+pub mod sync_manager;
+pub mod validate;
 pub mod wallet_file;
 mod wallet_state_table;
 
 pub struct WalletState {
-    key: WalletEntropy,
+    key: WalletKey,
     scan_config: ScanConfig,
     pub network: Network,
     num_symmetric_keys: AtomicU64,
@@ -67,8 +84,8 @@ pub struct WalletState {
 }
 
 impl WalletState {
-    pub async fn new_from_config(config: &Config) -> Result<Self> {
-        let wallet_config = config.get_current_wallet().await?;
+    pub async fn new_from_config(config: &Config, id: i64) -> Result<Self> {
+        let wallet_config = config.get_wallet(id).await?;
         let database = Self::wallet_database_path(config, wallet_config.id).await?;
         Self::new(wallet_config, &database).await
     }
@@ -139,6 +156,10 @@ impl WalletState {
         Ok(state)
     }
 
+    pub fn is_watch_only(&self) -> bool {
+        self.key.is_watch_only()
+    }
+
     pub async fn start_height(&self) -> Result<u64> {
         if let Some(tip) = self.get_tip().await? {
             return Ok(tip.0 + 1);
@@ -150,11 +171,18 @@ impl WalletState {
         Ok(self.scan_config.start_height)
     }
 
+    /// Apply a validated block on top of the wallet tip, returns the fork height if the
+    /// block does not extend the tip.
+    ///
+    /// `previous_mutator_set_accumulator` moves to `mutator_set_accumulator_after`, the
+    /// accumulator the block was validated with, once the block is stored.
     pub async fn update_new_tip(
         &self,
-        previous_mutator_set_accumulator: &MutatorSetAccumulator,
+        previous_mutator_set_accumulator: &mut MutatorSetAccumulator,
         block: &ExportedBlock,
+        mutator_set_accumulator_after: &MutatorSetAccumulator,
         should_update: bool,
+        progress: &SyncProgress,
     ) -> Result<Option<u64>> {
         let mut msa_state = previous_mutator_set_accumulator.clone();
         let height: u64 = block.kernel.header.height.into();
@@ -164,7 +192,10 @@ impl WalletState {
         let _spend_guard = self.spend_lock.lock().await;
 
         debug!("check fork");
-        if let Some(fork_point) = self.check_fork(&block).await.context("check fork")? {
+        let fork_check = progress.enter(SyncPhase::ForkCheck);
+        let fork = self.check_fork(&block).await.context("check fork")?;
+        drop(fork_check);
+        if let Some(fork_point) = fork {
             info!(
                 "reorganize_to_height: {} {}",
                 fork_point.0,
@@ -177,6 +208,7 @@ impl WalletState {
             return Ok(Some(fork_point.0));
         }
         debug!("update mutator set");
+        let scanning = progress.enter(SyncPhase::Scanning);
 
         let MutatorSetUpdate {
             additions: addition_records,
@@ -186,6 +218,7 @@ impl WalletState {
         debug!("get removal_records");
 
         debug!("scan for incoming utxo");
+        let key_window = self.key_window();
         let incommings = self.par_scan_for_incoming_utxo(&block).await?;
         let mut recovery_datas = Vec::with_capacity(incommings.len());
 
@@ -230,6 +263,7 @@ impl WalletState {
                 confirm_height: height.try_into()?,
                 confirmed_txid: None,
                 spent_txid: None,
+                frozen: false,
             };
             db_datas.push(db_data);
         }
@@ -281,12 +315,20 @@ impl WalletState {
         );
         self.set_tip(&mut *tx, (block.kernel.header.height.into(), block.hash()))
             .await?;
+        self.append_header(&mut *tx, block).await?;
+
+        // keys that entered the gap window missed the blocks before, rescan them later
+        self.record_key_window_growth(&mut *tx, key_window, height)
+            .await?;
 
         tx.commit().await?;
+        *previous_mutator_set_accumulator = mutator_set_accumulator_after.clone();
 
         self.clean_old_expected_utxos().await?;
+        drop(scanning);
 
         if should_update {
+            let _updating = progress.enter(SyncPhase::UpdatingPending);
             self.updater.update_transactions(&self).await;
         }
 
@@ -300,9 +342,10 @@ impl WalletState {
     ) -> anyhow::Result<Vec<IncomingUtxo>> {
         let transaction = &block.kernel.body.transaction_kernel();
 
-        let spendingkeys = self.get_future_generation_spending_keys(Range {
+        let (generation_end, symmetric_end) = self.key_window();
+        let spendingkeys = self.get_generation_scan_keys(Range {
             start: 0,
-            end: self.num_generation_spending_keys() + self.num_future_keys(),
+            end: generation_end,
         });
 
         let spend_to_spendingkeys = spendingkeys.par_iter().flat_map(|spendingkey| {
@@ -317,9 +360,9 @@ impl WalletState {
         self.set_num_generation_spending_keys(self.num_generation_spending_keys())
             .await?;
 
-        let symmetric_keys = self.get_future_symmetric_keys(Range {
+        let symmetric_keys = self.get_symmetric_scan_keys(Range {
             start: 0,
-            end: self.num_symmetric_keys() + self.num_future_keys(),
+            end: symmetric_end,
         });
 
         let spend_to_symmetrickeys = symmetric_keys.par_iter().flat_map(|spendingkey| {
@@ -334,13 +377,13 @@ impl WalletState {
         self.set_num_symmetric_keys(self.num_symmetric_keys())
             .await?;
 
-        let own_guesser_key = self.key.guesser_fee_key();
-        let was_guessed_by_us = block
-            .kernel
-            .header
-            .was_guessed_by(&own_guesser_key.to_address().into());
+        // watch-only wallets have no guesser fee key
+        let own_guesser_key = self
+            .key
+            .guesser_fee_key()
+            .filter(|key| block.kernel.header.was_guessed_by(&key.to_address().into()));
 
-        let gusser_incoming_utxos = if was_guessed_by_us {
+        let gusser_incoming_utxos = if let Some(own_guesser_key) = own_guesser_key {
             let sender_randomness = block.hash();
             block
                 .kernel
@@ -478,3 +521,20 @@ impl Drop for WalletState {
         }
     }
 }
+
+/// In-memory wallet state of the devnet wallet scanning 5 keys ahead.
+#[cfg(test)]
+pub(crate) async fn test_wallet_state() -> WalletState {
+    use neptune_privacy::state::wallet::wallet_entropy::WalletEntropy;
+
+    let config = WalletConfig {
+        id: 0,
+        key: WalletKey::Entropy(WalletEntropy::devnet_wallet()),
+        scan_config: ScanConfig {
+            num_keys: 5,
+            start_height: 0,
+        },
+        network: Network::Main,
+    };
+    WalletState::new(config, &PathBuf::new()).await.unwrap()
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use neptune_privacy::api::export::Timestamp;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::Row;
+use tracing::*;
+
+use crate::rpc_client;
+
+/// A synced block a second server disagrees with, sync stays paused until it is cleared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncAlert {
+    pub height: u64,
+    pub local_digest: String,
+    pub remote_digest: String,
+    pub server: String,
+    pub timestamp: u64,
+}
+
+impl super::WalletState {
+    /// Compare the block scanned at `height` with a second rest server.
+    ///
+    /// A mismatch is stored as a [SyncAlert] and returned.
+    pub async fn cross_check_height(&self, height: u64) -> Result<Option<SyncAlert>> {
+        let Some(local) = self.get_header(height).await? else {
+            return Ok(None);
+        };
+        let Some((server, remote)) = rpc_client::node_rpc_client()
+            .second_opinion_digest(height)
+            .await?
+        else {
+            debug!("no second server to cross check block {}", height);
+            return Ok(None);
+        };
+
+        if remote == local.digest {
+            debug!("cross checked block {} with {}", height, server);
+            return Ok(None);
+        }
+
+        let alert = SyncAlert {
+            height,
+            local_digest: local.digest.to_hex(),
+            remote_digest: remote.to_hex(),
+            server,
+            timestamp: Timestamp::now().to_millis(),
+        };
+        error!("block digest mismatch: {:?}", alert);
+
+        let value_db = serde_json::to_string(&alert)?;
+        sqlx::query("INSERT INTO wallet_state_keys (id, value) VALUES ('sync_alert', ?) ON CONFLICT(id) DO UPDATE SET value = ?")
+            .bind(&value_db)
+            .bind(&value_db)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(Some(alert))
+    }
+
+    pub async fn get_sync_alert(&self) -> Result<Option<SyncAlert>> {
+        let row = sqlx::query("SELECT value FROM wallet_state_keys WHERE id = 'sync_alert'")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_str(&row.get::<String, _>(0))?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn clear_sync_alert(&self) -> Result<()> {
+        sqlx::query("DELETE FROM wallet_state_keys WHERE id = 'sync_alert'")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
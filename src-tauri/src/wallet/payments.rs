@@ -0,0 +1,139 @@
+use anyhow::Result;
+use neptune_privacy::api::export::Timestamp;
+use neptune_privacy::protocol::consensus::transaction::utxo::Utxo;
+use neptune_privacy::protocol::consensus::type_scripts::native_currency_amount::NativeCurrencyAmount;
+use neptune_privacy::state::wallet::address::ReceivingAddress;
+use neptune_privacy::state::wallet::utxo_notification::UtxoNotificationMedium;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+use sqlx::SqliteConnection;
+
+/// An output we paid to another wallet.
+#[derive(Debug, Clone, Serialize)]
+pub struct SentPayment {
+    pub id: i64,
+    pub txid: String,
+    pub address: String,
+    pub amount: String,
+    /// Fee of the whole transaction
+    pub fee: String,
+    /// `on_chain` or `off_chain`
+    pub medium: String,
+    pub timestamp: Timestamp,
+    /// Set once the transaction is confirmed
+    pub confirm_height: Option<u64>,
+    /// The recipient can not spend the output before this time
+    pub release_date: Option<Timestamp>,
+}
+
+impl SentPayment {
+    fn from_row(row: &SqliteRow) -> Self {
+        Self {
+            id: row.get("id"),
+            txid: row.get("txid"),
+            address: row.get("address"),
+            amount: row.get("amount"),
+            fee: row.get("fee"),
+            medium: row.get("medium"),
+            timestamp: Timestamp::millis(row.get::<i64, _>("timestamp") as u64),
+            confirm_height: row
+                .get::<Option<i64>, _>("confirm_height")
+                .map(|height| height as u64),
+            release_date: row
+                .get::<Option<i64>, _>("release_date")
+                .map(|date| Timestamp::millis(date as u64)),
+        }
+    }
+}
+
+impl super::WalletState {
+    /// Record the outputs of `txid` that do not go back to this wallet.
+    pub(super) async fn record_sent_payments(
+        &self,
+        txid: &str,
+        outputs: &[(ReceivingAddress, NativeCurrencyAmount, Option<Timestamp>)],
+        fee: NativeCurrencyAmount,
+        medium: UtxoNotificationMedium,
+    ) -> Result<()> {
+        let timestamp = Timestamp::now().to_millis() as i64;
+        let medium = match medium {
+            UtxoNotificationMedium::OnChain => "on_chain",
+            UtxoNotificationMedium::OffChain => "off_chain",
+        };
+
+        let mut tx = self.pool.begin().await?;
+        // the transaction may be proven again after a failed broadcast
+        sqlx::query("DELETE FROM wallet_state_sent_payments WHERE txid = ?")
+            .bind(txid)
+            .execute(&mut *tx)
+            .await?;
+        for (address, amount, release_date) in outputs {
+            let utxo = Utxo::new_native_currency(address.lock_script_hash(), *amount);
+            if self.can_unlock(&utxo) {
+                continue;
+            }
+            sqlx::query(
+                "INSERT INTO wallet_state_sent_payments (txid, address, amount, fee, medium, timestamp, release_date) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(txid)
+            .bind(address.to_bech32m(self.network)?)
+            .bind(amount.display_lossless())
+            .bind(fee.display_lossless())
+            .bind(medium)
+            .bind(timestamp)
+            .bind(release_date.map(|date| date.to_millis() as i64))
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Payments of one transaction, or all of them newest first.
+    pub async fn sent_payments(&self, txid: Option<&str>) -> Result<Vec<SentPayment>> {
+        let rows = match txid {
+            Some(txid) => {
+                sqlx::query("SELECT * FROM wallet_state_sent_payments WHERE txid = ? ORDER BY id")
+                    .bind(txid)
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+            None => {
+                sqlx::query("SELECT * FROM wallet_state_sent_payments ORDER BY timestamp DESC, id")
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+        };
+
+        Ok(rows.iter().map(SentPayment::from_row).collect())
+    }
+
+    pub(super) async fn confirm_sent_payments<'c>(
+        &self,
+        tx: &'c mut SqliteConnection,
+        txid: &str,
+        height: u64,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE wallet_state_sent_payments SET confirm_height = ? WHERE txid = ? AND confirm_height IS NULL",
+        )
+        .bind(height as i64)
+        .bind(txid)
+        .execute(&mut *tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Unconfirmed payments of a forgotten transaction are dropped, confirmed ones stay.
+    pub(super) async fn delete_unconfirmed_payments(&self, txid: &str) -> Result<()> {
+        sqlx::query(
+            "DELETE FROM wallet_state_sent_payments WHERE txid = ? AND confirm_height IS NULL",
+        )
+        .bind(txid)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use itertools::Itertools;
 use neptune_privacy::api::export::Timestamp;
 use neptune_privacy::api::export::TransactionDetails;
 use neptune_privacy::api::export::TxProvingCapability;
@@ -21,7 +22,13 @@ impl super::WalletState {
         self.updater.get_pending_transaction_ids().await
     }
 
+    pub async fn get_pending_details(&self, txid: &str) -> Result<Option<Vec<u8>>> {
+        self.updater.get_details(txid).await
+    }
+
     pub async fn forget_tx(&self, txid: &str) -> Result<()> {
+        self.delete_saved_transaction(txid).await?;
+        self.delete_unconfirmed_payments(txid).await?;
         self.updater.delete_transaction(txid).await
     }
 }
@@ -61,6 +68,19 @@ sqlx_migrator::sqlite_migration!(
     )]
 );
 
+struct AddPendingTxExternalMigration;
+
+sqlx_migrator::sqlite_migration!(
+    AddPendingTxExternalMigration,
+    "wallet_state",
+    "add_pending_tx_external",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        "ALTER TABLE wallet_state_pending ADD COLUMN external INTEGER NOT NULL DEFAULT 0", //up
+        "ALTER TABLE wallet_state_pending DROP COLUMN external"                            //down
+    )]
+);
+
 pub struct TransactionUpdater {
     pool: SqlitePool,
 }
@@ -80,6 +100,7 @@ impl TransactionUpdater {
         // Adding migrations add its parents, replaces and not before as well
         migrator.add_migration(Box::new(CreatePendingTxMigration))?;
         migrator.add_migration(Box::new(CreatePendingTxDbIdsMigration))?;
+        migrator.add_migration(Box::new(AddPendingTxExternalMigration))?;
 
         let mut conn = self.pool.acquire().await?;
         // use apply all to apply all pending migration
@@ -151,6 +172,7 @@ impl TransactionUpdater {
         for tx_output in tx_outputs.iter_mut() {
             let new_sender_randomness = wallet_state
                 .key
+                .entropy()?
                 .generate_sender_randomness(tip_height, tx_output.receiver_digest());
             tx_output.set_sender_randomness(new_sender_randomness);
         }
@@ -210,6 +232,29 @@ impl TransactionUpdater {
         Ok(())
     }
 
+    /// Track a transaction proven elsewhere, it has no details and is never re-proven here.
+    pub async fn add_external_transaction(&self, tx_id: String, input_ids: Vec<i64>) -> Result<()> {
+        let mut conn = self.pool.begin().await?;
+
+        sqlx::query("INSERT INTO wallet_state_pending (id, details, external) VALUES (?, ?, 1)")
+            .bind(&tx_id)
+            .bind(Vec::<u8>::new())
+            .execute(&mut *conn)
+            .await?;
+
+        for utxo_id in input_ids {
+            sqlx::query("INSERT INTO wallet_state_pending_ids (txid, utxo_id) VALUES (?, ?)")
+                .bind(&tx_id)
+                .bind(&utxo_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        conn.commit().await?;
+
+        Ok(())
+    }
+
     async fn update_detail(&self, tx_id: &str, detail: &TransactionDetails) -> Result<()> {
         let mut conn = self.pool.acquire().await?;
 
@@ -227,9 +272,10 @@ impl TransactionUpdater {
         &self,
         tx: &'c mut SqliteConnection,
     ) -> Result<Vec<(String, TransactionDetails, Vec<i64>)>> {
-        let rows = sqlx::query("SELECT * FROM wallet_state_pending WHERE finished = 0")
-            .fetch_all(&mut *tx)
-            .await?;
+        let rows =
+            sqlx::query("SELECT * FROM wallet_state_pending WHERE finished = 0 AND external = 0")
+                .fetch_all(&mut *tx)
+                .await?;
 
         let mut result = vec![];
         for row in rows {
@@ -252,6 +298,17 @@ impl TransactionUpdater {
         Ok(result)
     }
 
+    /// The bincode [TransactionDetails] of a pending transaction proven by this wallet.
+    pub async fn get_details(&self, tx_id: &str) -> Result<Option<Vec<u8>>> {
+        let row =
+            sqlx::query("SELECT details FROM wallet_state_pending WHERE id = ? AND external = 0")
+                .bind(tx_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.map(|row| row.get::<Vec<u8>, _>(0)))
+    }
+
     pub async fn delete_transaction(&self, tx_id: &str) -> Result<()> {
         let mut conn = self.pool.acquire().await?;
 
@@ -332,28 +389,30 @@ impl TransactionUpdater {
         tx: &'c mut SqliteConnection,
         utxoid: Vec<i64>,
     ) -> Result<()> {
-        let transactions = match self.get_pending_transactions(&mut *tx).await {
-            Ok(transactions) => transactions,
-            Err(err) => {
-                error!("Error getting pending transactions: {}", err);
-                vec![]
-            }
-        };
+        let pending = sqlx::query(
+            "SELECT DISTINCT txid, utxo_id FROM wallet_state_pending_ids WHERE finished = 0",
+        )
+        .fetch_all(&mut *tx)
+        .await?;
 
-        for transaction in transactions {
-            if let Some(_utxo) = transaction.2.iter().find(|id| utxoid.contains(id)) {
-                //should be deleted
-                let txid = transaction.0;
-                sqlx::query("DELETE FROM wallet_state_pending WHERE id = ?")
-                    .bind(&txid)
-                    .execute(&mut *tx)
-                    .await?;
+        let txids = pending
+            .into_iter()
+            .filter(|row| utxoid.contains(&row.get::<i64, _>("utxo_id")))
+            .map(|row| row.get::<String, _>("txid"))
+            .unique()
+            .collect::<Vec<_>>();
 
-                sqlx::query("DELETE FROM wallet_state_pending_ids WHERE txid = ?")
-                    .bind(&txid)
-                    .execute(&mut *tx)
-                    .await?;
-            }
+        for txid in txids {
+            //should be deleted
+            sqlx::query("DELETE FROM wallet_state_pending WHERE id = ?")
+                .bind(&txid)
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query("DELETE FROM wallet_state_pending_ids WHERE txid = ?")
+                .bind(&txid)
+                .execute(&mut *tx)
+                .await?;
         }
 
         Ok(())
@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::*;
+
+use super::block_cache::BlockCache;
+use super::fake_archival_state::FakeArchivalState;
+use super::sync::SYNC_BLOCK_BATCH_SIZE;
+
+/// Number of batches kept downloading ahead of the scanned height.
+pub const PREFETCH_BATCHES: u64 = 3;
+const MIN_BATCH_SIZE: u64 = 10;
+pub const MAX_BATCH_SIZE: u64 = 200;
+/// Download time of a single batch the adaptive batch size aims for.
+const TARGET_BATCH_DURATION: Duration = Duration::from_secs(5);
+
+/// Blocks the prefetcher may hold in the block cache ahead of the scanned height.
+pub const PREFETCH_WINDOW: u64 = PREFETCH_BATCHES * MAX_BATCH_SIZE;
+
+pub struct Prefetcher {
+    inner: Mutex<PrefetchInner>,
+    /// Notified whenever a batch finished downloading.
+    landed: Notify,
+}
+
+struct PrefetchInner {
+    /// Heights in `from..until` are downloaded or downloading.
+    from: u64,
+    until: u64,
+    /// Batches downloading, keyed by start height.
+    in_flight: BTreeMap<u64, (u64, JoinHandle<()>)>,
+    batch_size: u64,
+    /// The server returned a short batch, nothing is scheduled past `until`.
+    at_tip: bool,
+    /// Bumped on restart so batches of an old pipeline are ignored.
+    epoch: u64,
+    error: Option<String>,
+}
+
+impl PrefetchInner {
+    fn restart(&mut self, height: u64) {
+        for (_, (_, handle)) in std::mem::take(&mut self.in_flight) {
+            handle.abort();
+        }
+        self.epoch += 1;
+        self.from = height;
+        self.until = height;
+        self.at_tip = false;
+    }
+
+    /// Book a finished batch, returns false if it belongs to an old pipeline.
+    fn land(
+        &mut self,
+        epoch: u64,
+        start: u64,
+        size: u64,
+        elapsed: Duration,
+        result: Result<usize>,
+    ) -> bool {
+        if self.epoch != epoch {
+            return false;
+        }
+        self.in_flight.remove(&start);
+
+        match result {
+            Ok(count) if (count as u64) < size => {
+                debug!("prefetch reached tip at {}", start + count as u64);
+                self.at_tip = true;
+                self.until = start + count as u64;
+                for (_, (_, handle)) in self.in_flight.split_off(&start) {
+                    handle.abort();
+                }
+            }
+            Ok(_) => {
+                let rate = size as f64 / elapsed.as_secs_f64().max(0.001);
+                let target = (rate * TARGET_BATCH_DURATION.as_secs_f64()) as u64;
+                self.batch_size =
+                    ((self.batch_size + target) / 2).clamp(MIN_BATCH_SIZE, MAX_BATCH_SIZE);
+                debug!(
+                    "prefetched {} blocks in {:?}, next batch size {}",
+                    size, elapsed, self.batch_size
+                );
+            }
+            Err(e) => {
+                warn!("prefetch blocks {} error: {:?}", start, e);
+                let from = self.from;
+                self.restart(from);
+                self.error = Some(format!("{:#}", e));
+            }
+        }
+
+        true
+    }
+
+    fn downloading(&self, height: u64) -> bool {
+        self.in_flight
+            .range(..=height)
+            .next_back()
+            .is_some_and(|(start, (size, _))| height < start + size)
+    }
+}
+
+impl Prefetcher {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(PrefetchInner {
+                from: 0,
+                until: 0,
+                in_flight: BTreeMap::new(),
+                batch_size: SYNC_BLOCK_BATCH_SIZE,
+                at_tip: false,
+                epoch: 0,
+                error: None,
+            }),
+            landed: Notify::new(),
+        }
+    }
+
+    /// Abort all downloads, the pipeline restarts at the next requested height.
+    pub fn cancel(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let from = inner.from;
+        inner.restart(from);
+        drop(inner);
+        self.landed.notify_waiters();
+    }
+}
+
+impl FakeArchivalState {
+    /// Make sure the block at `height` is cached and keep the next batches downloading.
+    ///
+    /// Waits only if the batch containing `height` is still in flight.
+    pub async fn prefetch(&self, height: u64) -> Result<()> {
+        let cached = self.block_cache.has_block_by_height(height).await?;
+
+        {
+            let mut inner = self.prefetcher.inner.lock().unwrap();
+            if height < inner.from || height >= inner.until {
+                if cached {
+                    return Ok(());
+                }
+                if height >= inner.until && !inner.in_flight.is_empty() {
+                    // the lowest wallet owns the pipeline, others fetch missing blocks one by one
+                    return Ok(());
+                }
+                debug!("restart prefetch at {}", height);
+                inner.restart(height);
+            }
+            inner.from = height;
+            self.schedule(&mut inner, height);
+            inner.error = None;
+        }
+
+        loop {
+            let notified = self.prefetcher.landed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let mut inner = self.prefetcher.inner.lock().unwrap();
+                if let Some(error) = inner.error.take() {
+                    return Err(anyhow!("prefetch blocks error: {}", error));
+                }
+                if !inner.downloading(height) {
+                    return Ok(());
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    fn schedule(&self, inner: &mut PrefetchInner, height: u64) {
+        while !inner.at_tip
+            && inner.in_flight.len() < PREFETCH_BATCHES as usize
+            && inner.until < height + PREFETCH_BATCHES * inner.batch_size
+        {
+            let start = inner.until;
+            let size = inner.batch_size;
+            let epoch = inner.epoch;
+
+            debug!("prefetch blocks: {} +{}", start, size);
+            let state = self.clone();
+            let handle = tokio::spawn(async move {
+                let started = Instant::now();
+                let result = state.fetch_batch(start, size).await;
+                state.batch_landed(epoch, start, size, started.elapsed(), result);
+            });
+
+            inner.in_flight.insert(start, (size, handle));
+            inner.until = start + size;
+        }
+    }
+
+    fn batch_landed(
+        &self,
+        epoch: u64,
+        start: u64,
+        size: u64,
+        elapsed: Duration,
+        result: Result<usize>,
+    ) {
+        let mut inner = self.prefetcher.inner.lock().unwrap();
+        if !inner.land(epoch, start, size, elapsed, result) {
+            return;
+        }
+
+        drop(inner);
+        self.prefetcher.landed.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(batches: &[(u64, u64)]) -> PrefetchInner {
+        let mut inner = PrefetchInner {
+            from: batches[0].0,
+            until: batches[0].0,
+            in_flight: BTreeMap::new(),
+            batch_size: 20,
+            at_tip: false,
+            epoch: 1,
+            error: None,
+        };
+        for &(start, size) in batches {
+            let handle = tokio::spawn(std::future::pending());
+            inner.in_flight.insert(start, (size, handle));
+            inner.until = start + size;
+        }
+        inner
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_in_flight() {
+        let mut inner = pipeline(&[(100, 20), (120, 20), (140, 20)]);
+        assert!(!inner.downloading(99));
+        assert!(inner.downloading(100));
+        assert!(inner.downloading(159));
+        assert!(!inner.downloading(160));
+
+        // a full batch adapts the batch size, 20 blocks in 1s aim for 100 per 5s
+        assert!(inner.land(1, 120, 20, Duration::from_secs(1), Ok(20)));
+        assert!(!inner.downloading(125));
+        assert!(inner.downloading(145));
+        assert_eq!(inner.batch_size, 60);
+
+        // a short batch marks the tip and aborts the batches after it
+        assert!(inner.land(1, 100, 20, Duration::from_secs(1), Ok(5)));
+        assert!(inner.at_tip);
+        assert_eq!(inner.until, 105);
+        assert!(inner.in_flight.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_epoch() {
+        let mut inner = pipeline(&[(100, 20), (120, 20)]);
+
+        // an error restarts the pipeline at the scanned height
+        inner.from = 110;
+        assert!(inner.land(1, 100, 20, Duration::from_secs(1), Err(anyhow!("timeout"))));
+        assert_eq!(inner.epoch, 2);
+        assert_eq!((inner.from, inner.until), (110, 110));
+        assert!(inner.in_flight.is_empty());
+        assert_eq!(inner.error.as_deref(), Some("timeout"));
+
+        // batches of the old pipeline land without touching the new one
+        inner.error = None;
+        inner
+            .in_flight
+            .insert(110, (20, tokio::spawn(std::future::pending())));
+        inner.until = 130;
+        assert!(!inner.land(1, 120, 20, Duration::from_secs(1), Ok(3)));
+        assert!(!inner.at_tip);
+        assert_eq!(inner.until, 130);
+        assert!(inner.downloading(110));
+    }
+}
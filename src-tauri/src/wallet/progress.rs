@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Serialize;
+
+/// Window the sync rate is averaged over.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// What the sync of a wallet is currently waiting on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPhase {
+    Idle,
+    Fetching,
+    SnapshotRead,
+    Scanning,
+    ForkCheck,
+    UpdatingPending,
+}
+
+const PHASES: [SyncPhase; 6] = [
+    SyncPhase::Idle,
+    SyncPhase::Fetching,
+    SyncPhase::SnapshotRead,
+    SyncPhase::Scanning,
+    SyncPhase::ForkCheck,
+    SyncPhase::UpdatingPending,
+];
+
+/// Cumulative time spent in each phase, in milliseconds.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PhaseTimings {
+    pub fetching_ms: u64,
+    pub snapshot_read_ms: u64,
+    pub scanning_ms: u64,
+    pub fork_check_ms: u64,
+    pub updating_pending_ms: u64,
+}
+
+pub struct SyncProgress {
+    phase: AtomicU8,
+    /// Indexed by the [SyncPhase] discriminant.
+    timings: [AtomicU64; PHASES.len()],
+    /// Synced heights of the last [RATE_WINDOW].
+    samples: Mutex<VecDeque<(Instant, u64)>>,
+}
+
+impl SyncProgress {
+    pub fn new() -> Self {
+        Self {
+            phase: AtomicU8::new(SyncPhase::Idle as u8),
+            timings: Default::default(),
+            samples: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn phase(&self) -> SyncPhase {
+        PHASES[self.phase.load(Ordering::Relaxed) as usize]
+    }
+
+    pub fn set_phase(&self, phase: SyncPhase) {
+        self.phase.store(phase as u8, Ordering::Relaxed);
+    }
+
+    /// Switch to `phase` until the returned guard is dropped, the time spent is recorded.
+    pub fn enter(&self, phase: SyncPhase) -> PhaseGuard<'_> {
+        let previous = self.phase();
+        self.set_phase(phase);
+        PhaseGuard {
+            progress: self,
+            phase,
+            previous,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn add_time(&self, phase: SyncPhase, duration: Duration) {
+        self.timings[phase as usize].fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn timings(&self) -> PhaseTimings {
+        let get = |phase: SyncPhase| self.timings[phase as usize].load(Ordering::Relaxed);
+        PhaseTimings {
+            fetching_ms: get(SyncPhase::Fetching),
+            snapshot_read_ms: get(SyncPhase::SnapshotRead),
+            scanning_ms: get(SyncPhase::Scanning),
+            fork_check_ms: get(SyncPhase::ForkCheck),
+            updating_pending_ms: get(SyncPhase::UpdatingPending),
+        }
+    }
+
+    /// Record that the blocks up to `height` are synced.
+    pub fn synced(&self, height: u64) {
+        let now = Instant::now();
+        let mut samples = self.samples.lock().unwrap();
+        if samples.back().is_some_and(|(_, last)| height < *last) {
+            // reorganized, the rate restarts from here
+            samples.clear();
+        }
+        samples.push_back((now, height));
+        while samples
+            .front()
+            .is_some_and(|(time, _)| now.duration_since(*time) > RATE_WINDOW)
+        {
+            samples.pop_front();
+        }
+    }
+
+    /// Blocks synced per second over the last minute.
+    pub fn blocks_per_sec(&self) -> f64 {
+        let samples = self.samples.lock().unwrap();
+        let (Some((first_time, first)), Some((_, last))) = (samples.front(), samples.back()) else {
+            return 0.0;
+        };
+        let elapsed = first_time.elapsed().as_secs_f64();
+        if elapsed < 1.0 {
+            return 0.0;
+        }
+        (last - first) as f64 / elapsed
+    }
+}
+
+pub struct PhaseGuard<'a> {
+    progress: &'a SyncProgress,
+    phase: SyncPhase,
+    previous: SyncPhase,
+    started: Instant,
+}
+
+impl Drop for PhaseGuard<'_> {
+    fn drop(&mut self) {
+        self.progress.add_time(self.phase, self.started.elapsed());
+        self.progress.set_phase(self.previous);
+    }
+}
@@ -0,0 +1,530 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::range::Range;
+use std::sync::atomic::Ordering;
+
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use itertools::Itertools;
+use neptune_privacy::api::export::Tip5;
+use neptune_privacy::application::rest_server::ExportedBlock;
+use neptune_privacy::protocol::consensus::block::mutator_set_update::MutatorSetUpdate;
+use neptune_privacy::util_types::mutator_set::removal_record::absolute_index_set::AbsoluteIndexSet;
+use rayon::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::SqliteConnection;
+use tracing::*;
+
+use super::fake_archival_state::FakeArchivalState;
+use super::incoming_utxo_recovery_data_from_incomming_utxo;
+use super::sync::SYNC_BLOCK_BATCH_SIZE;
+use super::wallet_state_table::UtxoBlockInfo;
+use super::wallet_state_table::UtxoDbData;
+
+/// How many gap windows past the current key window a manual rescan may reach, every key
+/// is derived and tried on every block.
+const MAX_RESCAN_WINDOWS: u64 = 10;
+
+/// Key indices to look for when rescanning blocks that were already synced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RescanKeys {
+    pub generation: std::ops::Range<u64>,
+    pub symmetric: std::ops::Range<u64>,
+}
+
+/// Keys that entered the gap window after the blocks up to `end_height` were scanned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingKeyRescan {
+    keys: RescanKeys,
+    end_height: u64,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RescanResult {
+    pub scanned_blocks: u64,
+    pub found_utxos: usize,
+    pub spent_utxos: usize,
+}
+
+impl super::WalletState {
+    /// End of the scanned generation and symmetric key indices.
+    ///
+    /// When key `i` receives funds the window grows to `i + num_future_keys`, for a watch-only
+    /// wallet up to the number of exported keys.
+    pub(super) fn key_window(&self) -> (u64, u64) {
+        let window = (
+            self.num_generation_spending_keys() + self.num_future_keys(),
+            self.num_symmetric_keys() + self.num_future_keys(),
+        );
+        match self.key.num_watched_keys() {
+            Some((generation, symmetric)) => (window.0.min(generation), window.1.min(symmetric)),
+            None => window,
+        }
+    }
+
+    /// Whether the gap window of a watch-only wallet reaches past its exported keys.
+    fn key_window_capped(&self) -> bool {
+        let (generation, symmetric) = self.key_window();
+        generation < self.num_generation_spending_keys() + self.num_future_keys()
+            || symmetric < self.num_symmetric_keys() + self.num_future_keys()
+    }
+
+    /// All keys in the current window, the default key set of a manual rescan.
+    pub fn window_rescan_keys(&self) -> RescanKeys {
+        let (generation_end, symmetric_end) = self.key_window();
+        RescanKeys {
+            generation: 0..generation_end,
+            symmetric: 0..symmetric_end,
+        }
+    }
+
+    /// Reject inverted key ranges and ranges ending more than [MAX_RESCAN_WINDOWS] gap windows
+    /// past the key window.
+    pub fn check_rescan_keys(&self, keys: &RescanKeys) -> Result<()> {
+        let (generation_end, symmetric_end) = self.key_window();
+        let extra = self.num_future_keys() * MAX_RESCAN_WINDOWS;
+        for (name, range, max) in [
+            ("generation", &keys.generation, generation_end + extra),
+            ("symmetric", &keys.symmetric, symmetric_end + extra),
+        ] {
+            ensure!(
+                range.start <= range.end,
+                "invalid {} key range {}..{}",
+                name,
+                range.start,
+                range.end
+            );
+            ensure!(
+                range.end <= max,
+                "{} key range {}..{} ends after key {}",
+                name,
+                range.start,
+                range.end,
+                max
+            );
+        }
+        Ok(())
+    }
+
+    /// Remember the keys that entered the window since `before`, blocks up to `height`
+    /// were scanned without them.
+    pub(super) async fn record_key_window_growth(
+        &self,
+        tx: &mut SqliteConnection,
+        before: (u64, u64),
+        height: u64,
+    ) -> Result<()> {
+        let after = self.key_window();
+        if after.0 <= before.0 && after.1 <= before.1 {
+            return Ok(());
+        }
+        if self.key_window_capped() {
+            warn!(
+                "key window stops at the {:?} exported keys, export more keys to find funds sent to later ones",
+                after
+            );
+        }
+
+        info!(
+            "key window grew from {:?} to {:?} at height {}",
+            before, after, height
+        );
+
+        let mut rescan = PendingKeyRescan {
+            keys: RescanKeys {
+                generation: before.0..after.0.max(before.0),
+                symmetric: before.1..after.1.max(before.1),
+            },
+            end_height: height,
+        };
+
+        if let Some(pending) = self.get_pending_key_rescan(&mut *tx).await? {
+            rescan.keys.generation = merge_range(&pending.keys.generation, &rescan.keys.generation);
+            rescan.keys.symmetric = merge_range(&pending.keys.symmetric, &rescan.keys.symmetric);
+            rescan.end_height = rescan.end_height.max(pending.end_height);
+        }
+
+        let value_db = serde_json::to_string(&rescan)?;
+        sqlx::query("INSERT INTO wallet_state_keys (id, value) VALUES ('key_rescan', ?) ON CONFLICT(id) DO UPDATE SET value = ?")
+            .bind(&value_db)
+            .bind(&value_db)
+            .execute(&mut *tx)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_pending_key_rescan(
+        &self,
+        tx: &mut SqliteConnection,
+    ) -> Result<Option<PendingKeyRescan>> {
+        let row = sqlx::query("SELECT value FROM wallet_state_keys WHERE id = 'key_rescan'")
+            .fetch_one(&mut *tx)
+            .await;
+
+        match row {
+            Ok(row) => Ok(Some(serde_json::from_str(&row.get::<String, _>(0))?)),
+            Err(sqlx::Error::RowNotFound) => Ok(None),
+            Err(err) => Err(err)?,
+        }
+    }
+
+    /// Rescan already synced blocks for keys that entered the gap window late.
+    ///
+    /// Funds found on those keys may grow the window again, so this runs until it is stable.
+    pub async fn rescan_new_keys(&self, archival: &FakeArchivalState) -> Result<()> {
+        loop {
+            let mut conn = self.pool.acquire().await?;
+            let Some(pending) = self.get_pending_key_rescan(&mut *conn).await? else {
+                return Ok(());
+            };
+            drop(conn);
+
+            info!(
+                "rescan blocks {}..={} for new keys {:?}",
+                self.scan_config.start_height, pending.end_height, pending.keys
+            );
+
+            let before = self.key_window();
+            let range = Range {
+                start: self.scan_config.start_height,
+                end: pending.end_height + 1,
+            };
+            let result = self
+                .rescan_blocks(archival, range, pending.keys.clone())
+                .await?;
+            info!("rescan for new keys done: {:?}", result);
+
+            let mut tx = self.pool.begin().await?;
+            sqlx::query("DELETE FROM wallet_state_keys WHERE id = 'key_rescan'")
+                .execute(&mut *tx)
+                .await?;
+            self.record_key_window_growth(&mut *tx, before, pending.end_height)
+                .await?;
+            tx.commit().await?;
+        }
+    }
+
+    /// Walk `range` looking only for `keys` and merge new UTXOs and spends into the wallet.
+    ///
+    /// Blocks above the wallet tip are skipped, the tip itself is not touched.
+    pub async fn rescan_blocks(
+        &self,
+        archival: &FakeArchivalState,
+        range: Range<u64>,
+        keys: RescanKeys,
+    ) -> Result<RescanResult> {
+        let Some((tip_height, _)) = self.get_tip().await? else {
+            return Ok(RescanResult::default());
+        };
+        let end = range.end.min(tip_height + 1);
+
+        let generation_keys = self.get_generation_scan_keys(Range {
+            start: keys.generation.start,
+            end: keys.generation.end,
+        });
+        let symmetric_keys = self.get_symmetric_scan_keys(Range {
+            start: keys.symmetric.start,
+            end: keys.symmetric.end,
+        });
+
+        let known = self
+            .get_utxos()
+            .await?
+            .into_iter()
+            .map(|utxo| (utxo.hash, utxo.recovery_data.aocl_index))
+            .collect::<HashSet<_>>();
+
+        let mut unspent = self
+            .get_unspent_utxos()
+            .await?
+            .into_iter()
+            .map(|utxo| (utxo.recovery_data.abs_i(), utxo.id))
+            .collect_vec();
+
+        let mut found: Vec<UtxoDbData> = vec![];
+        let mut spent: Vec<(i64, UtxoBlockInfo)> = vec![];
+
+        for height in range.start..end {
+            if (height - range.start) % SYNC_BLOCK_BATCH_SIZE == 0 {
+                archival.prepare(height, SYNC_BLOCK_BATCH_SIZE).await?;
+            }
+            let block = archival
+                .get_block_by_height(height)
+                .await?
+                .with_context(|| format!("block {} not found", height))?;
+
+            let inputs = spent_index_sets(&block);
+            let block_info = block_info(&block);
+
+            mark_spent(&mut found, &inputs, &block_info);
+            unspent.retain(|(abs_i, id)| {
+                if inputs.contains(abs_i) {
+                    spent.push((*id, block_info.clone()));
+                    return false;
+                }
+                true
+            });
+
+            let transaction = &block.kernel.body.transaction_kernel();
+            let incoming = generation_keys
+                .par_iter()
+                .flat_map(|key| {
+                    let utxo = key.1.scan_for_announced_utxos(&transaction);
+                    if utxo.len() > 0 {
+                        self.num_generation_spending_keys
+                            .fetch_max(key.0, Ordering::SeqCst);
+                    }
+                    utxo
+                })
+                .chain(symmetric_keys.par_iter().flat_map(|key| {
+                    let utxo = key.1.scan_for_announced_utxos(&transaction);
+                    if utxo.len() > 0 {
+                        self.num_symmetric_keys.fetch_max(key.0, Ordering::SeqCst);
+                    }
+                    utxo
+                }))
+                .map(|v| (v.addition_record(), v))
+                .collect::<HashMap<_, _>>();
+
+            if incoming.is_empty() {
+                continue;
+            }
+
+            let MutatorSetUpdate {
+                additions: addition_records,
+                removals: _,
+            } = block.mutator_set_update();
+
+            let mut msa_state = archival.mutator_set_accumulator_before(height).await?;
+            for addition_record in &addition_records {
+                if let Some(incoming_utxo) = incoming.get(addition_record) {
+                    let recovery_data = incoming_utxo_recovery_data_from_incomming_utxo(
+                        incoming_utxo.clone(),
+                        &msa_state,
+                    );
+                    let hash = Tip5::hash(&recovery_data.utxo).to_hex();
+                    if !known.contains(&(hash.clone(), recovery_data.aocl_index)) {
+                        info!("rescan found utxo {} at height {}", hash, height);
+                        found.push(UtxoDbData {
+                            id: 0,
+                            hash,
+                            recovery_data,
+                            spent_in_block: None,
+                            confirmed_in_block: block_info.clone(),
+                            spent_height: None,
+                            confirm_height: height.try_into()?,
+                            confirmed_txid: None,
+                            spent_txid: None,
+                            frozen: false,
+                        });
+                    }
+                }
+                msa_state.add(addition_record);
+            }
+        }
+
+        self.set_num_generation_spending_keys(self.num_generation_spending_keys())
+            .await?;
+        self.set_num_symmetric_keys(self.num_symmetric_keys())
+            .await?;
+
+        let _spend_guard = self.spend_lock.lock().await;
+
+        // the new UTXOs may be spent in blocks synced after the range
+        let tip_height = self.get_tip().await?.map(|tip| tip.0).unwrap_or(tip_height);
+        if found.iter().any(|utxo| utxo.spent_in_block.is_none()) {
+            for height in end..=tip_height {
+                if (height - end) % SYNC_BLOCK_BATCH_SIZE == 0 {
+                    archival.prepare(height, SYNC_BLOCK_BATCH_SIZE).await?;
+                }
+                let block = archival
+                    .get_block_by_height(height)
+                    .await?
+                    .with_context(|| format!("block {} not found", height))?;
+                mark_spent(&mut found, &spent_index_sets(&block), &block_info(&block));
+            }
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let found = self.merge_utxos(&mut *tx, found).await?;
+        let spent_utxos = spent.len();
+        self.update_spent_utxos(&mut *tx, spent).await?;
+        tx.commit().await?;
+
+        let result = RescanResult {
+            scanned_blocks: end.saturating_sub(range.start),
+            found_utxos: found.len(),
+            spent_utxos: spent_utxos + found.iter().filter(|u| u.spent_in_block.is_some()).count(),
+        };
+
+        Ok(result)
+    }
+}
+
+fn merge_range(a: &std::ops::Range<u64>, b: &std::ops::Range<u64>) -> std::ops::Range<u64> {
+    if a.is_empty() {
+        return b.clone();
+    }
+    if b.is_empty() {
+        return a.clone();
+    }
+    a.start.min(b.start)..a.end.max(b.end)
+}
+
+fn spent_index_sets(block: &ExportedBlock) -> Vec<AbsoluteIndexSet> {
+    block
+        .kernel
+        .body
+        .transaction_kernel()
+        .inputs
+        .iter()
+        .map(|rr| rr.absolute_indices)
+        .collect_vec()
+}
+
+fn block_info(block: &ExportedBlock) -> UtxoBlockInfo {
+    UtxoBlockInfo {
+        block_height: block.kernel.header.height.into(),
+        block_digest: block.hash(),
+        timestamp: block.kernel.header.timestamp,
+    }
+}
+
+fn mark_spent(utxos: &mut [UtxoDbData], inputs: &[AbsoluteIndexSet], block_info: &UtxoBlockInfo) {
+    for utxo in utxos
+        .iter_mut()
+        .filter(|utxo| utxo.spent_in_block.is_none())
+    {
+        if inputs.contains(&utxo.recovery_data.abs_i()) {
+            utxo.spent_in_block = Some(block_info.clone());
+            utxo.spent_height = Some(block_info.block_height as i64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use neptune_privacy::api::export::Network;
+    use neptune_privacy::state::wallet::wallet_entropy::WalletEntropy;
+
+    use super::*;
+    use crate::config::wallet::ScanConfig;
+    use crate::config::wallet::WalletConfig;
+    use crate::config::wallet::WalletKey;
+    use crate::config::wallet::WatchOnlyKeys;
+    use crate::wallet::test_wallet_state;
+    use crate::wallet::WalletState;
+
+    #[test]
+    fn test_merge_range() {
+        assert_eq!(merge_range(&(0..0), &(3..5)), 3..5);
+        assert_eq!(merge_range(&(3..5), &(7..7)), 3..5);
+        assert_eq!(merge_range(&(3..5), &(4..9)), 3..9);
+        assert_eq!(merge_range(&(6..9), &(1..2)), 1..9);
+    }
+
+    #[tokio::test]
+    async fn test_check_rescan_keys() {
+        let wallet_state = test_wallet_state().await;
+        let keys = |generation, symmetric| RescanKeys {
+            generation,
+            symmetric,
+        };
+
+        // window (5, 5) plus ten windows of 5 keys
+        assert!(wallet_state.check_rescan_keys(&keys(0..55, 0..0)).is_ok());
+        assert!(wallet_state.check_rescan_keys(&keys(50..55, 7..7)).is_ok());
+        assert!(wallet_state.check_rescan_keys(&keys(0..56, 0..0)).is_err());
+        assert!(wallet_state
+            .check_rescan_keys(&keys(0..5, 0..u64::MAX))
+            .is_err());
+        assert!(wallet_state.check_rescan_keys(&keys(3..2, 0..0)).is_err());
+    }
+
+    async fn record_and_get(
+        wallet_state: &WalletState,
+        before: (u64, u64),
+        height: u64,
+    ) -> Option<PendingKeyRescan> {
+        // the in-memory database lives in a single connection, do not hold it across calls
+        let mut conn = wallet_state.pool.acquire().await.unwrap();
+        wallet_state
+            .record_key_window_growth(&mut conn, before, height)
+            .await
+            .unwrap();
+        wallet_state
+            .get_pending_key_rescan(&mut conn)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_record_key_window_growth() {
+        let wallet_state = test_wallet_state().await;
+
+        // no growth, nothing to rescan
+        let before = wallet_state.key_window();
+        assert_eq!(before, (5, 5));
+        assert!(record_and_get(&wallet_state, before, 10).await.is_none());
+
+        wallet_state
+            .set_num_generation_spending_keys(3)
+            .await
+            .unwrap();
+        let pending = record_and_get(&wallet_state, before, 10).await.unwrap();
+        assert_eq!(pending.keys.generation, 5..8);
+        assert!(pending.keys.symmetric.is_empty());
+        assert_eq!(pending.end_height, 10);
+
+        // a second growth merges into the pending rescan
+        let before = wallet_state.key_window();
+        wallet_state.set_num_symmetric_keys(2).await.unwrap();
+        let pending = record_and_get(&wallet_state, before, 20).await.unwrap();
+        assert_eq!(pending.keys.generation, 5..8);
+        assert_eq!(pending.keys.symmetric, 5..7);
+        assert_eq!(pending.end_height, 20);
+    }
+
+    #[tokio::test]
+    async fn test_watch_only_key_window_capped() {
+        let keys = WatchOnlyKeys::from_entropy(&WalletEntropy::devnet_wallet(), 7).unwrap();
+        let config = WalletConfig {
+            id: 0,
+            key: WalletKey::WatchOnly(keys),
+            scan_config: ScanConfig {
+                num_keys: 5,
+                start_height: 0,
+            },
+            network: Network::Main,
+        };
+        let wallet_state = WalletState::new(config, &PathBuf::new()).await.unwrap();
+
+        let before = wallet_state.key_window();
+        assert_eq!(before, (5, 5));
+        wallet_state
+            .set_num_generation_spending_keys(4)
+            .await
+            .unwrap();
+        assert_eq!(wallet_state.key_window(), (7, 5));
+        assert!(wallet_state.key_window_capped());
+        let pending = record_and_get(&wallet_state, before, 10).await.unwrap();
+        assert_eq!(pending.keys.generation, 5..7);
+
+        // no keys past the exported ones, the window can not grow further
+        let before = wallet_state.key_window();
+        wallet_state
+            .set_num_generation_spending_keys(6)
+            .await
+            .unwrap();
+        assert_eq!(wallet_state.key_window(), before);
+        let pending = record_and_get(&wallet_state, before, 20).await.unwrap();
+        assert_eq!(pending.keys.generation, 5..7);
+        assert_eq!(pending.end_height, 10);
+    }
+}
@@ -0,0 +1,303 @@
+use anyhow::Result;
+use neptune_privacy::api::export::Timestamp;
+use neptune_privacy::api::export::TransactionDetails;
+use neptune_privacy::protocol::consensus::transaction::Transaction;
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::SqliteConnection;
+use tracing::*;
+
+use super::airgap::SignedTransaction;
+use super::spend::SendError;
+use super::wallet_state_table::ExpectedUtxoData;
+use crate::rpc_client;
+
+/// A proven transaction kept in the wallet so that it survives a failed broadcast.
+#[derive(Debug, Clone, Serialize)]
+pub struct SavedTransaction {
+    pub txid: String,
+    pub input_ids: Vec<i64>,
+    pub broadcasted: bool,
+    pub timestamp: Timestamp,
+    /// Why it no longer reserves its inputs, set when an input is spent by another
+    /// transaction or the server rejects it
+    pub dropped: Option<String>,
+}
+
+impl super::WalletState {
+    /// Store a proven transaction, `details` makes it re-provable once it is pending.
+    pub async fn save_proven_transaction(
+        &self,
+        signed: &SignedTransaction,
+        details: Option<&TransactionDetails>,
+    ) -> Result<()> {
+        let details = details.map(bincode::serialize).transpose()?;
+        let timestamp = Timestamp::now().to_millis() as i64;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO wallet_state_saved_txs (txid, data, details, input_ids, timestamp) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(signed.txid())
+        .bind(signed.to_bytes()?)
+        .bind(details)
+        .bind(serde_json::to_string(&signed.input_ids)?)
+        .bind(timestamp)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_saved_transaction(
+        &self,
+        txid: &str,
+    ) -> Result<Option<(SignedTransaction, Option<TransactionDetails>)>> {
+        let row = sqlx::query("SELECT data, details FROM wallet_state_saved_txs WHERE txid = ?")
+            .bind(txid)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let signed = SignedTransaction::from_bytes(&row.get::<Vec<u8>, _>("data"))?;
+        let details = row
+            .get::<Option<Vec<u8>>, _>("details")
+            .map(|details| bincode::deserialize(&details))
+            .transpose()?;
+
+        Ok(Some((signed, details)))
+    }
+
+    pub async fn saved_transactions(&self) -> Result<Vec<SavedTransaction>> {
+        let rows = sqlx::query(
+            "SELECT txid, input_ids, broadcasted, timestamp, dropped FROM wallet_state_saved_txs ORDER BY timestamp DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut saved = Vec::with_capacity(rows.len());
+        for row in rows {
+            saved.push(SavedTransaction {
+                txid: row.get("txid"),
+                input_ids: serde_json::from_str(&row.get::<String, _>("input_ids"))?,
+                broadcasted: row.get("broadcasted"),
+                timestamp: Timestamp::millis(row.get::<i64, _>("timestamp") as u64),
+                dropped: row.get("dropped"),
+            });
+        }
+
+        Ok(saved)
+    }
+
+    /// Inputs of saved transactions that were never broadcast and are not dropped, they must
+    /// not be spent twice.
+    pub async fn get_saved_spent_utxos(&self) -> Result<Vec<i64>> {
+        let rows = sqlx::query(
+            "SELECT input_ids FROM wallet_state_saved_txs WHERE broadcasted = 0 AND dropped IS NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut ids = vec![];
+        for row in rows {
+            ids.extend(serde_json::from_str::<Vec<i64>>(&row.get::<String, _>(0))?);
+        }
+
+        Ok(ids)
+    }
+
+    /// Release the inputs of a saved transaction that can not be broadcast anymore.
+    async fn drop_saved_transaction(&self, txid: &str, reason: &str) -> Result<()> {
+        warn!("dropping saved tx {}: {}", txid, reason);
+        sqlx::query("UPDATE wallet_state_saved_txs SET dropped = ? WHERE txid = ?")
+            .bind(reason)
+            .bind(txid)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Drop the saved transactions that were never broadcast and spend `utxo_id`, once the
+    /// UTXO is spent on chain.
+    pub(super) async fn drop_saved_transactions_spending(
+        &self,
+        tx: &mut SqliteConnection,
+        utxo_id: i64,
+        reason: &str,
+    ) -> Result<()> {
+        let result = sqlx::query(
+            "UPDATE wallet_state_saved_txs SET dropped = ? WHERE broadcasted = 0 AND dropped IS NULL AND EXISTS (SELECT 1 FROM json_each(input_ids) WHERE value = ?)",
+        )
+        .bind(reason)
+        .bind(utxo_id)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() > 0 {
+            info!("dropped {} saved txs: {}", result.rows_affected(), reason);
+        }
+        Ok(())
+    }
+
+    pub async fn delete_saved_transaction(&self, txid: &str) -> Result<()> {
+        sqlx::query("DELETE FROM wallet_state_saved_txs WHERE txid = ?")
+            .bind(txid)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Broadcast a transaction saved by [Self::save_proven_transaction].
+    pub async fn broadcast_saved_transaction(&self, txid: &str) -> Result<Transaction, SendError> {
+        let _spend_guard = self.spend_lock.lock().await;
+
+        let (signed, details) = self
+            .get_saved_transaction(txid)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("no saved transaction {}", txid))?;
+
+        self.broadcast_proven_transaction(signed, details).await
+    }
+
+    /// Broadcast, then register the expected UTXOs, the pending inputs and the payments.
+    ///
+    /// The caller must hold the spend lock and have saved the transaction, it stays saved if
+    /// the broadcast fails. It is dropped if it does not spend unspent wallet UTXOs anymore
+    /// or the server rejects it.
+    pub(super) async fn broadcast_proven_transaction(
+        &self,
+        signed: SignedTransaction,
+        details: Option<TransactionDetails>,
+    ) -> Result<Transaction, SendError> {
+        let now = Timestamp::now();
+
+        let transaction = match self.check_signed_transaction(&signed).await {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                self.drop_saved_transaction(&signed.txid(), &format!("{:#}", e))
+                    .await?;
+                return Err(e.into());
+            }
+        };
+
+        let txid = match rpc_client::node_rpc_client()
+            .broadcast_transaction(&transaction)
+            .await
+        {
+            Ok(txid) => txid,
+            Err(source) => {
+                if source.is_rejected() {
+                    self.drop_saved_transaction(&signed.txid(), &source.to_string())
+                        .await?;
+                } else {
+                    warn!("broadcast of {} failed, kept it saved", signed.txid());
+                }
+                return Err(SendError::Saved {
+                    txid: signed.txid(),
+                    source,
+                });
+            }
+        };
+
+        if self.get_pending_transactions().await?.contains(&txid) {
+            // broadcast again, the wallet already tracks it
+            debug!("{} is already pending", txid);
+        } else {
+            self.record_sent_payments(
+                &txid,
+                &signed.payments,
+                transaction.kernel.fee,
+                signed.payment_medium,
+            )
+            .await?;
+
+            let expected_utxo_data = signed
+                .expected_utxos
+                .into_iter()
+                .map(|expected_utxo| ExpectedUtxoData {
+                    id: 0,
+                    txid: txid.clone(),
+                    expected_utxo,
+                    timestamp: now,
+                })
+                .collect();
+            self.add_expected_utxo(expected_utxo_data).await?;
+
+            match details {
+                Some(details) => {
+                    self.updater
+                        .add_transaction(txid.clone(), details, signed.input_ids)
+                        .await?
+                }
+                None => {
+                    self.updater
+                        .add_external_transaction(txid.clone(), signed.input_ids)
+                        .await?
+                }
+            }
+        }
+
+        sqlx::query(
+            "UPDATE wallet_state_saved_txs SET broadcasted = 1, dropped = NULL WHERE txid = ?",
+        )
+        .bind(&txid)
+        .execute(&self.pool)
+        .await
+        .map_err(anyhow::Error::from)?;
+
+        Ok(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::test_wallet_state;
+
+    #[tokio::test]
+    async fn test_drop_saved_transactions_spending() {
+        let wallet_state = test_wallet_state().await;
+
+        for (txid, input_ids, broadcasted) in [("a", "[1,2]", 0), ("b", "[3]", 0), ("c", "[2]", 1)]
+        {
+            sqlx::query(
+                "INSERT INTO wallet_state_saved_txs (txid, data, input_ids, broadcasted, timestamp) VALUES (?, x'00', ?, ?, 0)",
+            )
+            .bind(txid)
+            .bind(input_ids)
+            .bind(broadcasted)
+            .execute(&wallet_state.pool)
+            .await
+            .unwrap();
+        }
+        let mut reserved = wallet_state.get_saved_spent_utxos().await.unwrap();
+        reserved.sort();
+        assert_eq!(reserved, vec![1, 2, 3]);
+
+        {
+            let mut conn = wallet_state.pool.acquire().await.unwrap();
+            wallet_state
+                .drop_saved_transactions_spending(&mut conn, 2, "input 2 was spent in block 7")
+                .await
+                .unwrap();
+        }
+        assert_eq!(wallet_state.get_saved_spent_utxos().await.unwrap(), vec![3]);
+
+        let saved = wallet_state.saved_transactions().await.unwrap();
+        let dropped = |txid: &str| {
+            saved
+                .iter()
+                .find(|saved| saved.txid == txid)
+                .unwrap()
+                .dropped
+                .clone()
+        };
+        assert_eq!(
+            dropped("a").as_deref(),
+            Some("input 2 was spent in block 7")
+        );
+        assert_eq!(dropped("b"), None);
+        // already broadcast, nothing to release
+        assert_eq!(dropped("c"), None);
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Context;
+use anyhow::Result;
+use neptune_privacy::api::export::Timestamp;
+use neptune_privacy::application::rest_server::ExportedBlock;
+use tokio::select;
+use tracing::*;
+
+use super::progress::SyncPhase;
+use super::sync::SyncState;
+use super::sync_manager::SyncManager;
+use super::validate::validate_block;
+
+/// How often the rest servers are health checked and their tip polled for the sync progress.
+const SERVER_TIP_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+impl SyncManager {
+    /// Spawn the scan loop feeding blocks to all wallets of this manager.
+    pub(super) async fn spawn_scanner(self: &Arc<Self>) {
+        let manager = self.clone();
+        let task = tokio::spawn(async move {
+            let mut tip_refreshed: Option<Instant> = None;
+            loop {
+                if manager.cancel.load(Ordering::Relaxed) {
+                    info!("scan canceled");
+                    return;
+                }
+
+                if tip_refreshed.is_none_or(|at| at.elapsed() > SERVER_TIP_REFRESH_INTERVAL) {
+                    tip_refreshed = Some(Instant::now());
+                    let archival = manager.fake_archival_state.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = archival.refresh_server_tip().await {
+                            warn!("refresh server tip error: {:?}", e);
+                        }
+                    });
+                }
+
+                let progressed = manager.scan_round().await;
+
+                if !progressed {
+                    select! {
+                        _ = tokio::time::sleep(Duration::from_secs(1)) => {},
+                        _ = manager.waker.notified() => {},
+                    }
+                }
+            }
+        });
+
+        self.handler.lock().await.replace(task);
+    }
+
+    /// Advance every ready wallet by one block.
+    ///
+    /// Wallets waiting for the same height share a single fetched and decoded block,
+    /// returns whether any wallet moved forward.
+    async fn scan_round(&self) -> bool {
+        let _round = self.round_lock.lock().await;
+
+        let wallets = self
+            .wallets
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for state in wallets.iter().filter(|state| state.needs_init()) {
+            if let Err(e) = state.init().await {
+                error!("sync error: {:?}", e);
+                state.retry_after(Duration::from_secs(5));
+            }
+        }
+
+        let now = Timestamp::now().to_millis();
+        let mut groups: BTreeMap<u64, Vec<Arc<SyncState>>> = BTreeMap::new();
+        for state in wallets {
+            if state.poll_ready(now) {
+                groups.entry(state.next_height()).or_default().push(state);
+            }
+        }
+
+        let mut progressed = false;
+        for (height, states) in groups {
+            if self.cancel.load(Ordering::Relaxed) {
+                break;
+            }
+
+            match self.scan_height(height, &states).await {
+                Ok(true) => progressed = true,
+                Ok(false) => {}
+                Err(e) => {
+                    error!("sync height error: {:?}", e);
+                    for state in states {
+                        state.retry_after(Duration::from_secs(5));
+                    }
+                }
+            }
+        }
+
+        progressed
+    }
+
+    /// Fetch and validate the block at `height` once and apply it to every wallet in `states`.
+    async fn scan_height(&self, height: u64, states: &[Arc<SyncState>]) -> Result<bool> {
+        info!("syncing block {height} for {} wallets", states.len());
+
+        for state in states {
+            state.progress.set_phase(SyncPhase::Fetching);
+        }
+        let fetch_started = Instant::now();
+        let block = self.fetch_block(height).await;
+        for state in states {
+            state
+                .progress
+                .add_time(SyncPhase::Fetching, fetch_started.elapsed());
+            state.progress.set_phase(SyncPhase::Idle);
+        }
+
+        let Some(block) = block? else {
+            for state in states {
+                if let Err(e) = state.reached_tip().await {
+                    error!("sync error: {:?}", e);
+                    state.retry_after(Duration::from_secs(5));
+                }
+            }
+            return Ok(false);
+        };
+
+        debug!("get block done: {}", height);
+
+        // validated once here, every wallet applies the same block
+        let mutator_set_accumulator_before = self
+            .fake_archival_state
+            .mutator_set_accumulator_before(height)
+            .await?;
+        let mutator_set_accumulator_after =
+            match validate_block(&block, &mutator_set_accumulator_before) {
+                Ok(msa) => msa,
+                Err(invalid) => {
+                    self.fake_archival_state
+                        .quarantine(&block, invalid.to_string())
+                        .await
+                        .context("quarantine block error")?;
+                    return Err(invalid.into());
+                }
+            };
+
+        let results = futures::future::join_all(
+            states
+                .iter()
+                .map(|state| state.apply_block(&block, &mutator_set_accumulator_after)),
+        )
+        .await;
+
+        for (state, result) in states.iter().zip(results) {
+            if let Err(e) = result {
+                error!("sync wallet {} error: {:?}", state.wallet.id, e);
+                state.retry_after(Duration::from_secs(5));
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn fetch_block(&self, height: u64) -> Result<Option<ExportedBlock>> {
+        self.fake_archival_state
+            .prefetch(height)
+            .await
+            .context("prefetch blocks error")?;
+
+        debug!("getting block {height}");
+
+        self.fake_archival_state
+            .get_block_by_height(height)
+            .await
+            .context("get block error")
+    }
+}
@@ -20,25 +20,101 @@ use neptune_privacy::state::wallet::utxo_notification::UtxoNotificationMedium;
 use neptune_privacy::state::wallet::utxo_notification::UtxoNotificationMethod;
 use neptune_privacy::util_types::mutator_set::mutator_set_accumulator::MutatorSetAccumulator;
 use num_traits::CheckedSub;
+use num_traits::Zero;
 use thiserror::Error;
 use tracing::*;
 
+use super::airgap::SignedTransaction;
 use super::input::InputSelectionRule;
 use crate::prover::ProofBuilder;
-use crate::rpc_client;
 use crate::rpc_client::BroadcastError;
-use crate::wallet::wallet_state_table::ExpectedUtxoData;
 
 impl super::WalletState {
     pub async fn send_to_address(
         &self,
-        outputs: Vec<(ReceivingAddress, NativeCurrencyAmount)>,
+        outputs: Vec<(ReceivingAddress, NativeCurrencyAmount, Option<Timestamp>)>,
         utxo_notification_media: (UtxoNotificationMedium, UtxoNotificationMedium),
         fee: NativeCurrencyAmount,
         rule: InputSelectionRule,
         must_include_utxos: Vec<i64>,
     ) -> anyhow::Result<Transaction, SendError> {
+        if self.is_watch_only() {
+            return Err(SendError::WatchOnly);
+        }
+
+        let _spend_guard = self.spend_lock.lock().await;
+
+        let (tx_inputs, db_ids, tip_msa, tip_height) = self
+            .create_input(&outputs, fee, rule, must_include_utxos)
+            .await?;
+
+        self.prove_and_broadcast(
+            outputs,
+            utxo_notification_media,
+            fee,
+            tx_inputs,
+            db_ids,
+            tip_msa,
+            tip_height,
+        )
+        .await
+    }
+
+    /// Send every spendable UTXO, or the `utxos` among them, to `address`.
+    ///
+    /// The fee is deducted from the swept amount and there is no change output.
+    pub async fn sweep_to_address(
+        &self,
+        address: ReceivingAddress,
+        release_date: Option<Timestamp>,
+        utxo_notification_media: (UtxoNotificationMedium, UtxoNotificationMedium),
+        fee: NativeCurrencyAmount,
+        utxos: Vec<i64>,
+    ) -> anyhow::Result<Transaction, SendError> {
+        if self.is_watch_only() {
+            return Err(SendError::WatchOnly);
+        }
+
         let _spend_guard = self.spend_lock.lock().await;
+
+        let (inputs, db_ids) = self.select_sweep_inputs(utxos).await?;
+        let total: NativeCurrencyAmount = inputs
+            .iter()
+            .map(|input| input.utxo.get_native_currency_amount())
+            .sum();
+        let amount = total
+            .checked_sub(&fee)
+            .filter(|amount| *amount > NativeCurrencyAmount::zero())
+            .ok_or_else(|| {
+                anyhow::anyhow!("swept amount {} does not cover the fee {}", total, fee)
+            })?;
+        info!("sweeping {} from {} utxos", amount, db_ids.len());
+
+        let (tx_inputs, tip_msa, tip_height) = self.unlock_utxos(inputs).await?;
+
+        self.prove_and_broadcast(
+            vec![(address, amount, release_date)],
+            utxo_notification_media,
+            fee,
+            tx_inputs,
+            db_ids,
+            tip_msa,
+            tip_height,
+        )
+        .await
+    }
+
+    #[expect(clippy::too_many_arguments)]
+    async fn prove_and_broadcast(
+        &self,
+        outputs: Vec<(ReceivingAddress, NativeCurrencyAmount, Option<Timestamp>)>,
+        utxo_notification_media: (UtxoNotificationMedium, UtxoNotificationMedium),
+        fee: NativeCurrencyAmount,
+        tx_inputs: Vec<UnlockedUtxo>,
+        db_ids: Vec<i64>,
+        tip_msa: MutatorSetAccumulator,
+        tip_height: BlockHeight,
+    ) -> anyhow::Result<Transaction, SendError> {
         let now = Timestamp::now();
         let tx_proving_capability = TxProvingCapability::ProofCollection;
 
@@ -53,7 +129,7 @@ impl super::WalletState {
 
         // obtain next unused symmetric key for change utxo
         let change_key = {
-            let symmetric_key = self.key.nth_symmetric_key(0);
+            let symmetric_key = self.key.entropy()?.nth_symmetric_key(0);
             let spending_key = SpendingKey::Symmetric(symmetric_key);
             // self.set_num_symmetric_keys(self.num_symmetric_keys() + 1)
             //     .await?;
@@ -66,10 +142,6 @@ impl super::WalletState {
             "stmi: step 2. generate outputs.",
         );
 
-        let (tx_inputs, db_ids, tip_msa, tip_height) = self
-            .create_input(&outputs, fee, rule, must_include_utxos)
-            .await?;
-
         let tx_outputs = self
             .generate_tx_outputs(
                 outputs.clone(),
@@ -77,7 +149,7 @@ impl super::WalletState {
                 unowned_utxo_notification_medium,
                 tip_height,
             )
-            .await;
+            .await?;
 
         let _ =
             crate::service::app::emit_event_to("main", "send_state", "stmi: step 3. create tx.");
@@ -117,60 +189,52 @@ impl super::WalletState {
 
         let utxos_sent_to_self = self.extract_expected_utxos(&full_outputs, UtxoNotifier::Myself);
 
-        let _ = crate::service::app::emit_event_to(
-            "main",
-            "send_state",
-            "stmi: step 5. broadcast transaction.",
-        );
-
-        let txid = rpc_client::node_rpc_client()
-            .broadcast_transaction(&transaction)
+        // keep the proof in case the broadcast fails
+        let signed = SignedTransaction {
+            network: self.network,
+            transaction: (&transaction)
+                .try_into()
+                .map_err(|e| anyhow::anyhow!("transaction is not transferable: {:?}", e))?,
+            input_ids: db_ids,
+            expected_utxos: utxos_sent_to_self,
+            payments: outputs,
+            payment_medium: unowned_utxo_notification_medium,
+        };
+        self.save_proven_transaction(&signed, Some(&transaction_details))
             .await?;
 
         let _ = crate::service::app::emit_event_to(
             "main",
             "send_state",
-            "stmi: step 5. save to updater.",
+            "stmi: step 5. broadcast transaction.",
         );
 
-        let expected_utxo_data = utxos_sent_to_self
-            .into_iter()
-            .map(|expected_utxo| ExpectedUtxoData {
-                id: 0,
-                txid: txid.clone(),
-                expected_utxo,
-                timestamp: now,
-            })
-            .collect();
-        self.add_expected_utxo(expected_utxo_data).await?;
-
-        self.updater
-            .add_transaction(txid.clone(), transaction_details, db_ids)
-            .await?;
-
-        Ok(transaction)
+        self.broadcast_proven_transaction(signed, Some(transaction_details))
+            .await
     }
 
     pub async fn generate_tx_outputs(
         &self,
-        outputs: impl IntoIterator<Item = (ReceivingAddress, NativeCurrencyAmount)>,
+        outputs: impl IntoIterator<Item = (ReceivingAddress, NativeCurrencyAmount, Option<Timestamp>)>,
         owned_utxo_notify_medium: UtxoNotificationMedium,
         unowned_utxo_notify_medium: UtxoNotificationMedium,
         block_height: BlockHeight,
-    ) -> TxOutputList {
+    ) -> anyhow::Result<TxOutputList> {
+        let entropy = self.key.entropy()?;
+
         // Convert outputs.  [address:amount] --> TxOutputList
         let tx_outputs: Vec<_> = outputs
             .into_iter()
-            .map(|(address, amount)| {
-                let sender_randomness = self
-                    .key
-                    .generate_sender_randomness(block_height, address.privacy_digest());
+            .map(|(address, amount, release_date)| {
+                let sender_randomness =
+                    entropy.generate_sender_randomness(block_height, address.privacy_digest());
 
                 // The UtxoNotifyMethod (Onchain or Offchain) is auto-detected
                 // based on whether the address belongs to our wallet or not
                 self.auto_outputs(
                     address,
                     amount,
+                    release_date,
                     sender_randomness,
                     owned_utxo_notify_medium,
                     unowned_utxo_notify_medium,
@@ -178,25 +242,31 @@ impl super::WalletState {
             })
             .collect();
 
-        tx_outputs.into()
+        Ok(tx_outputs.into())
     }
 
+    /// Whether the UTXO belongs to one of the used keys, also for watch-only wallets.
     pub fn can_unlock(&self, utxo: &Utxo) -> bool {
-        self.get_known_spending_keys()
+        self.get_known_scan_keys()
             .iter()
-            .find(|k| k.lock_script_hash() == utxo.lock_script_hash())
-            .is_some()
+            .any(|k| k.lock_script_hash() == utxo.lock_script_hash())
     }
 
+    /// Output paying `amount` to `address`, time-locked until `release_date` if given.
     pub fn auto_outputs(
         &self,
         address: ReceivingAddress,
         amount: NativeCurrencyAmount,
+        release_date: Option<Timestamp>,
         sender_randomness: Digest,
         owned_utxo_notify_medium: UtxoNotificationMedium,
         unowned_utxo_notify_medium: UtxoNotificationMedium,
     ) -> TxOutput {
         let utxo = Utxo::new_native_currency(address.lock_script_hash(), amount);
+        let utxo = match release_date {
+            Some(release_date) => utxo.with_time_lock(release_date),
+            None => utxo,
+        };
 
         let has_matching_spending_key = self.can_unlock(&utxo);
 
@@ -299,6 +369,7 @@ impl super::WalletState {
         let receiver_digest = own_receiving_address.privacy_digest();
         let change_sender_randomness = {
             self.key
+                .entropy()?
                 .generate_sender_randomness(tip_height, receiver_digest)
         };
 
@@ -427,4 +498,11 @@ pub enum SendError {
     Proof(#[from] anyhow::Error),
     #[error(transparent)]
     Broadcast(#[from] BroadcastError),
+    #[error("broadcast failed, proven transaction {txid} is saved: {source}")]
+    Saved {
+        txid: String,
+        source: BroadcastError,
+    },
+    #[error("watch-only wallet can not spend")]
+    WatchOnly,
 }
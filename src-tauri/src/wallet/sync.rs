@@ -1,109 +1,164 @@
+use std::range::Range;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicI8;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use neptune_privacy::api::export::Timestamp;
-use neptune_privacy::protocol::consensus::block::Block;
+use neptune_privacy::application::rest_server::ExportedBlock;
 use neptune_privacy::util_types::mutator_set::mutator_set_accumulator::MutatorSetAccumulator;
 use serde::Serialize;
-use tokio::select;
 use tokio::sync::Mutex;
 use tokio::sync::Notify;
-use tokio::task::JoinHandle;
 use tracing::*;
 
 use super::fake_archival_state::FakeArchivalState;
-use super::fake_archival_state::SnapshotReader;
+use super::paranoid::SyncAlert;
+use super::progress::PhaseTimings;
+use super::progress::SyncPhase;
+use super::progress::SyncProgress;
+use super::rescan::RescanKeys;
+use super::rescan::RescanResult;
+use super::validate::QuarantinedBlock;
 use super::WalletState;
 use crate::config::Config;
-use crate::wallet::block_cache::BlockCacheImpl;
 
 const SYNC_STOPPED: i8 = 0;
 const SYNC_SYNCING: i8 = 1;
 const SYNC_PAUSED: i8 = 2;
 const SYNC_WAIT_PAUSE: i8 = 3;
 
+/// Initial batch size of the block prefetcher.
 pub const SYNC_BLOCK_BATCH_SIZE: u64 = 50;
+const TIP_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// In paranoid mode one in this many synced blocks is cross checked with a second server.
+const PARANOID_SAMPLE_INTERVAL: u64 = 500;
+
+/// Per-wallet sync progress, the blocks are fed by the scanner of [super::sync_manager::SyncManager].
 pub struct SyncState {
+    /// The next height to scan.
     height: AtomicU64,
     updated_to_tip: AtomicI8,
     syncing: AtomicI8,
     fake_archival_state: FakeArchivalState,
     pub wallet: super::WalletState,
-    cancel: AtomicI8,
-    /// Used to notify the sync task to wake up and check for new blocks.
-    waker: Notify,
-    handler: Mutex<Option<JoinHandle<()>>>,
+    previous_mutator_set_accumulator: Mutex<MutatorSetAccumulator>,
+    /// Timestamp in millis before which the scanner skips this wallet.
+    next_check: AtomicU64,
+    /// Used to notify the scanner to wake up and check for new blocks.
+    waker: Arc<Notify>,
+    /// Only the wallet selected in the GUI emits sync events.
+    active: AtomicBool,
+    last_sync_event_time: AtomicU64,
+    pub(super) progress: SyncProgress,
+    /// Notified when the sync is paused.
+    paused: Notify,
+    /// Held while already synced blocks are rescanned, outside of the scanner rounds.
+    rescan_lock: Arc<Mutex<()>>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct SyncStatus {
+    pub wallet_id: i64,
     pub height: u64,
     pub syncing: bool,
     pub updated_to_tip: bool,
+    /// Tip height of the rest server, if known.
+    pub server_tip_height: Option<u64>,
+    /// Average over the last minute.
+    pub blocks_per_sec: f64,
+    /// Estimated seconds until the server tip is reached.
+    pub eta_secs: Option<u64>,
+    pub phase: SyncPhase,
+    /// The snapshot read time is shared by all wallets.
+    pub phase_timings: PhaseTimings,
+    /// Blocks that failed validation, shared by all wallets.
+    pub quarantined_blocks: Vec<QuarantinedBlock>,
+    /// Set when a second server disagreed with a synced block, sync is paused.
+    pub alert: Option<SyncAlert>,
 }
 
-static LAST_SYNC_EVENT_TIME: AtomicU64 = AtomicU64::new(0);
-
 impl SyncState {
-    pub async fn new(config: &Config) -> Result<Self> {
-        let wallet = WalletState::new_from_config(&config).await?;
-        let data_dir = config.get_data_dir().await?;
-        let snapshot_reader = match SnapshotReader::new(&data_dir).await {
-            Ok(v) => {
-                debug!("snapshot reader created : {:?}", v);
-                Some(v)
-            }
-            Err(e) => {
-                error!("failed to create snapshot reader: {:#?}", e);
-                None
-            }
-        };
-
-        let block_cache = if config.get_disk_cache().await? {
-            info!("disk cache enabled");
-            BlockCacheImpl::new_persist(&data_dir, config.get_network().await?, 200).await?
-        } else {
-            warn!("disk cache is disabled, this will cause performance issues");
-            BlockCacheImpl::new_memory(200)
-        };
+    pub async fn new(
+        config: &Config,
+        wallet_id: i64,
+        fake_archival_state: FakeArchivalState,
+        waker: Arc<Notify>,
+    ) -> Result<Self> {
+        let wallet = WalletState::new_from_config(&config, wallet_id).await?;
 
         Ok(Self {
             height: AtomicU64::new(0),
             updated_to_tip: AtomicI8::new(0),
             syncing: AtomicI8::new(0),
-            fake_archival_state: FakeArchivalState::new(
-                block_cache,
-                wallet.network,
-                snapshot_reader,
-            ),
+            fake_archival_state,
             wallet,
-            cancel: AtomicI8::new(0),
-            waker: Notify::new(),
-            handler: Mutex::new(None),
+            previous_mutator_set_accumulator: Mutex::new(MutatorSetAccumulator::default()),
+            next_check: AtomicU64::new(0),
+            waker,
+            active: AtomicBool::new(false),
+            last_sync_event_time: AtomicU64::new(0),
+            progress: SyncProgress::new(),
+            paused: Notify::new(),
+            rescan_lock: Arc::new(Mutex::new(())),
         })
     }
 
+    pub fn set_active(&self, active: bool) {
+        self.active.store(active, Ordering::Relaxed);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
     pub async fn status(&self) -> SyncStatus {
+        let height = self.height.load(Ordering::SeqCst);
+        let server_tip_height = self.fake_archival_state.server_tip_height();
+        let blocks_per_sec = self.progress.blocks_per_sec();
+        let eta_secs = server_tip_height
+            .filter(|_| blocks_per_sec > 0.0)
+            .map(|tip| ((tip + 1).saturating_sub(height) as f64 / blocks_per_sec) as u64);
+
+        let mut phase = self.progress.phase();
+        if phase == SyncPhase::Fetching && self.fake_archival_state.is_reading_snapshot() {
+            phase = SyncPhase::SnapshotRead;
+        }
+        let mut phase_timings = self.progress.timings();
+        phase_timings.snapshot_read_ms = self.fake_archival_state.snapshot_read_time();
+
         return SyncStatus {
-            height: self.height.load(Ordering::SeqCst),
+            wallet_id: self.wallet.id,
+            height,
             syncing: self.syncing.load(Ordering::SeqCst) != 0,
             updated_to_tip: self.updated_to_tip.load(Ordering::SeqCst) != 0,
+            server_tip_height,
+            blocks_per_sec,
+            eta_secs,
+            phase,
+            phase_timings,
+            quarantined_blocks: self.fake_archival_state.quarantined(),
+            alert: self.wallet.get_sync_alert().await.unwrap_or_default(),
         };
     }
 
     pub async fn reset_to_height(&self, height: u64) -> Result<()> {
         if self.syncing.load(Ordering::Relaxed) != SYNC_PAUSED {
             self.syncing.store(SYNC_WAIT_PAUSE, Ordering::Relaxed);
+            self.waker.notify_one();
             loop {
+                let paused = self.paused.notified();
+                tokio::pin!(paused);
+                paused.as_mut().enable();
                 if self.syncing.load(Ordering::Relaxed) == SYNC_PAUSED {
                     break;
                 }
-                tokio::time::sleep(Duration::from_millis(100)).await;
+                paused.await;
             }
         }
 
@@ -120,195 +175,142 @@ impl SyncState {
                 .await?;
             tx.commit().await?;
             self.fake_archival_state.reset_to_height(height).await?;
-            self.height.store(height + 1, Ordering::Relaxed);
             Ok::<(), anyhow::Error>(())
         };
 
         let result = task.await;
-        self.syncing.store(SYNC_SYNCING, Ordering::Relaxed);
+        // reload tip and mutator set from the wallet
+        self.syncing.store(SYNC_STOPPED, Ordering::Relaxed);
+        self.next_check.store(0, Ordering::Relaxed);
         self.waker.notify_one();
 
         result
     }
 
-    pub async fn sync(self: Arc<Self>) {
-        let self_clone = self.clone();
-        let task = tokio::spawn(async move {
-            loop {
-                match self_clone.sync_inner().await {
-                    Err(e) => {
-                        error!("sync error: {:?}", e);
-                        tokio::time::sleep(Duration::from_secs(5)).await;
-                    }
-                    Ok(_) => {
-                        break;
-                    }
-                }
-            }
-        });
+    /// Resume a sync paused by a [SyncAlert], e.g. after the server list was fixed.
+    pub async fn clear_alert(&self) -> Result<()> {
+        self.wallet.clear_sync_alert().await?;
+        if self.syncing.load(Ordering::Relaxed) == SYNC_PAUSED {
+            self.syncing.store(SYNC_STOPPED, Ordering::Relaxed);
+        }
+        self.next_check.store(0, Ordering::Relaxed);
+        self.waker.notify_one();
+        Ok(())
+    }
+
+    /// Rescan `range` for `keys` without moving the wallet tip.
+    pub async fn rescan(&self, range: Range<u64>, keys: RescanKeys) -> Result<RescanResult> {
+        let _rescan = self.rescan_lock.lock().await;
+        self.wallet
+            .rescan_blocks(&self.fake_archival_state, range, keys)
+            .await
+    }
 
-        self.handler.lock().await.replace(task);
+    pub fn next_height(&self) -> u64 {
+        self.height.load(Ordering::Relaxed)
     }
 
-    async fn sync_inner(&self) -> Result<()> {
+    pub(super) fn needs_init(&self) -> bool {
+        self.syncing.load(Ordering::Relaxed) == SYNC_STOPPED
+    }
+
+    /// Load the wallet tip and the mutator set after it, scanning resumes from the next block.
+    pub(super) async fn init(&self) -> Result<()> {
         let start = self.wallet.start_height().await?;
         debug!("start set to: {start}");
 
-        let mut previous_mutator_set_accumulator = match start {
-            0 => MutatorSetAccumulator::default(),
-            1 => Block::genesis(self.wallet.network).mutator_set_accumulator_after()?,
-            _ => {
-                let context = format!(
-                    "Prev block does not exist. Could not get block with height {}",
-                    start - 1
-                );
-                let block = self
-                    .fake_archival_state
-                    .get_block_by_height(start - 1)
-                    .await?
-                    .context(context)?;
-                block.mutator_set_accumulator_after()
-            }
-        };
+        let previous_mutator_set_accumulator = self
+            .fake_archival_state
+            .mutator_set_accumulator_before(start)
+            .await?;
 
         self.update(if start > 1 { start - 1 } else { start });
         self.height.store(start, Ordering::Relaxed);
+        *self.previous_mutator_set_accumulator.lock().await = previous_mutator_set_accumulator;
+
+        if let Some(alert) = self.wallet.get_sync_alert().await? {
+            warn!(
+                "sync of wallet {} paused by alert: {:?}",
+                self.wallet.id, alert
+            );
+            self.pause();
+            return Ok(());
+        }
 
-        if let Err(e) = self
-            .fake_archival_state
-            .prepare(
-                start - (start % SYNC_BLOCK_BATCH_SIZE),
-                SYNC_BLOCK_BATCH_SIZE,
-            )
-            .await
-        {
-            error!("prepare blocks error: {:?}", e);
-        };
+        let _ = self.syncing.compare_exchange(
+            SYNC_STOPPED,
+            SYNC_SYNCING,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
 
-        self.syncing.store(1, Ordering::Relaxed);
+        Ok(())
+    }
 
-        loop {
-            match self
-                .sync_height(&mut previous_mutator_set_accumulator)
-                .await
-            {
-                Ok(duration) => {
-                    if let Some(duration) = duration {
-                        self.syncing.store(SYNC_PAUSED, Ordering::Relaxed);
-                        select! {
-                            _ = tokio::time::sleep(duration) => {
-                                self.syncing.store(SYNC_SYNCING, Ordering::Relaxed);
-                            },
-                            _ = self.waker.notified()=>{
-                                if self.cancel.load(Ordering::Relaxed) != 0 {
-                                    info!("scan canceled");
-                                    self.syncing.store(SYNC_STOPPED, Ordering::Relaxed);
-                                    return Ok(());
-                                }
-                                self.syncing.store(SYNC_SYNCING, Ordering::Relaxed);
-                            }
-                        }
-                    } else {
-                        if self.cancel.load(Ordering::Relaxed) != 0 {
-                            info!("scan canceled");
-                            self.syncing.store(SYNC_STOPPED, Ordering::Relaxed);
-                            return Ok(());
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("sync height error: {:?}", e);
-                    select! {
-                        _ = tokio::time::sleep(Duration::from_secs(5)) => {
-                        },
-                        _ = self.waker.notified()=>{
-                            if self.cancel.load(Ordering::Relaxed) != 0 {
-                                info!("scan canceled");
-                                self.syncing.store(SYNC_STOPPED, Ordering::Relaxed);
-                                return Ok(());
-                            }
-                        }
-                    }
-                }
+    /// Whether the scanner may feed this wallet a block now.
+    ///
+    /// A pause requested by [Self::reset_to_height] is acknowledged here.
+    pub(super) fn poll_ready(&self, now: u64) -> bool {
+        match self.syncing.load(Ordering::Relaxed) {
+            SYNC_SYNCING => now >= self.next_check.load(Ordering::Relaxed),
+            SYNC_WAIT_PAUSE => {
+                self.pause();
+                false
             }
+            _ => false,
         }
     }
-    async fn sync_height(
-        &self,
-        previous_mutator_set_accumulator: &mut MutatorSetAccumulator,
-    ) -> Result<Option<Duration>> {
-        if self.syncing.load(Ordering::Relaxed) != SYNC_SYNCING {
-            self.syncing.store(SYNC_PAUSED, Ordering::Relaxed);
-            return Ok(Some(Duration::from_secs(1)));
-        }
-
-        let current_height = self.height.load(Ordering::Relaxed);
-        info!("syncing block {current_height}");
-
-        if current_height > 0 && (current_height - 1) % SYNC_BLOCK_BATCH_SIZE == 0 {
-            debug!("prepare blocks: {}", current_height);
-            self.fake_archival_state
-                .prepare(current_height, SYNC_BLOCK_BATCH_SIZE)
-                .await
-                .context("prepare blocks error")?;
-            debug!("prepare blocks done: {}", current_height);
-        }
-
-        debug!("getting block {current_height}");
 
-        let current_block = match self
-            .fake_archival_state
-            .get_block_by_height(current_height)
-            .await
-            .context("get block error")?
-        {
-            Some(block) => {
-                self.syncing_new_tip(block.kernel.header.height.into());
-                block
-            }
-            None => {
-                debug!("block {current_height} not found");
-                {
-                    //update balance after sync
-                    let balance = self.wallet.get_balance().await?;
-                    let config = crate::service::get_state::<Arc<Config>>();
-                    config
-                        .update_wallet_balance(self.wallet.id, balance.display_lossless())
-                        .await?;
-                }
-                if self.updated_to_tip.load(Ordering::Relaxed) == 0 {
-                    info!("updated to tip, waiting for new block {}", current_height);
-                }
-                self.updated_to_tip(current_height);
-                return Ok(Some(Duration::from_secs(60)));
-            }
-        };
-
-        debug!("get block done: {}", current_height);
+    /// Delay the next scan of this wallet, used after errors and when waiting for a new block.
+    pub(super) fn retry_after(&self, duration: Duration) {
+        let next = Timestamp::now().to_millis() + duration.as_millis() as u64;
+        self.next_check.store(next, Ordering::Relaxed);
+    }
 
-        let current_mutator_set_accumulator = current_block.mutator_set_accumulator_after();
+    /// Apply a block that the scanner fetched for all wallets at this height.
+    ///
+    /// The scanner validated the block, `mutator_set_accumulator_after` is shared by all wallets.
+    pub(super) async fn apply_block(
+        &self,
+        block: &ExportedBlock,
+        mutator_set_accumulator_after: &MutatorSetAccumulator,
+    ) -> Result<()> {
+        let current_height: u64 = block.kernel.header.height.into();
+        ensure!(
+            current_height == self.next_height(),
+            "block {} is not the next block {}",
+            current_height,
+            self.next_height()
+        );
+        self.syncing_new_tip(current_height);
 
         debug!("update wallet state with new block: {}", current_height);
 
         let mut should_update = self.updated_to_tip.load(Ordering::Relaxed) == 1;
         if should_update {
-            if (Timestamp::now() - current_block.kernel.header.timestamp).as_duration()
+            if (Timestamp::now() - block.kernel.header.timestamp).as_duration()
                 > Duration::from_secs(26 * 60)
             {
                 should_update = false
             }
         }
 
-        if let Some(fork) = self
+        let mut previous_mutator_set_accumulator =
+            self.previous_mutator_set_accumulator.lock().await;
+
+        let fork = self
             .wallet
             .update_new_tip(
-                &previous_mutator_set_accumulator,
-                &current_block,
+                &mut previous_mutator_set_accumulator,
+                block,
+                mutator_set_accumulator_after,
                 should_update,
+                &self.progress,
             )
             .await
-            .context("update wallet state error")?
-        {
+            .context("update wallet state error")?;
+
+        if let Some(fork) = fork {
             info!("fork at height: {}", fork);
 
             let fork_block = self
@@ -320,12 +322,14 @@ impl SyncState {
             *previous_mutator_set_accumulator = fork_block.mutator_set_accumulator_after();
 
             self.update(fork);
+            // only the blocks this wallet applied from the orphaned chain, the block just
+            // fetched and the ones cached for other wallets are still good
             self.fake_archival_state
-                .reset_to_height(fork)
+                .drop_orphaned_blocks(fork + 1, current_height)
                 .await
-                .context("reset to height error")?;
+                .context("drop orphaned blocks error")?;
             self.height.store(fork + 1, Ordering::Relaxed);
-            return Ok(None);
+            return Ok(());
         }
 
         debug!(
@@ -333,44 +337,125 @@ impl SyncState {
             current_height
         );
 
-        *previous_mutator_set_accumulator = current_mutator_set_accumulator;
-
         let now = Timestamp::now().to_millis();
-        if now - LAST_SYNC_EVENT_TIME.load(Ordering::Relaxed) > 100 {
+        if now - self.last_sync_event_time.load(Ordering::Relaxed) > 100 {
             self.update(current_height);
-            LAST_SYNC_EVENT_TIME.store(now, Ordering::Relaxed);
+            self.last_sync_event_time.store(now, Ordering::Relaxed);
         }
         self.height.store(current_height + 1, Ordering::Relaxed);
+        self.progress.synced(current_height);
+
+        if rand::random_range(0..PARANOID_SAMPLE_INTERVAL) == 0 {
+            self.cross_check(current_height).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Called by the scanner when the next block of this wallet does not exist yet.
+    pub(super) async fn reached_tip(self: &Arc<Self>) -> Result<()> {
+        let current_height = self.next_height();
+        debug!("block {current_height} not found");
+        self.retry_after(TIP_POLL_INTERVAL);
 
-        Ok(None)
+        if current_height > 0 {
+            self.cross_check(current_height - 1).await?;
+        }
+
+        self.spawn_rescan_new_keys();
+
+        if self.updated_to_tip.load(Ordering::Relaxed) == 0 {
+            info!("updated to tip, waiting for new block {}", current_height);
+        }
+        self.updated_to_tip(current_height);
+        Ok(())
+    }
+
+    /// Rescan for the keys discovered while syncing and update the balance.
+    ///
+    /// The rescan may walk the whole chain, it runs in a task of its own so the scanner
+    /// keeps syncing the other wallets.
+    fn spawn_rescan_new_keys(self: &Arc<Self>) {
+        let Ok(rescan) = self.rescan_lock.clone().try_lock_owned() else {
+            debug!("wallet {} is still rescanning", self.wallet.id);
+            return;
+        };
+
+        let state = self.clone();
+        tokio::spawn(async move {
+            let _rescan = rescan;
+            let result = async {
+                state
+                    .wallet
+                    .rescan_new_keys(&state.fake_archival_state)
+                    .await
+                    .context("rescan new keys error")?;
+
+                //update balance after sync
+                let balance = state.wallet.get_balance().await?;
+                let config = crate::service::get_state::<Arc<Config>>();
+                config
+                    .update_wallet_balance(state.wallet.id, balance.display_lossless())
+                    .await?;
+                Ok::<(), anyhow::Error>(())
+            }
+            .await;
+            if let Err(e) = result {
+                error!(
+                    "wallet {} error after reaching tip: {:?}",
+                    state.wallet.id, e
+                );
+            }
+        });
+    }
+
+    fn pause(&self) {
+        self.syncing.store(SYNC_PAUSED, Ordering::Relaxed);
+        self.paused.notify_waiters();
+    }
+
+    /// In paranoid mode compare the block at `height` with a second server,
+    /// a mismatch pauses the sync until the alert is cleared.
+    async fn cross_check(&self, height: u64) -> Result<()> {
+        let config = crate::service::get_state::<Arc<Config>>();
+        if !config.get_paranoid_mode().await? {
+            return Ok(());
+        }
+
+        let alert = match self.wallet.cross_check_height(height).await {
+            Ok(alert) => alert,
+            Err(e) => {
+                warn!("cross check block {} error: {:?}", height, e);
+                return Ok(());
+            }
+        };
+
+        if let Some(alert) = alert {
+            self.pause();
+            if self.is_active() {
+                let _ = crate::service::app::emit_event_to("main", "sync_alert", alert);
+            }
+        }
+        Ok(())
     }
 
     fn update(&self, height: u64) {
         self.updated_to_tip.store(0, Ordering::Relaxed);
-        let _ = crate::service::app::emit_event_to("main", "sync_height", height);
+        if self.is_active() {
+            let _ = crate::service::app::emit_event_to("main", "sync_height", height);
+        }
     }
 
     fn updated_to_tip(&self, height: u64) {
         self.updated_to_tip.store(1, Ordering::Relaxed);
-        let _ = crate::service::app::emit_event_to("main", "sync_finish", height);
+        if self.is_active() {
+            let _ = crate::service::app::emit_event_to("main", "sync_finish", height);
+        }
     }
 
     fn syncing_new_tip(&self, height: u64) {
-        let _ = crate::service::app::emit_event_to("main", "syncing_new_block", height);
-    }
-
-    pub async fn cancel_sync(&self) {
-        self.cancel.store(1, Ordering::Relaxed);
-        self.waker.notify_waiters();
-
-        if let Some(mut handler) = self.handler.lock().await.take() {
-            match tokio::time::timeout(Duration::from_secs(5), &mut handler).await {
-                Ok(_) => {}
-                Err(_) => {
-                    warn!("cancel timeout after 5s");
-                    handler.abort();
-                }
-            };
+        if self.is_active() {
+            let _ = crate::service::app::emit_event_to("main", "syncing_new_block", height);
         }
     }
 }
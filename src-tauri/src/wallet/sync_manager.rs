@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use tokio::sync::Mutex;
+use tokio::sync::Notify;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::*;
+
+use super::block_cache::BlockCacheImpl;
+use super::fake_archival_state::FakeArchivalState;
+use super::fake_archival_state::SnapshotReader;
+use super::prefetch::PREFETCH_WINDOW;
+use super::sync::SyncState;
+use super::sync::SyncStatus;
+use super::sync::SYNC_BLOCK_BATCH_SIZE;
+use crate::config::Config;
+
+const MIN_BLOCK_CACHE_SIZE: usize = 200;
+
+/// Keeps one [SyncState] per row of the `wallets` table syncing in the background.
+///
+/// All wallets share a single [FakeArchivalState] and a single scan loop, so every
+/// block is downloaded, cached and decoded once no matter how many wallets are being synced.
+pub struct SyncManager {
+    config: Arc<Config>,
+    pub(super) fake_archival_state: FakeArchivalState,
+    pub(super) wallets: RwLock<HashMap<i64, Arc<SyncState>>>,
+    /// The wallet selected in the GUI, used when no wallet id is given.
+    current: AtomicI64,
+    pub(super) cancel: AtomicBool,
+    /// Used to notify the scanner to wake up and check for new blocks.
+    pub(super) waker: Arc<Notify>,
+    pub(super) handler: Mutex<Option<JoinHandle<()>>>,
+    /// Held by the scanner while a round is applied, so a removed wallet is no longer touched.
+    pub(super) round_lock: Mutex<()>,
+    /// Serializes [Self::add_wallet] so a wallet database is opened once, readers of
+    /// `wallets` are not blocked meanwhile.
+    add_lock: Mutex<()>,
+}
+
+impl SyncManager {
+    pub async fn new(config: Arc<Config>) -> Result<Self> {
+        let data_dir = config.get_data_dir().await?;
+        let network = config.get_network().await?;
+
+        let snapshot_reader = match SnapshotReader::new(&data_dir).await {
+            Ok(v) => {
+                debug!("snapshot reader created : {:?}", v);
+                Some(v)
+            }
+            Err(e) => {
+                error!("failed to create snapshot reader: {:#?}", e);
+                None
+            }
+        };
+
+        // every wallet may keep a couple of batches in the memory cache, on top of the
+        // blocks prefetched ahead of the scanner
+        let num_wallets = config.get_wallet_ids().await?.len();
+        let cache_size = MIN_BLOCK_CACHE_SIZE.max(num_wallets * 2 * SYNC_BLOCK_BATCH_SIZE as usize)
+            + PREFETCH_WINDOW as usize;
+
+        let block_cache = if config.get_disk_cache().await? {
+            info!("disk cache enabled");
+            BlockCacheImpl::new_persist(&data_dir, network, cache_size).await?
+        } else {
+            warn!("disk cache is disabled, this will cause performance issues");
+            BlockCacheImpl::new_memory(cache_size)
+        };
+
+        let current = config.get_wallet_id().await?;
+
+        Ok(Self {
+            config,
+            fake_archival_state: FakeArchivalState::new(block_cache, network, snapshot_reader),
+            wallets: RwLock::new(HashMap::new()),
+            current: AtomicI64::new(current),
+            cancel: AtomicBool::new(false),
+            waker: Arc::new(Notify::new()),
+            handler: Mutex::new(None),
+            round_lock: Mutex::new(()),
+            add_lock: Mutex::new(()),
+        })
+    }
+
+    /// Start syncing every configured wallet.
+    pub async fn start(self: &Arc<Self>) -> Result<()> {
+        for id in self.config.get_wallet_ids().await? {
+            if let Err(e) = self.add_wallet(id).await {
+                error!("failed to start sync for wallet {}: {:?}", id, e);
+            }
+        }
+        self.spawn_scanner().await;
+        Ok(())
+    }
+
+    /// Start syncing the wallet with `id`, returns the running state if it is already syncing.
+    pub async fn add_wallet(&self, id: i64) -> Result<Arc<SyncState>> {
+        let _adding = self.add_lock.lock().await;
+        if let Some(state) = self.wallets.read().await.get(&id) {
+            return Ok(state.clone());
+        }
+
+        info!("start syncing wallet {}", id);
+        let state = SyncState::new(
+            &self.config,
+            id,
+            self.fake_archival_state.clone(),
+            self.waker.clone(),
+        )
+        .await
+        .with_context(|| format!("create sync state for wallet {}", id))?;
+        let state = Arc::new(state);
+        state.set_active(id == self.current_id());
+        let state = self
+            .wallets
+            .write()
+            .await
+            .entry(id)
+            .or_insert(state)
+            .clone();
+
+        self.waker.notify_one();
+
+        Ok(state)
+    }
+
+    /// Stop syncing the wallet with `id`.
+    pub async fn remove_wallet(&self, id: i64) {
+        let state = self.wallets.write().await.remove(&id);
+        if state.is_some() {
+            info!("stop syncing wallet {}", id);
+            // wait for the round that may still be applying a block to it
+            let _round = self.round_lock.lock().await;
+        }
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Arc<SyncState>> {
+        self.wallets
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .with_context(|| format!("wallet {} is not syncing", id))
+    }
+
+    pub async fn current(&self) -> Result<Arc<SyncState>> {
+        self.get(self.current_id()).await
+    }
+
+    pub fn current_id(&self) -> i64 {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// Select the wallet that emits GUI events and serves requests without a wallet id.
+    pub async fn set_current(&self, id: i64) -> Result<()> {
+        self.current.store(id, Ordering::Relaxed);
+        for (wallet_id, state) in self.wallets.read().await.iter() {
+            state.set_active(*wallet_id == id);
+        }
+        self.add_wallet(id).await?;
+        Ok(())
+    }
+
+    pub async fn statuses(&self) -> Vec<SyncStatus> {
+        let wallets = self
+            .wallets
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut statuses = Vec::with_capacity(wallets.len());
+        for state in wallets {
+            statuses.push(state.status().await);
+        }
+        statuses.sort_by_key(|s| s.wallet_id);
+        statuses
+    }
+
+    /// The lowest height any wallet has synced to, blocks above it are still needed.
+    pub async fn min_height(&self) -> u64 {
+        self.statuses()
+            .await
+            .iter()
+            .map(|s| s.height)
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub async fn cancel_all(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.waker.notify_waiters();
+        self.fake_archival_state.cancel_prefetch();
+
+        if let Some(mut handler) = self.handler.lock().await.take() {
+            match tokio::time::timeout(Duration::from_secs(5), &mut handler).await {
+                Ok(_) => {}
+                Err(_) => {
+                    warn!("cancel timeout after 5s");
+                    handler.abort();
+                }
+            };
+        }
+
+        self.wallets.write().await.clear();
+    }
+}
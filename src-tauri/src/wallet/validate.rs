@@ -0,0 +1,201 @@
+use neptune_privacy::application::rest_server::ExportedBlock;
+use neptune_privacy::prelude::tasm_lib::prelude::Digest;
+use neptune_privacy::protocol::proof_abstractions::mast_hash::MastHash;
+use neptune_privacy::util_types::mutator_set::mutator_set_accumulator::MutatorSetAccumulator;
+use serde::Serialize;
+use thiserror::Error;
+
+/// A downloaded block that must not reach the wallet database.
+#[derive(Debug, Error)]
+pub enum BlockValidationError {
+    #[error("requested block {requested} but got block {height}")]
+    UnexpectedHeight { requested: u64, height: u64 },
+    #[error("block {height} is not followed by block {next}")]
+    HeightGap { height: u64, next: u64 },
+    #[error("block {height} does not chain to the previous block {prev}")]
+    BrokenChain { height: u64, prev: String },
+    #[error("block {height} body MAST hash {body} does not match its header {header}")]
+    BodyMismatch {
+        height: u64,
+        header: String,
+        body: String,
+    },
+    #[error("block {height} mutator set update can not be applied: {reason}")]
+    InvalidMutatorSetUpdate { height: u64, reason: String },
+    #[error("block {height} mutator set accumulator does not match its mutator set update")]
+    MutatorSetMismatch { height: u64 },
+    #[error("block {height} {digest} does not carry more work than the local tip")]
+    InsufficientWork { height: u64, digest: String },
+}
+
+impl BlockValidationError {
+    /// Height of the block that failed.
+    pub fn height(&self) -> u64 {
+        match self {
+            Self::UnexpectedHeight { height, .. } => *height,
+            Self::HeightGap { next, .. } => *next,
+            Self::BrokenChain { height, .. }
+            | Self::BodyMismatch { height, .. }
+            | Self::InvalidMutatorSetUpdate { height, .. }
+            | Self::MutatorSetMismatch { height }
+            | Self::InsufficientWork { height, .. } => *height,
+        }
+    }
+}
+
+/// A block that failed validation, kept for inspection.
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantinedBlock {
+    pub height: u64,
+    pub digest: String,
+    pub reason: String,
+    pub timestamp: u64,
+}
+
+/// Height, previous block digest and digest of a block.
+type ChainLink = (u64, Digest, Digest);
+
+/// Check that a downloaded batch starts at `height` and forms a single chain.
+pub fn validate_batch(height: u64, blocks: &[ExportedBlock]) -> Result<(), BlockValidationError> {
+    if let Some(first) = blocks.first() {
+        let first_height: u64 = first.kernel.header.height.into();
+        if first_height != height {
+            return Err(BlockValidationError::UnexpectedHeight {
+                requested: height,
+                height: first_height,
+            });
+        }
+    }
+    validate_chain(blocks)
+}
+
+/// Check that a batch of consecutive blocks forms a single chain.
+fn validate_chain(blocks: &[ExportedBlock]) -> Result<(), BlockValidationError> {
+    let links = blocks
+        .iter()
+        .map(|block| {
+            (
+                block.kernel.header.height.into(),
+                block.kernel.header.prev_block_digest,
+                block.hash(),
+            )
+        })
+        .collect::<Vec<ChainLink>>();
+    validate_links(&links)
+}
+
+fn validate_links(links: &[ChainLink]) -> Result<(), BlockValidationError> {
+    for pair in links.windows(2) {
+        let (height, _, digest) = pair[0];
+        let (next, prev_digest, _) = pair[1];
+        if next != height + 1 {
+            return Err(BlockValidationError::HeightGap { height, next });
+        }
+        if prev_digest != digest {
+            return Err(BlockValidationError::BrokenChain {
+                height: next,
+                prev: digest.to_hex(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check that the body is the one the header commits to.
+fn validate_body(height: u64, header: Digest, body: Digest) -> Result<(), BlockValidationError> {
+    if header != body {
+        return Err(BlockValidationError::BodyMismatch {
+            height,
+            header: header.to_hex(),
+            body: body.to_hex(),
+        });
+    }
+    Ok(())
+}
+
+/// Check a block against the accumulator after its parent, returns the accumulator after
+/// the block.
+pub fn validate_block(
+    block: &ExportedBlock,
+    mutator_set_accumulator_before: &MutatorSetAccumulator,
+) -> Result<MutatorSetAccumulator, BlockValidationError> {
+    let height: u64 = block.kernel.header.height.into();
+
+    validate_body(
+        height,
+        block.kernel.header.body_mast_hash,
+        block.kernel.body.mast_hash(),
+    )?;
+
+    let mut msa = mutator_set_accumulator_before.clone();
+    block
+        .mutator_set_update()
+        .apply_to_accumulator(&mut msa)
+        .map_err(|e| BlockValidationError::InvalidMutatorSetUpdate {
+            height,
+            reason: format!("{:?}", e),
+        })?;
+    if msa.hash() != block.mutator_set_accumulator_after().hash() {
+        return Err(BlockValidationError::MutatorSetMismatch { height });
+    }
+
+    Ok(msa)
+}
+
+#[cfg(test)]
+mod tests {
+    use neptune_privacy::api::export::Tip5;
+
+    use super::*;
+
+    fn chain(heights: std::ops::Range<u64>) -> Vec<ChainLink> {
+        heights
+            .map(|height| (height, Tip5::hash(&(height - 1)), Tip5::hash(&height)))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_links() {
+        assert!(validate_links(&[]).is_ok());
+        assert!(validate_links(&chain(5..10)).is_ok());
+
+        let mut gap = chain(5..10);
+        gap.remove(2);
+        assert!(matches!(
+            validate_links(&gap),
+            Err(BlockValidationError::HeightGap { height: 6, next: 8 })
+        ));
+        // the block after the gap is the one quarantined
+        assert_eq!(validate_links(&gap).unwrap_err().height(), 8);
+
+        let mut broken = chain(5..10);
+        broken[3].1 = Tip5::hash(&100u64);
+        match validate_links(&broken) {
+            Err(BlockValidationError::BrokenChain { height, prev }) => {
+                assert_eq!(height, 8);
+                assert_eq!(prev, Tip5::hash(&7u64).to_hex());
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_body() {
+        let body = Tip5::hash(&7u64);
+        assert!(validate_body(7, body, body).is_ok());
+
+        let tampered = Tip5::hash(&8u64);
+        match validate_body(7, body, tampered) {
+            Err(BlockValidationError::BodyMismatch {
+                height,
+                header,
+                body: got,
+            }) => {
+                assert_eq!(height, 7);
+                assert_eq!(header, body.to_hex());
+                assert_eq!(got, tampered.to_hex());
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
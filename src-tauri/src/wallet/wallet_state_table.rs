@@ -15,6 +15,7 @@ use sqlx_migrator::Migrator;
 use sqlx_migrator::Plan;
 use tracing::info;
 
+use super::balance::amount_limbs;
 use super::UtxoRecoveryData;
 use super::WalletState;
 
@@ -84,6 +85,205 @@ sqlx_migrator::sqlite_migration!(
     )]
 );
 
+struct CreateWalletStateHeadersMigration;
+sqlx_migrator::sqlite_migration!(
+    CreateWalletStateHeadersMigration,
+    "wallet_state",
+    "create_wallet_state_headers",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        "CREATE TABLE wallet_state_headers (
+            height INTEGER PRIMARY KEY,
+            digest TEXT NOT NULL,
+            prev_digest TEXT NOT NULL,
+            cumulative_pow TEXT NOT NULL
+        )",
+        "DROP TABLE wallet_state_headers"
+    )]
+);
+
+struct CreateWalletStateSavedTxsMigration;
+sqlx_migrator::sqlite_migration!(
+    CreateWalletStateSavedTxsMigration,
+    "wallet_state",
+    "create_wallet_state_saved_txs",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        "CREATE TABLE wallet_state_saved_txs (
+            txid TEXT PRIMARY KEY,
+            data BLOB NOT NULL,
+            details BLOB,
+            input_ids TEXT NOT NULL,
+            broadcasted INTEGER NOT NULL DEFAULT 0,
+            timestamp INTEGER NOT NULL
+        )",
+        "DROP TABLE wallet_state_saved_txs"
+    )]
+);
+
+struct AddWalletStateUtxoCoinControlMigration;
+sqlx_migrator::sqlite_migration!(
+    AddWalletStateUtxoCoinControlMigration,
+    "wallet_state",
+    "add_wallet_state_utxo_coin_control",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        "ALTER TABLE wallet_state_utxos ADD COLUMN frozen INTEGER NOT NULL DEFAULT 0",
+        "ALTER TABLE wallet_state_utxos DROP COLUMN frozen"
+    )]
+);
+
+/// Labels of transactions, addresses and UTXOs, UTXOs are keyed by hash.
+struct CreateWalletStateLabelsMigration;
+sqlx_migrator::sqlite_migration!(
+    CreateWalletStateLabelsMigration,
+    "wallet_state",
+    "create_wallet_state_labels",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        "CREATE TABLE wallet_state_labels (
+            type TEXT NOT NULL,
+            ref TEXT NOT NULL,
+            label TEXT NOT NULL,
+            PRIMARY KEY (type, ref)
+        )",
+        "DROP TABLE wallet_state_labels"
+    )]
+);
+
+struct CreateWalletStateSentPaymentsMigration;
+sqlx_migrator::sqlite_migration!(
+    CreateWalletStateSentPaymentsMigration,
+    "wallet_state",
+    "create_wallet_state_sent_payments",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![
+        (
+            "CREATE TABLE wallet_state_sent_payments (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                txid TEXT NOT NULL,
+                address TEXT NOT NULL,
+                amount TEXT NOT NULL,
+                fee TEXT NOT NULL,
+                medium TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                confirm_height INTEGER DEFAULT NULL
+            )",
+            "DROP TABLE wallet_state_sent_payments"
+        ),
+        (
+            "CREATE INDEX wallet_state_sent_payments_txid ON wallet_state_sent_payments (txid)",
+            "DROP INDEX wallet_state_sent_payments_txid"
+        )
+    ]
+);
+
+struct AddWalletStateSentPaymentsReleaseDateMigration;
+sqlx_migrator::sqlite_migration!(
+    AddWalletStateSentPaymentsReleaseDateMigration,
+    "wallet_state",
+    "add_wallet_state_sent_payments_release_date",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        "ALTER TABLE wallet_state_sent_payments ADD COLUMN release_date INTEGER DEFAULT NULL",
+        "ALTER TABLE wallet_state_sent_payments DROP COLUMN release_date"
+    )]
+);
+
+/// Amounts as 32 bit limbs so that SQLite can sum them exactly, see [super::balance].
+struct AddWalletStateUtxoAmountsMigration;
+sqlx_migrator::sqlite_migration!(
+    AddWalletStateUtxoAmountsMigration,
+    "wallet_state",
+    "add_wallet_state_utxo_amounts",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![
+        (
+            "ALTER TABLE wallet_state_utxos ADD COLUMN amount_0 INTEGER DEFAULT NULL",
+            "ALTER TABLE wallet_state_utxos DROP COLUMN amount_0"
+        ),
+        (
+            "ALTER TABLE wallet_state_utxos ADD COLUMN amount_1 INTEGER DEFAULT NULL",
+            "ALTER TABLE wallet_state_utxos DROP COLUMN amount_1"
+        ),
+        (
+            "ALTER TABLE wallet_state_utxos ADD COLUMN amount_2 INTEGER DEFAULT NULL",
+            "ALTER TABLE wallet_state_utxos DROP COLUMN amount_2"
+        ),
+        (
+            "ALTER TABLE wallet_state_utxos ADD COLUMN amount_3 INTEGER DEFAULT NULL",
+            "ALTER TABLE wallet_state_utxos DROP COLUMN amount_3"
+        ),
+        (
+            "ALTER TABLE wallet_state_utxos ADD COLUMN confirm_timestamp INTEGER DEFAULT NULL",
+            "ALTER TABLE wallet_state_utxos DROP COLUMN confirm_timestamp"
+        ),
+        (
+            "ALTER TABLE wallet_state_utxos ADD COLUMN spent_timestamp INTEGER DEFAULT NULL",
+            "ALTER TABLE wallet_state_utxos DROP COLUMN spent_timestamp"
+        ),
+        (
+            "CREATE INDEX wallet_state_utxos_confirm_height ON wallet_state_utxos (confirm_height)",
+            "DROP INDEX wallet_state_utxos_confirm_height"
+        ),
+        (
+            "CREATE INDEX wallet_state_utxos_spent_height ON wallet_state_utxos (spent_height)",
+            "DROP INDEX wallet_state_utxos_spent_height"
+        )
+    ]
+);
+
+struct AddWalletStateUtxoAoclIndexMigration;
+
+sqlx_migrator::sqlite_migration!(
+    AddWalletStateUtxoAoclIndexMigration,
+    "wallet_state",
+    "add_wallet_state_utxo_aocl_index",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        // filled by backfill_utxo_aocl_indices
+        "ALTER TABLE wallet_state_utxos ADD COLUMN aocl_index INTEGER DEFAULT NULL",
+        "ALTER TABLE wallet_state_utxos DROP COLUMN aocl_index"
+    )]
+);
+
+/// A UTXO found again by an overlapping rescan is not stored twice.
+///
+/// Rows stored before the AOCL index column have none yet, NULLs never conflict, their
+/// duplicates are dropped by [WalletState::backfill_utxo_aocl_indices].
+struct AddWalletStateUtxoHashAoclIndexMigration;
+
+sqlx_migrator::sqlite_migration!(
+    AddWalletStateUtxoHashAoclIndexMigration,
+    "wallet_state",
+    "add_wallet_state_utxo_hash_aocl_index",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![(
+        "CREATE UNIQUE INDEX IF NOT EXISTS wallet_state_utxos_hash_aocl_index ON wallet_state_utxos (hash, aocl_index)",
+        "DROP INDEX wallet_state_utxos_hash_aocl_index"
+    )]
+);
+
+/// Why a saved transaction that was never broadcast no longer reserves its inputs.
+struct AddWalletStateSavedTxsDroppedMigration;
+
+sqlx_migrator::sqlite_migration!(
+    AddWalletStateSavedTxsDroppedMigration,
+    "wallet_state",
+    "add_wallet_state_saved_txs_dropped",
+    sqlx_migrator::vec_box![],
+    sqlx_migrator::vec_box![
+        (
+            "ALTER TABLE wallet_state_saved_txs ADD COLUMN dropped TEXT DEFAULT NULL",
+            "ALTER TABLE wallet_state_saved_txs DROP COLUMN dropped"
+        ),
+        (
+            "UPDATE wallet_state_saved_txs SET dropped = 'an input was spent' WHERE broadcasted = 0 AND EXISTS (SELECT 1 FROM json_each(input_ids) i JOIN wallet_state_utxos u ON u.id = i.value WHERE u.spent_in_block IS NOT NULL)",
+            "UPDATE wallet_state_saved_txs SET dropped = NULL"
+        )
+    ]
+);
+
 #[derive(Debug, Clone, Serialize)]
 pub struct UtxoDbData {
     pub id: i64,
@@ -101,6 +301,9 @@ pub struct UtxoDbData {
 
     pub confirmed_txid: Option<String>,
     pub spent_txid: Option<String>,
+
+    /// Frozen UTXOs are never picked by automatic input selection
+    pub frozen: bool,
 }
 
 impl UtxoDbData {}
@@ -142,27 +345,42 @@ impl UtxoDbData {
             spent_height: row.get("spent_height"),
             confirmed_txid: row.get("confirmed_txid"),
             spent_txid: row.get("spent_txid"),
+            frozen: row.get("frozen"),
         })
     }
 
-    pub async fn create<'c, E>(&self, executor: E) -> anyhow::Result<()>
+    /// Insert the UTXO, returns false if it is already stored.
+    pub async fn create<'c, E>(&self, executor: E) -> anyhow::Result<bool>
     where
         E: sqlx::Executor<'c, Database = Sqlite>,
     {
-        let query = "INSERT INTO wallet_state_utxos (hash, recovery_data, confirmed_in_block, confirm_height) VALUES (?, ?, ?, ?)";
+        let query = "INSERT OR IGNORE INTO wallet_state_utxos (hash, recovery_data, confirmed_in_block, confirm_height, amount_0, amount_1, amount_2, amount_3, confirm_timestamp, aocl_index) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
 
         let data = bincode::serialize(&self.recovery_data)?;
 
         let confirmed_in_block = serde_json::to_string(&self.confirmed_in_block)?;
 
-        sqlx::query(query)
+        let [amount_0, amount_1, amount_2, amount_3] = amount_limbs(
+            self.recovery_data
+                .utxo
+                .get_native_currency_amount()
+                .to_nau(),
+        );
+
+        let res = sqlx::query(query)
             .bind(&self.hash)
             .bind(&data)
             .bind(&confirmed_in_block)
             .bind(&self.confirm_height)
+            .bind(amount_0)
+            .bind(amount_1)
+            .bind(amount_2)
+            .bind(amount_3)
+            .bind(self.confirmed_in_block.timestamp.to_millis() as i64)
+            .bind(self.recovery_data.aocl_index as i64)
             .execute(executor)
             .await?;
-        Ok(())
+        Ok(res.rows_affected() > 0)
     }
 
     pub fn display_pretty(&self) -> String {
@@ -231,20 +449,120 @@ impl ExpectedUtxoData {
     }
 }
 
+fn wallet_state_migrator() -> Result<Migrator<Sqlite>> {
+    let mut migrator = Migrator::default();
+    // Adding migration can fail if another migration with same app and name and different values gets added
+    // Adding migrations add its parents, replaces and not before as well
+    migrator.add_migration(Box::new(CreateWalletStateNumKeysMigration))?;
+    migrator.add_migration(Box::new(CreateWalletStateUtxosMigration))?;
+    migrator.add_migration(Box::new(CreateWalletStateExpectedUtxoMigration))?;
+    migrator.add_migration(Box::new(CreateWalletStateKnownRawHashKeysMigration))?;
+    migrator.add_migration(Box::new(CreateWalletStateHeadersMigration))?;
+    migrator.add_migration(Box::new(CreateWalletStateSavedTxsMigration))?;
+    migrator.add_migration(Box::new(AddWalletStateUtxoCoinControlMigration))?;
+    migrator.add_migration(Box::new(CreateWalletStateLabelsMigration))?;
+    migrator.add_migration(Box::new(CreateWalletStateSentPaymentsMigration))?;
+    migrator.add_migration(Box::new(AddWalletStateUtxoAmountsMigration))?;
+    migrator.add_migration(Box::new(AddWalletStateSentPaymentsReleaseDateMigration))?;
+    migrator.add_migration(Box::new(AddWalletStateUtxoAoclIndexMigration))?;
+    migrator.add_migration(Box::new(AddWalletStateSavedTxsDroppedMigration))?;
+    migrator.add_migration(Box::new(AddWalletStateUtxoHashAoclIndexMigration))?;
+
+    Ok(migrator)
+}
+
 impl WalletState {
     pub async fn migrate_tables(&self) -> anyhow::Result<()> {
-        let mut migrator = Migrator::default();
-        // Adding migration can fail if another migration with same app and name and different values gets added
-        // Adding migrations add its parents, replaces and not before as well
-        migrator.add_migration(Box::new(CreateWalletStateNumKeysMigration))?;
-        migrator.add_migration(Box::new(CreateWalletStateUtxosMigration))?;
-        migrator.add_migration(Box::new(CreateWalletStateExpectedUtxoMigration))?;
-        migrator.add_migration(Box::new(CreateWalletStateKnownRawHashKeysMigration))?;
+        let migrator = wallet_state_migrator()?;
 
         let mut conn = self.pool.acquire().await?;
         // use apply all to apply all pending migration
         migrator.run(&mut *conn, &Plan::apply_all()).await?;
 
+        self.backfill_utxo_amounts().await?;
+        self.backfill_utxo_aocl_indices().await?;
+
+        Ok(())
+    }
+
+    /// Fill the AOCL index column and drop duplicates inserted by overlapping rescans.
+    ///
+    /// The first copy of a UTXO gets its index, the unique index leaves later copies
+    /// without one and they are deleted.
+    async fn backfill_utxo_aocl_indices(&self) -> Result<()> {
+        let rows =
+            sqlx::query("SELECT * FROM wallet_state_utxos WHERE aocl_index IS NULL ORDER BY id")
+                .fetch_all(&self.pool)
+                .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        for row in rows {
+            let utxo = UtxoDbData::from_row(row)?;
+            sqlx::query("UPDATE OR IGNORE wallet_state_utxos SET aocl_index = ? WHERE id = ?")
+                .bind(utxo.recovery_data.aocl_index as i64)
+                .bind(utxo.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        let removed = sqlx::query("DELETE FROM wallet_state_utxos WHERE aocl_index IS NULL")
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if removed > 0 {
+            info!("removed {} duplicate utxos", removed);
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Fill the amount and timestamp columns of UTXOs stored before they existed.
+    ///
+    /// Spends seen while syncing never stored their height, it is taken from the spending block.
+    async fn backfill_utxo_amounts(&self) -> Result<()> {
+        let rows = sqlx::query("SELECT * FROM wallet_state_utxos WHERE amount_0 IS NULL")
+            .fetch_all(&self.pool)
+            .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+        info!("backfilling amounts of {} utxos", rows.len());
+
+        let mut tx = self.pool.begin().await?;
+        for row in rows {
+            let utxo = UtxoDbData::from_row(row)?;
+            let [amount_0, amount_1, amount_2, amount_3] = amount_limbs(
+                utxo.recovery_data
+                    .utxo
+                    .get_native_currency_amount()
+                    .to_nau(),
+            );
+            sqlx::query(
+                "UPDATE wallet_state_utxos SET amount_0 = ?, amount_1 = ?, amount_2 = ?, amount_3 = ?, confirm_timestamp = ?, spent_timestamp = ?, spent_height = COALESCE(spent_height, ?) WHERE id = ?",
+            )
+            .bind(amount_0)
+            .bind(amount_1)
+            .bind(amount_2)
+            .bind(amount_3)
+            .bind(utxo.confirmed_in_block.timestamp.to_millis() as i64)
+            .bind(
+                utxo.spent_in_block
+                    .as_ref()
+                    .map(|block| block.timestamp.to_millis() as i64),
+            )
+            .bind(
+                utxo.spent_in_block
+                    .map(|block| block.block_height as i64),
+            )
+            .bind(utxo.id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
         Ok(())
     }
 
@@ -344,6 +662,38 @@ impl WalletState {
         Ok(())
     }
 
+    /// Insert UTXOs found by a rescan together with their spent state, returns the ones
+    /// that were not stored yet.
+    pub async fn merge_utxos<'c>(
+        &self,
+        tx: &'c mut SqliteConnection,
+        utxos: Vec<UtxoDbData>,
+    ) -> Result<Vec<UtxoDbData>> {
+        let mut inserted = vec![];
+        for utxo in utxos {
+            let tx = &mut *tx;
+            // an overlapping rescan or sync may have stored it since the scan started
+            if !utxo.create(&mut *tx).await? {
+                continue;
+            }
+
+            if let Some(spent_in_block) = &utxo.spent_in_block {
+                let info = serde_json::to_string(spent_in_block)?;
+                sqlx::query::<Sqlite>(
+                    "UPDATE wallet_state_utxos SET spent_in_block = ?, spent_height = ?, spent_timestamp = ? WHERE id = last_insert_rowid()",
+                )
+                .bind(&info)
+                .bind(&utxo.spent_height)
+                .bind(spent_in_block.timestamp.to_millis() as i64)
+                .execute(&mut *tx)
+                .await?;
+            }
+            inserted.push(utxo);
+        }
+
+        Ok(inserted)
+    }
+
     pub async fn update_spent_utxos<'c>(
         &self,
         tx: &'c mut SqliteConnection,
@@ -352,15 +702,28 @@ impl WalletState {
         for utxo in &utxos {
             let info = serde_json::to_string(&utxo.1)?;
 
-            sqlx::query::<Sqlite>("UPDATE wallet_state_utxos SET spent_in_block = ? WHERE id = ?")
+            sqlx::query::<Sqlite>(
+                "UPDATE wallet_state_utxos SET spent_in_block = ?, spent_height = ?, spent_timestamp = ? WHERE id = ?",
+            )
                 .bind(&info)
+                .bind(utxo.1.block_height as i64)
+                .bind(utxo.1.timestamp.to_millis() as i64)
                 .bind(&utxo.0)
                 .execute(&mut *tx)
                 .await?;
+            self.drop_saved_transactions_spending(
+                &mut *tx,
+                utxo.0,
+                &format!(
+                    "input {} was spent in block {}",
+                    utxo.0, utxo.1.block_height
+                ),
+            )
+            .await?;
         }
 
         // remove from pending so it will not be updated again
-        for (id, _) in utxos {
+        for (id, block) in utxos {
             info!("checking utxo {} for pending", id);
             if let Some(txid) = self.updater.try_remove_pending_by_utxo_id(tx, id).await? {
                 info!("removing pending tx {}", txid);
@@ -369,6 +732,8 @@ impl WalletState {
                     .bind(&id)
                     .execute(&mut *tx)
                     .await?;
+                self.confirm_sent_payments(&mut *tx, &txid, block.block_height)
+                    .await?;
             };
         }
 
@@ -461,6 +826,26 @@ impl WalletState {
         Ok(utxos)
     }
 
+    /// Freeze or unfreeze unspent UTXOs, returns how many were changed.
+    pub async fn set_utxos_frozen(&self, ids: &[i64], frozen: bool) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        let mut changed = 0;
+        for id in ids {
+            changed += sqlx::query(
+                "UPDATE wallet_state_utxos SET frozen = ? WHERE id = ? AND spent_in_block IS NULL",
+            )
+            .bind(frozen)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+
+        tx.commit().await?;
+        Ok(changed)
+    }
+
     pub async fn add_expected_utxo(&self, utxo: Vec<ExpectedUtxoData>) -> Result<()> {
         let mut tx = self.pool.begin().await?;
 
@@ -596,11 +981,19 @@ impl WalletState {
             .execute(&mut *tx)
             .await?;
 
-        sqlx::query("UPDATE wallet_state_utxos SET spent_height = NULL, spent_txid = NULL, spent_in_block = NULL WHERE spent_height > ?")
+        sqlx::query("UPDATE wallet_state_utxos SET spent_height = NULL, spent_timestamp = NULL, spent_txid = NULL, spent_in_block = NULL WHERE spent_height > ?")
             .bind(&height_i64)
             .execute(&mut *tx)
             .await?;
 
+        sqlx::query(
+            "UPDATE wallet_state_sent_payments SET confirm_height = NULL WHERE confirm_height > ?",
+        )
+        .bind(&height_i64)
+        .execute(&mut *tx)
+        .await?;
+
+        self.delete_headers_above(&mut *tx, height).await?;
         self.set_tip(&mut *tx, (height, digest)).await?;
 
         Ok(())
@@ -617,11 +1010,12 @@ mod tests {
     use super::*;
     use crate::config::wallet::ScanConfig;
     use crate::config::wallet::WalletConfig;
+    use crate::config::wallet::WalletKey;
     #[tokio::test]
     async fn test_migrate_tables() {
         let config = WalletConfig {
             id: 0,
-            key: WalletEntropy::devnet_wallet(),
+            key: WalletKey::Entropy(WalletEntropy::devnet_wallet()),
             scan_config: ScanConfig {
                 num_keys: 1,
                 start_height: 0,
@@ -671,4 +1065,14 @@ mod tests {
 
         // assert!(wallet_state.get_known_raw_hash_keys().len() == 1);
     }
+
+    #[tokio::test]
+    async fn test_revert_migrations() {
+        let wallet_state = crate::wallet::test_wallet_state().await;
+        let migrator = wallet_state_migrator().unwrap();
+
+        let mut conn = wallet_state.pool.acquire().await.unwrap();
+        migrator.run(&mut *conn, &Plan::revert_all()).await.unwrap();
+        migrator.run(&mut *conn, &Plan::apply_all()).await.unwrap();
+    }
 }
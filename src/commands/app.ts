@@ -1,11 +1,65 @@
 import { invoke } from "@tauri-apps/api/core";
+import { Contact } from "@/database/types/contact";
+import { ExecutionHistory } from "@/database/types/localhistory";
 
 export async function run_rpc_server() {
     await invoke('run_rpc_server', {})
 }
 
-export async function persist_store_execute(sql: string): Promise<[{}]> {
-    return await invoke('persist_store_execute', { sql })
+export async function persist_store_get(namespace: string, key: string): Promise<string | null> {
+    return await invoke('persist_store_get', { namespace, key })
+}
+
+export async function persist_store_set(namespace: string, key: string, value: string) {
+    await invoke('persist_store_set', { namespace, key, value })
+}
+
+export async function persist_store_del(namespace: string, key: string) {
+    await invoke('persist_store_del', { namespace, key })
+}
+
+export async function persist_store_list(namespace: string): Promise<Record<string, string>> {
+    return await invoke('persist_store_list', { namespace })
+}
+
+export async function execution_history(addressId: number): Promise<ExecutionHistory[]> {
+    return await invoke('execution_history', { addressId })
+}
+
+export async function add_execution_history(history: ExecutionHistory) {
+    await invoke('add_execution_history', { history })
+}
+
+export async function delete_execution_history(txid: string) {
+    await invoke('delete_execution_history', { txid })
+}
+
+export async function list_contacts(): Promise<Contact[]> {
+    return await invoke('list_contacts', {})
+}
+
+export async function search_contacts(query: string): Promise<Contact[]> {
+    return await invoke('search_contacts', { query })
+}
+
+export async function add_contact(contact: Contact): Promise<number> {
+    return await invoke('add_contact', { contact })
+}
+
+export async function update_contact(contact: Contact) {
+    await invoke('update_contact', { contact })
+}
+
+export async function delete_contact(id: number) {
+    await invoke('delete_contact', { id })
+}
+
+export async function export_contacts(format: 'json' | 'csv'): Promise<string> {
+    return await invoke('export_contacts', { format })
+}
+
+export async function import_contacts(format: 'json' | 'csv', data: string): Promise<number> {
+    return await invoke('import_contacts', { format, data })
 }
 
 export async function snapshot_dir(): Promise<string> {
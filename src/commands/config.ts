@@ -30,3 +30,33 @@ export async function delete_cache(path: String): Promise<boolean> {
     return await invoke('delete_cache', { path })
 }
 
+
+export async function get_rest_urls(): Promise<string[]> {
+    return await invoke('get_remote_rests', {})
+}
+
+export async function set_rest_urls(rest_urls: string[]) {
+    return await invoke('set_remote_rests', { rests: rest_urls })
+}
+
+export interface RestServerStatus {
+    url: string,
+    healthy: boolean,
+    latency_ms: number,
+    consecutive_errors: number,
+    total_errors: number,
+    total_requests: number,
+    tip_height: number | null,
+}
+
+export async function get_rest_status(): Promise<RestServerStatus[]> {
+    return await invoke('remote_rest_status', {})
+}
+
+export async function set_paranoid_mode(enable: boolean) {
+    await invoke('set_paranoid_mode', { enabled: enable })
+}
+
+export async function get_paranoid_mode(): Promise<boolean> {
+    return await invoke('get_paranoid_mode', {})
+}
@@ -5,10 +5,14 @@ export interface WalletData {
     name: string,
     address: string,
     balance: string,
+    watch_only: boolean,
 }
 export async function addWallet(name: String, mnemonic: String, num_keys: number, start_height: number, is_new: boolean): Promise<number> {
     return await invoke('add_wallet', { name: name, mnemonic: mnemonic, numKeys: num_keys, startHeight: start_height, isNew: is_new })
 }
+export async function addWatchOnlyWallet(name: String, keys: String, num_keys: number, start_height: number): Promise<number> {
+    return await invoke('add_watch_only_wallet', { name: name, keys: keys, numKeys: num_keys, startHeight: start_height })
+}
 export async function setCurrentWallet(id: number) {
     await invoke('set_wallet_id', { id })
 }
@@ -31,6 +35,10 @@ export async function ExportWallet(password: string, id: number): Promise<string
     return await invoke('export_wallet', { password, id })
 }
 
+export async function ExportWatchOnlyKeys(password: string, id: number, num_keys: number): Promise<string> {
+    return await invoke('export_watch_only_keys', { password, id, numKeys: num_keys })
+}
+
 export async function resetToHeight(height:number): Promise<string[]> {
     return await invoke('reset_to_height', { height})
 }
@@ -8,6 +8,27 @@ export const START_SCAN_BLOCK = '/rpc/scan/0/0'
 export const WALLET_ACTIVITY_HISTORY = '/rpc/wallet/history'
 
 export const WALLET_AVAILABLE_UTXOS = '/rpc/wallet/available_utxos'
+// History grouped by transaction: ?page=&page_size=&from=&to=&min_amount=&max_amount=&sort=time|amount&order=desc|asc
+export const WALLET_TX_HISTORY = '/rpc/wallet/tx_history'
+// Accounting export, POST { format: 'csv' | 'json', from, to, prices }
+export const WALLET_LEDGER = '/rpc/wallet/ledger'
+// Confirmed balance as of ?height= or ?timestamp= (ms)
+export const WALLET_BALANCE_AT = '/rpc/wallet/balance_at'
+// Balance per bucket: ?from=&to= (ms)&interval=day|week
+export const WALLET_BALANCE_SERIES = '/rpc/wallet/balance_series'
+// Outputs paid to other wallets
+export const WALLET_SENT_PAYMENTS = '/rpc/wallet/sent_payments'
+// Coin control, POST a list of utxo ids; label: /rpc/wallet/utxos/${id}/label
+export const WALLET_FREEZE_UTXOS = '/rpc/wallet/utxos/freeze'
+export const WALLET_UNFREEZE_UTXOS = '/rpc/wallet/utxos/unfreeze'
+export const WALLET_UTXOS = '/rpc/wallet/utxos/'
+// Labels of transactions, addresses and UTXO hashes, GET lists and POST sets one
+export const WALLET_LABELS = '/rpc/wallet/labels'
+// BIP-329-like JSONL
+export const WALLET_LABELS_EXPORT = '/rpc/wallet/labels/export'
+export const WALLET_LABELS_IMPORT = '/rpc/wallet/labels/import'
+// Merge small UTXOs into one per batch
+export const WALLET_CONSOLIDATE_UTXOS = '/rpc/wallet/consolidate'
 
 // Transaction history in progress
 export const WALLET_PENDING_HISTORY = '/rpc/mempool/pendingtx'
@@ -15,6 +36,14 @@ export const WALLET_PENDING_HISTORY = '/rpc/mempool/pendingtx'
 export const WALLET_FORGET_TX = '/rpc/forget_tx/'
 // Send transaction
 export const WALLET_SEND_TRANSACTION = '/rpc/send'
+// Air-gapped signing: prepare on the watch wallet, sign offline, broadcast online
+export const WALLET_AIRGAP_PREPARE = '/rpc/airgap/prepare'
+export const WALLET_AIRGAP_SIGN = '/rpc/airgap/sign'
+export const WALLET_AIRGAP_BROADCAST = '/rpc/airgap/broadcast'
+// Proven transactions kept for a later broadcast, /rpc/saved_tx/${txid}[/broadcast]
+export const WALLET_SAVED_TX = '/rpc/saved_tx'
+// Decode a transaction blob or a saved/pending transaction by txid
+export const WALLET_DECODE_TX = '/rpc/tx/decode'
 
 // Wallet balance
 export const WALLET_BALANCE = '/rpc/wallet/balance'
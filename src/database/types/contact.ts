@@ -1,7 +1,10 @@
 export interface Contact {
+    id?: number;
     aliasName: string; 
     address: string;
     type: string;
     remark: string;
     createdTime: number;
+    // the configured network if empty
+    network?: string;
 }
\ No newline at end of file
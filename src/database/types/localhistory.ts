@@ -12,17 +12,3 @@ export interface ExecutionHistory {
     outputs: string[];
     batchOutput: SendInputItem[]
 }
-
-export interface ExecutionDbHistory {
-    txid: string;
-    timestamp: number;
-    height: number;
-    addressId: number;
-    address: string;
-    fee: string;
-    priorityFee: string;
-    status?: string; 
-    batchOutput: string
-}
-
-
@@ -69,6 +69,9 @@ export interface UtxoItem {
     confirmed_txid: string,
     locked: boolean,
     amount: string,
+    // never picked by automatic input selection
+    frozen: boolean,
+    label?: string,
 }
 
 export interface Activity {
@@ -179,7 +182,39 @@ export interface SyncState {
 export interface SyncingData {
     height: number,
     syncing: boolean,
-    updated_to_tip: boolean
+    updated_to_tip: boolean,
+    server_tip_height?: number | null,
+    blocks_per_sec?: number,
+    eta_secs?: number | null,
+    phase?: SyncPhase,
+    phase_timings?: PhaseTimings,
+    quarantined_blocks?: QuarantinedBlock[],
+    alert?: SyncAlert | null,
+}
+
+export interface QuarantinedBlock {
+    height: number,
+    digest: string,
+    reason: string,
+    timestamp: number,
+}
+
+export interface SyncAlert {
+    height: number,
+    local_digest: string,
+    remote_digest: string,
+    server: string,
+    timestamp: number,
+}
+
+export type SyncPhase = "idle" | "fetching" | "snapshot_read" | "scanning" | "fork_check" | "updating_pending"
+
+export interface PhaseTimings {
+    fetching_ms: number,
+    snapshot_read_ms: number,
+    scanning_ms: number,
+    fork_check_ms: number,
+    updating_pending_ms: number,
 }
 
 export interface ExecutionState {
@@ -2,6 +2,8 @@ export interface SendTransactionParam {
     outputs: Output[]
     fee: string,
     inputs: number[]
+    // spend all spendable UTXOs (or only `inputs`) to the single output, fee deducted
+    sweep?: boolean
 }
 
 export interface SendTransactionResponse {
@@ -10,8 +12,12 @@ export interface SendTransactionResponse {
 }
 
 export interface Output {
+    // empty when sending to contact_id
     address: string;
-    amount: string
+    amount: string;
+    contact_id?: number;
+    // unix ms, time-locks the output until then
+    release_date?: number;
 }
 
 export interface WalletBalanceData {
@@ -31,9 +37,57 @@ export interface HistoryData {
     height: number,
     index: number,
     release_date: any,
-    txid: string
+    txid: string,
+    label?: string,
+}
+
+
+export interface SentPayment {
+    id: number,
+    txid: string,
+    address: string,
+    amount: string,
+    // fee of the whole transaction
+    fee: string,
+    medium: 'on_chain' | 'off_chain',
+    // unix ms
+    timestamp: number,
+    confirm_height?: number,
+    // unix ms, the output is time-locked until then
+    release_date?: number,
 }
 
+export interface TransactionHistoryItem {
+    txid?: string,
+    height: number,
+    timestamp: any,
+    confirmations: number,
+    received: string,
+    spent: string,
+    // received minus spent
+    delta: string,
+    fee?: string,
+    counterparty_outputs?: number,
+    payments: SentPayment[],
+    received_utxos: number[],
+    spent_utxos: number[],
+    // unix ms, the latest time-lock of the received outputs
+    release_date?: number,
+    label?: string,
+}
+
+export interface TransactionHistoryPage {
+    total: number,
+    items: TransactionHistoryItem[],
+}
+
+export interface BalancePoint {
+    // start of the bucket
+    timestamp: any,
+    // last millisecond counted, the last bucket is cut off at the end of the series
+    end: any,
+    balance: string,
+}
 
 export interface SendInputItem {
     index: number,
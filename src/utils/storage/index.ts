@@ -1,13 +1,12 @@
-import { persist_store_execute } from "@/commands/app";
+import { add_contact, add_execution_history, delete_contact, delete_execution_history, execution_history, list_contacts } from "@/commands/app";
 import { Contact } from "@/database/types/contact";
-import { ExecutionDbHistory, ExecutionHistory } from "@/database/types/localhistory";
+import { ExecutionHistory } from "@/database/types/localhistory";
 import { notifications } from "@mantine/notifications";
 
 export async function addContactAddress({ contact }: { contact: Contact }): Promise<boolean> {
     let success = false
     try {
-        let sql = `INSERT INTO contacts (aliasName, address, type, remark, createdTime) VALUES ('${contact.aliasName}','${contact.address}','${contact.type}','${contact.remark}',${contact.createdTime})`
-        await persist_store_execute(sql)
+        await add_contact(contact)
         success = true
     } catch (error) {
         throw (error)
@@ -18,8 +17,10 @@ export async function addContactAddress({ contact }: { contact: Contact }): Prom
 export async function deleteContactAddress({ address }: { address: string }): Promise<boolean> {
     let success = false
     try {
-        let sql = `DELETE FROM contacts WHERE address = '${address}'`
-        await persist_store_execute(sql)
+        let contacts = await list_contacts()
+        for (const contact of contacts.filter((contact) => contact.address === address)) {
+            await delete_contact(contact.id!)
+        }
         success = true
     } catch (error) {
         throw (error)
@@ -28,13 +29,9 @@ export async function deleteContactAddress({ address }: { address: string }): Pr
 }
 
 export async function getContactList(): Promise<Contact[]> {
-    let sql = `SELECT * FROM contacts`
     let contactList = [] as Contact[]
     try {
-        let req = await persist_store_execute(sql)
-        if (req && req.length > 0) {
-            contactList = req as unknown as Contact[]
-        }
+        contactList = await list_contacts()
     } catch (error) {
         throw (error)
     }
@@ -43,16 +40,9 @@ export async function getContactList(): Promise<Contact[]> {
 
 
 export async function getExecutionHistory({ addressId }: { addressId: number }): Promise<ExecutionHistory[]> {
-    let sql = `SELECT * FROM execution_history 
-WHERE addressId = '${addressId}'
-ORDER BY timestamp DESC`
     let historys = [] as ExecutionHistory[]
     try {
-        let req = await persist_store_execute(sql)
-        let list = req as unknown as ExecutionDbHistory[]
-        list.map((item) => {
-            historys.push({ ...item, outputs: item.status && item.status != "" && item.status != "[]" && item.status != "undefined" ? JSON.parse(item.status) : [], batchOutput: item.batchOutput && item.batchOutput != "" && item.batchOutput != "[]" && item.batchOutput != "undefined" ? JSON.parse(item.batchOutput) : [] })
-        })
+        historys = await execution_history(addressId)
     } catch (error) {
         throw (error)
     }
@@ -60,30 +50,9 @@ ORDER BY timestamp DESC`
 }
 
 export async function addExecutionHistory({ localHistory }: { localHistory: ExecutionHistory }) {
-    let sql = `INSERT INTO execution_history (
-    txid, 
-    timestamp, 
-    height, 
-    addressId, 
-    address, 
-    fee, 
-    priorityFee, 
-    status,  
-    batchOutput
-) VALUES (
-    '${localHistory.txid}', 
-    ${localHistory.timestamp}, 
-    ${localHistory.height}, 
-    ${localHistory.addressId}, 
-    '${localHistory.address}', 
-    '${localHistory.fee}', 
-    '${localHistory.priorityFee}',   
-    '${localHistory.outputs.length > 0 ? JSON.stringify(localHistory.outputs) : ""}',  
-    '${localHistory.batchOutput.length > 0 ? JSON.stringify(localHistory.batchOutput) : ""}'
-);`
     let success = false
     try {
-        await persist_store_execute(sql)
+        await add_execution_history(localHistory)
         success = true
     } catch (error: any) {
         console.log(error);
@@ -98,10 +67,9 @@ export async function addExecutionHistory({ localHistory }: { localHistory: Exec
 }
 
 export async function deleteExecutionHistory({ txid }: { txid: string }): Promise<boolean> {
-    let sql = `DELETE FROM execution_history WHERE txid = '${txid}'`
     let success = false;
     try {
-        await persist_store_execute(sql)
+        await delete_execution_history(txid)
         success = true
     } catch (error: any) {
         notifications.show({
@@ -112,4 +80,4 @@ export async function deleteExecutionHistory({ txid }: { txid: string }): Promis
         })
     }
     return success
-}  
\ No newline at end of file
+}